@@ -0,0 +1,165 @@
+use std::{fs, path};
+
+use crate::bmc;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Reads the next line of the contents from the position
+fn next_line<'a>(contents: &'a [u8], position: &mut usize) -> Option<&'a str> {
+    let rest = contents.get(*position..)?;
+    let end = rest.iter().position(|&byte| byte == b'\n').unwrap_or(rest.len());
+    *position += end + 1;
+    std::str::from_utf8(&rest[..end]).ok()
+}
+
+fn parse_literals(line: &str) -> Option<Vec<bmc::AigLiteral>> {
+    line.split_whitespace().map(|token| token.parse::<bmc::AigLiteral>().ok()).collect()
+}
+
+// Reads a number of the binary format: seven bits per byte, least significant
+// first, with the high bit set on all bytes but the last
+fn next_number(contents: &[u8], position: &mut usize) -> Option<u32> {
+    let mut number = 0;
+    for shift in (0..32).step_by(7) {
+        let byte = *contents.get(*position)?;
+        *position += 1;
+        number |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Some(number)
+        }
+    }
+    None
+}
+
+// Reads a circuit in the AIGER format, in ASCII ("aag") or binary ("aig"),
+// with the bad state and invariant constraint sections of AIGER 1.9 but no
+// justice or fairness properties
+pub fn read_aiger<P>(path: P, verbosity: usize) -> Option<bmc::Aig>
+where P: AsRef<path::Path> {
+    let contents = fs::read(path).ok()?;
+    let mut position = 0;
+    let header: Vec<&str> = next_line(&contents, &mut position)?.split_whitespace().collect();
+    let binary = match header.first() {
+        Some(&"aag") => false,
+        Some(&"aig") => true,
+        _ => return None,
+    };
+    let counts = header[1..].iter().map(|token| token.parse::<usize>().ok()).collect::<Option<Vec<_>>>()?;
+    let (max_var, inputs, latches, outputs, ands) = match counts[..] {
+        [m, i, l, o, a, ..] => (m, i, l, o, a),
+        _ => return None,
+    };
+    let section = |index: usize| counts.get(index).copied().unwrap_or(0);
+    let (bad, constraints) = (section(5), section(6));
+    if section(7) != 0 || section(8) != 0 {
+        return None
+    }
+    let lines = |count: usize, position: &mut usize| -> Option<Vec<Vec<bmc::AigLiteral>>> {
+        (0..count).map(|_| parse_literals(next_line(&contents, position)?)).collect()
+    };
+    let mut aig = bmc::Aig {
+        max_var: max_var as u32,
+        inputs: Vec::new(),
+        latches: Vec::new(),
+        outputs: Vec::new(),
+        bad: Vec::new(),
+        constraints: Vec::new(),
+        ands: Vec::new(),
+    };
+    // Binary files leave out the literals being defined, which are the
+    // variables in order: inputs, then latches, then gates
+    if binary {
+        aig.inputs = (1..=inputs as u32).map(|var| 2 * var).collect();
+    } else {
+        for line in lines(inputs, &mut position)? {
+            aig.inputs.push(*line.first()?);
+        }
+    }
+    for (index, line) in lines(latches, &mut position)?.into_iter().enumerate() {
+        let line = if binary { [vec![2 * (inputs + index + 1) as u32], line].concat() } else { line };
+        let (literal, next) = (*line.first()?, *line.get(1)?);
+        let reset = match line.get(2) {
+            None | Some(0) => Some(false),
+            Some(1) => Some(true),
+            Some(&reset) if reset == literal => None,
+            Some(_) => return None,
+        };
+        aig.latches.push(bmc::Latch { literal, next, reset });
+    }
+    for (literals, count) in [(&mut aig.outputs, outputs), (&mut aig.bad, bad), (&mut aig.constraints, constraints)] {
+        for line in lines(count, &mut position)? {
+            literals.push(*line.first()?);
+        }
+    }
+    if binary {
+        for index in 0..ands {
+            let lhs = 2 * (inputs + latches + index + 1) as u32;
+            let rhs0 = lhs.checked_sub(next_number(&contents, &mut position)?)?;
+            let rhs1 = rhs0.checked_sub(next_number(&contents, &mut position)?)?;
+            aig.ands.push((lhs, rhs0, rhs1));
+        }
+    } else {
+        for line in lines(ands, &mut position)? {
+            match line[..] {
+                [lhs, rhs0, rhs1] => aig.ands.push((lhs, rhs0, rhs1)),
+                _ => return None,
+            }
+        }
+    }
+    // The symbol table and comments follow, and are ignored
+    let mut literals = aig.inputs.iter().copied()
+        .chain(aig.latches.iter().flat_map(|latch| [latch.literal, latch.next]))
+        .chain(aig.outputs.iter().copied())
+        .chain(aig.bad.iter().copied())
+        .chain(aig.constraints.iter().copied())
+        .chain(aig.ands.iter().flat_map(|&(lhs, rhs0, rhs1)| [lhs, rhs0, rhs1]));
+    if literals.any(|literal| aig.max_var < literal / 2) {
+        return None
+    }
+    if 1 < verbosity {
+        log::info!("Read circuit of {} inputs, {} latches and {} and gates", inputs, latches, ands);
+    }
+    Some(aig)
+}
+
+// The trace in the AIGER witness format: the status (1 for a counterexample),
+// the property violated, the initial state, and the inputs of each step
+pub fn write_witness<W>(writer: &mut W, trace: &bmc::Trace) -> std::io::Result<()>
+where W: std::io::Write {
+    let bits = |bits: &[bool]| bits.iter().map(|&bit| if bit { '1' } else { '0' }).collect::<String>();
+    writeln!(writer, "1")?;
+    writeln!(writer, "b{}", trace.property)?;
+    writeln!(writer, "{}", bits(&trace.latches))?;
+    for inputs in trace.inputs.iter() {
+        writeln!(writer, "{}", bits(inputs))?;
+    }
+    writeln!(writer, ".")
+}
+
+// Prints a witness of a counterexample, or else the status 2 (unknown), as
+// bounded model checking cannot show that no bad state is reachable
+pub fn read_aiger_bmc_and_print<P>(path: P, bound: usize, verbosity: usize) -> Result<Option<bmc::Trace>, String>
+where P: AsRef<path::Path> {
+    let aig = read_aiger(path, verbosity).ok_or("Error parsing AIGER file.")?;
+    let trace = bmc::check(&aig, bound, verbosity)?;
+    match &trace {
+        Some(trace) => {
+            if 0 < verbosity {
+                log::info!("Found a counterexample of {} steps", trace.inputs.len() - 1);
+            }
+            write_witness(&mut std::io::stdout(), trace).map_err(|err| err.to_string())?;
+        },
+        None => {
+            if 0 < verbosity {
+                log::info!("No counterexample within {} steps", bound);
+            }
+            println!("2");
+            for property in 0..aig.properties().len() {
+                println!("b{}", property);
+            }
+            println!(".");
+        },
+    }
+    Ok(trace)
+}
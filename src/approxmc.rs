@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use crate::ast;
+use crate::bignum::BigUint;
+use crate::dpll;
+use crate::util;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// A solver for a cnf along with random xor hashes over its sampling set, each
+// of which includes an activation atom so that it only applies when that atom
+// is assumed false. The cell of the first m hashes (the models for which they
+// all hold) can then be explored incrementally, for each m, by one solver.
+pub struct Hashed {
+    // Only used to find fresh atoms
+    cnf: ast::Cnf,
+    solver: dpll::Solver,
+    projection: Vec<ast::Atom>,
+    activations: Vec<ast::Atom>,
+    rng: util::Rng,
+    verbosity: usize,
+}
+
+impl Hashed {
+    pub fn new(cnf: &ast::Cnf, projection: &[ast::Atom], seed: u64, verbosity: usize) -> Self {
+        let mut solver = dpll::Solver::new(cnf, verbosity.saturating_sub(1));
+        solver.prioritize(projection);
+        Self {
+            cnf: cnf.clone(),
+            solver,
+            projection: projection.to_vec(),
+            activations: Vec::new(),
+            rng: util::Rng::new(seed),
+            verbosity,
+        }
+    }
+
+    // Adds hashes until there are at least the given number. Each contains every
+    // atom of the sampling set with probability 1/2, and has a random parity.
+    fn extend(&mut self, hashes: usize) {
+        while self.activations.len() < hashes {
+            let activation = self.cnf.fresh_atom();
+            let mut atoms: Vec<ast::Atom> = self.projection.iter()
+                .filter(|_| self.rng.next_bool())
+                .cloned()
+                .collect();
+            atoms.push(activation);
+            let xor = ast::Xor::new(atoms, self.rng.next_bool());
+            if 2 < self.verbosity {
+                log::info!("Adding hash {}", xor);
+            }
+            self.solver.add_xor(&xor);
+            self.activations.push(activation);
+        }
+    }
+
+    // The models in the cell of the first m hashes, projected onto the sampling
+    // set, up to the limit
+    pub fn cell(&mut self, m: usize, limit: usize) -> Vec<ast::Asgmt> {
+        self.extend(m);
+        // Blocking clauses only apply to this call, while this atom is assumed
+        // false
+        let blocking = self.cnf.fresh_atom();
+        let assumptions: Vec<ast::Literal> = self.activations[..m].iter()
+            .chain(std::iter::once(&blocking))
+            .map(|&atom| ast::Literal::new(false, atom))
+            .collect();
+        let mut models = Vec::new();
+        while models.len() < limit {
+            let Some(model) = self.solver.solve_with(&assumptions) else {
+                break
+            };
+            let mut projected = ast::Asgmt::new();
+            let mut clause = vec![ast::Literal::new(true, blocking)];
+            for &atom in self.projection.iter() {
+                let phase = model.get(&atom).unwrap();
+                projected.insert(atom, phase);
+                clause.push(ast::Literal::new(!phase, atom));
+            }
+            self.solver.add_clause(&ast::Clause::from(clause));
+            models.push(projected);
+        }
+        self.solver.add_clause(&ast::Clause::from(vec![ast::Literal::new(true, blocking)]));
+        if 1 < self.verbosity {
+            log::info!("Found {} models in the cell of {} hashes", models.len(), m);
+        }
+        models
+    }
+}
+
+// The cell size below which hashing stops, for the given tolerance
+pub fn threshold(epsilon: f64) -> usize {
+    (1.0 + 9.84 * (1.0 + epsilon / (1.0 + epsilon)) * (1.0 + 1.0 / epsilon).powi(2)).ceil() as usize
+}
+
+// The projection onto the given sampling set, or else onto the declared atoms
+pub fn sampling_set(cnf: &ast::Cnf, projection: Option<Vec<ast::Atom>>) -> Vec<ast::Atom> {
+    let mut projection = projection.unwrap_or_else(|| cnf.declared_atoms().chain(cnf.atoms()).collect());
+    projection.sort();
+    projection.dedup();
+    projection
+}
+
+// Estimates the number of models of the cnf projected onto the sampling set,
+// as in ApproxMC: with probability at least 1 - delta, the estimate is within a
+// factor of 1 + epsilon of the count. Each round finds the fewest hashes whose
+// cell is smaller than the threshold, and the estimate is the median of the
+// cell sizes scaled by the number of cells. Fails if no round finds a cell
+// below the threshold, even with a hash per atom.
+pub fn approx_count(cnf: &ast::Cnf, projection: Option<Vec<ast::Atom>>, epsilon: f64, delta: f64, seed: u64, verbosity: usize) -> Result<BigUint, String> {
+    let projection = sampling_set(cnf, projection);
+    let threshold = threshold(epsilon);
+    let mut rng = util::Rng::new(seed);
+
+    // Small counts are found exactly
+    let models = Hashed::new(cnf, &projection, rng.next_u64(), verbosity).cell(0, threshold).len();
+    if models < threshold {
+        if 0 < verbosity {
+            log::info!("Counted {} models exactly", models);
+        }
+        return Ok(BigUint::from(models as u64))
+    }
+
+    let rounds = (17.0 * (3.0 / delta).log2()).ceil() as usize;
+    let mut estimates = Vec::new();
+    // The number of hashes found by the previous round, which is likely close
+    // to that of the next
+    let mut guess = 1;
+    for round in 0..rounds {
+        let mut hashed = Hashed::new(cnf, &projection, rng.next_u64(), verbosity);
+        let mut sizes: HashMap<usize, usize> = HashMap::new();
+        let mut size = |hashed: &mut Hashed, m: usize| *sizes.entry(m).or_insert_with(|| hashed.cell(m, threshold).len());
+        // Cells shrink as hashes are added, so gallop from the guess and then
+        // bisect, keeping a cell of lo hashes at the threshold and one of hi
+        // hashes below it (the cell of no hashes is known to be large)
+        let mut step = 1;
+        let (mut lo, mut hi);
+        if size(&mut hashed, guess) < threshold {
+            hi = guess;
+            loop {
+                lo = hi.saturating_sub(step);
+                if lo == 0 || threshold <= size(&mut hashed, lo) {
+                    break
+                }
+                hi = lo;
+                step *= 2;
+            }
+        } else {
+            lo = guess;
+            loop {
+                hi = (lo + step).min(projection.len());
+                if size(&mut hashed, hi) < threshold || hi == projection.len() {
+                    break
+                }
+                lo = hi;
+                step *= 2;
+            }
+            if threshold <= size(&mut hashed, hi) {
+                if 0 < verbosity {
+                    log::info!("Round {} failed to find a small cell", round);
+                }
+                continue
+            }
+        }
+        while lo + 1 < hi {
+            let mid = (lo + hi) / 2;
+            if size(&mut hashed, mid) < threshold {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        let estimate = BigUint::from(size(&mut hashed, hi) as u64) << hi;
+        if 0 < verbosity {
+            log::info!("Round {} estimated {} models with {} hashes", round, estimate, hi);
+        }
+        estimates.push(estimate);
+        guess = hi;
+    }
+    estimates.sort();
+    estimates.get(estimates.len() / 2).cloned().ok_or_else(|| "Every round failed to find a small cell.".to_string())
+}
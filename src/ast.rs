@@ -2,13 +2,11 @@ use core::panic;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 
-use crate::util;
-
 
 ////////////////////////////////////////////////////////////////////////////////
 
 // Invariant: nonnegative
-#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct Atom (u32);
 
 impl Atom {
@@ -20,6 +18,11 @@ impl Atom {
             }
         )
     }
+
+    // Dense index, for solvers which keep per-atom state in vectors
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
 }
 
 impl fmt::Display for Atom {
@@ -44,7 +47,7 @@ impl Asgmt {
     }
 
     pub fn get(&self, atom: &Atom) -> Option<bool> {
-        self.0.get(atom).map(|&b| b)
+        self.0.get(atom).copied()
     }
 
     pub fn insert(&mut self, atom: Atom, phase: bool) -> Option<bool> {
@@ -60,6 +63,12 @@ impl Asgmt {
     }
 }
 
+impl Default for Asgmt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl fmt::Display for Asgmt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{{{}}}", itertools::join(self.0.iter().map(|(&atom, &pos)| Literal::new(pos, atom)), ", "))
@@ -70,7 +79,7 @@ impl fmt::Display for Asgmt {
 ////////////////////////////////////////////////////////////////////////////////
 
 // A positive or negative atom
-#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 // The MSB represents the sign, and the rest represent the atom
 pub struct Literal (u32);
 
@@ -94,6 +103,11 @@ impl Literal {
     pub fn inversion(&self) -> Self {
         Self::new(!self.phase(), self.atom())
     }
+
+    // Dense index, with the two literals of an atom adjacent
+    pub fn index(&self) -> usize {
+        2 * self.atom().index() + if self.phase() { 0 } else { 1 }
+    }
 }
 
 impl fmt::Display for Literal {
@@ -106,7 +120,6 @@ impl fmt::Display for Literal {
 ////////////////////////////////////////////////////////////////////////////////
 
 // A disjunction of literals
-// TODO: add normalize and is_normal functions
 #[derive(Hash, PartialEq, Eq, Clone, Debug)]
 pub struct Clause {
     literals: Vec<Literal>,
@@ -131,6 +144,10 @@ impl Clause {
         self.literals.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.literals.is_empty()
+    }
+
     pub fn literals(&self) -> impl Iterator<Item = &Literal> {
         self.literals.iter()
     }
@@ -142,35 +159,20 @@ impl Clause {
             .filter(|lit| asgmt.get(&lit.atom()).is_none())
     }
 
-    // - Removes duplicate literals.
-    // - Returns Err if a clause is trivially unsatisfiable (empty, or includes
-    //   two literals of the same atom with a different phase).
-    // - If trivial unit clause, returns the literal
-    // TODO: shrink here, remove public interface?
-    pub fn normalize(&mut self) -> Result<Option<Literal>, ()> {
-        let asgmt = util::fold_option(self.literals.iter(), Asgmt::new(), |mut asgmt, literal|
-            match asgmt.get(&literal.atom()) {
-                Some(phase) => {
-                    if phase == literal.phase() {
-                        Some(asgmt)
-                    } else {
-                        None
-                    }
-                },
-                None => {
-                    asgmt.insert(literal.atom(), literal.phase());
-                    Some(asgmt)
-                },
-            }
-        ).ok_or(())?;
-        self.literals = asgmt.0.into_iter().map(|(atom, phase)| Literal::new(phase, atom)).collect();
+    // Sorts the literals by atom and then phase, and removes duplicates.
+    // Returns false if the clause is a tautology (includes two literals of the
+    // same atom with a different phase), which is trivially satisfied.
+    pub fn normalize(&mut self) -> bool {
+        self.literals.sort_by_key(|lit| (lit.atom(), lit.phase()));
+        self.literals.dedup();
         self.literals.shrink_to_fit();
-        let len = self.literals.len();
-        match len {
-            0 => Err(()),
-            1 => Ok(Some(self.literals.get(0).unwrap().clone())),
-            _ => Ok(None)
-        }
+        !self.literals.windows(2).any(|pair| pair[0].atom() == pair[1].atom())
+    }
+
+    // Whether the literals are sorted by atom and then phase, and every atom
+    // occurs once
+    pub fn is_normal(&self) -> bool {
+        self.literals.windows(2).all(|pair| pair[0].atom() < pair[1].atom())
     }
 
     // Evaluates clause when fully assigned
@@ -203,6 +205,12 @@ impl std::convert::From<Vec<Literal>> for Clause {
     }
 }
 
+impl Default for Clause {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl fmt::Display for Clause {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "({})", itertools::join(self.literals.iter(), " "))
@@ -212,41 +220,179 @@ impl fmt::Display for Clause {
 
 ////////////////////////////////////////////////////////////////////////////////
 
-// A conjunction of clauses
+// An exclusive disjunction of atoms, which holds when the parity of the number
+// of true atoms is odd (parity true) or even (parity false)
+// Invariant: atoms are sorted, without duplicates
+#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+pub struct Xor {
+    atoms: Vec<Atom>,
+    parity: bool,
+}
+
+impl Xor {
+    // Atoms occurring twice cancel out
+    pub fn new(atoms: impl IntoIterator<Item = Atom>, parity: bool) -> Self {
+        let mut atoms: Vec<Atom> = atoms.into_iter().collect();
+        atoms.sort();
+        let mut deduped: Vec<Atom> = Vec::with_capacity(atoms.len());
+        for atom in atoms {
+            if deduped.last() == Some(&atom) {
+                deduped.pop();
+            } else {
+                deduped.push(atom);
+            }
+        }
+        Self { atoms: deduped, parity }
+    }
+
+    // The xor of the literals is true. Negative literals flip the parity.
+    pub fn from_literals(literals: impl IntoIterator<Item = Literal>) -> Self {
+        let mut parity = true;
+        let atoms: Vec<Atom> = literals.into_iter()
+            .map(|literal| {
+                parity ^= !literal.phase();
+                literal.atom()
+            })
+            .collect();
+        Self::new(atoms, parity)
+    }
+
+    pub fn atoms(&self) -> impl Iterator<Item = &Atom> {
+        self.atoms.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.atoms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.atoms.is_empty()
+    }
+
+    pub fn parity(&self) -> bool {
+        self.parity
+    }
+
+    // Evaluates xor when fully assigned
+    pub fn eval(&self, asgmt: &Asgmt) -> Option<bool> {
+        let mut parity = false;
+        for atom in self.atoms.iter() {
+            parity ^= asgmt.get(atom)?;
+        }
+        Some(parity == self.parity)
+    }
+}
+
+impl fmt::Display for Xor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({} = {})", itertools::join(self.atoms.iter(), " ^ "), if self.parity { 1 } else { 0 })
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// A conjunction of clauses, along with any xors kept natively rather than by
+// their clausal encoding
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Cnf {
     clauses: Vec<Clause>,
+    xors: Vec<Xor>,
     atoms: HashSet<Atom>,
+    // Invariant: greater than every atom which occurs in or was declared for
+    // the cnf, and than 0, which cannot be written in DIMACS
+    next_atom: u32,
 }
 
 impl Cnf {
     pub fn new() -> Self {
         Self {
             clauses: Vec::new(),
-            atoms: HashSet::new()
+            xors: Vec::new(),
+            atoms: HashSet::new(),
+            next_atom: 1,
         }
     }
 
     pub fn shrink_to_fit(&mut self) {
         self.clauses.shrink_to_fit();
+        self.xors.shrink_to_fit();
         self.atoms.shrink_to_fit()
     }
 
     pub fn add(&mut self, clause: Clause) {
+        for literal in clause.literals() {
+            self.declare_atom(literal.atom());
+        }
         self.atoms.extend(clause.literals().map(|literal| literal.atom()));
         self.clauses.push(clause)
     }
 
+    pub fn add_xor(&mut self, xor: Xor) {
+        for &atom in xor.atoms() {
+            self.declare_atom(atom);
+        }
+        self.atoms.extend(xor.atoms());
+        self.xors.push(xor)
+    }
+
+    // Removes and returns the xors, whose atoms are still considered atoms of
+    // the cnf
+    pub fn take_xors(&mut self) -> Vec<Xor> {
+        std::mem::take(&mut self.xors)
+    }
+
+    // Keeps only the clauses satisfying the predicate. The atoms of removed
+    // clauses are still considered atoms of the cnf.
+    pub fn retain_clauses<F>(&mut self, f: F)
+    where F: FnMut(&Clause) -> bool {
+        self.clauses.retain(f)
+    }
+
+    // Ensures that atoms returned by fresh_atom will be distinct from the given
+    // atom, even if it does not (yet) occur in any clause.
+    pub fn declare_atom(&mut self, atom: Atom) {
+        self.next_atom = self.next_atom.max(atom.0 + 1)
+    }
+
+    // Returns an atom which does not occur in the cnf, and which will not be
+    // returned again.
+    pub fn fresh_atom(&mut self) -> Atom {
+        let atom = self.next_atom();
+        self.next_atom += 1;
+        atom
+    }
+
+    // The atom which fresh_atom would return next, without reserving it
+    pub fn next_atom(&self) -> Atom {
+        Atom::new(self.next_atom)
+    }
+
     pub fn len(&self) -> usize {
         self.clauses.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+
     pub fn clauses(&self) -> std::slice::Iter<'_, Clause> {
         self.clauses.iter()
     }
 
+    pub fn xors(&self) -> std::slice::Iter<'_, Xor> {
+        self.xors.iter()
+    }
+
     pub fn atoms<'a>(&'a self) -> impl Iterator<Item = Atom> + 'a {
-        self.atoms.iter().map(|&atom| atom)
+        self.atoms.iter().copied()
+    }
+
+    // The atoms which occur in or were declared for the cnf, taking atoms to be
+    // numbered from 1 as in DIMACS
+    pub fn declared_atoms(&self) -> impl Iterator<Item = Atom> + '_ {
+        self.atoms.get(&Atom(0)).copied().into_iter()
+            .chain((1..self.next_atom).map(Atom))
     }
 
     pub fn free_bound_atoms_pair(&self, asgmt: &Asgmt) -> (HashSet<Atom>, HashSet<Atom>) {
@@ -259,36 +405,29 @@ impl Cnf {
         self.free_bound_atoms_pair(asgmt).0
     }
 
-    // - Removes duplicate literals in clauses.
-    // - Returns None if a clause is trivially unsatisfiable (empty, or includes
-    //   two literals of the same atom with a different phase).
+    // - Normalizes clauses, and removes tautologies.
+    // - Returns None if a clause is trivially unsatisfiable (empty, or a unit
+    //   clause conflicting with another).
     // - Removes trivial unit clauses and returns their value in an initial assignment
     pub fn normalize(&mut self) -> Option<Asgmt> {
-        let (asgmt, clauses) = util::fold_option(std::mem::take(&mut self.clauses).into_iter(), (Asgmt::new(), Vec::new()),
-            |(mut asgmt, mut vec), mut clause| {
-                match clause.normalize() {
-                    Ok(Some(literal)) => {
-                        // asgmt.insert(literal.atom(), literal.phase());
-                        match asgmt.get(&literal.atom()) {
-                            Some(phase) => {
-                                if phase != literal.phase() {
-                                    return None
-                                }
-                            },
-                            None => {
-                                asgmt.insert(literal.atom(), literal.phase());
-                            },
-                        };
-                        Some((asgmt, vec))
-                    },
-                    Ok(None) => {
-                        vec.push(clause);
-                        Some((asgmt, vec))
+        let mut asgmt = Asgmt::new();
+        let mut clauses = Vec::new();
+        for mut clause in std::mem::take(&mut self.clauses) {
+            if !clause.normalize() {
+                continue
+            }
+            match clause.literals[..] {
+                [] => return None,
+                [literal] => match asgmt.get(&literal.atom()) {
+                    Some(phase) if phase != literal.phase() => return None,
+                    Some(_) => (),
+                    None => {
+                        asgmt.insert(literal.atom(), literal.phase());
                     },
-                    Err(()) => None,
-                }
+                },
+                _ => clauses.push(clause),
             }
-        )?;
+        }
         self.clauses = clauses;
         Some(asgmt)
     }
@@ -297,8 +436,10 @@ impl Cnf {
     // then true if all true, false if exists false, undefined otherwise).
     pub fn eval(&self, asgmt: &Asgmt) -> Option<bool> {
         let mut under_assigned = false;
-        for clause in self.clauses.iter() {
-            if let Some(val) = clause.eval(asgmt) {
+        let clause_vals = self.clauses.iter().map(|clause| clause.eval(asgmt));
+        let xor_vals = self.xors.iter().map(|xor| xor.eval(asgmt));
+        for val in clause_vals.chain(xor_vals) {
+            if let Some(val) = val {
                 if !val {
                     return Some(false)
                 }
@@ -326,10 +467,11 @@ impl IntoIterator for Cnf {
 impl std::convert::From<Vec<Clause>> for Cnf {
     fn from(mut clauses: Vec<Clause>) -> Self {
         clauses.shrink_to_fit();
-        let atoms = clauses.iter()
+        let atoms: HashSet<Atom> = clauses.iter()
             .flat_map(|clause| clause.literals.iter().map(|literal| literal.atom()))
             .collect();
-        Self {clauses, atoms}
+        let next_atom = atoms.iter().map(|atom| atom.0 + 1).max().unwrap_or(1);
+        Self {clauses, xors: Vec::new(), atoms, next_atom}
     }
 }
 
@@ -340,8 +482,14 @@ impl std::convert::From<Vec<Vec<Literal>>> for Cnf {
     }
 }
 
+impl Default for Cnf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl fmt::Display for Cnf {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "({})", itertools::join(self.clauses.iter(), ""))
+        write!(f, "({}{})", itertools::join(self.clauses.iter(), ""), itertools::join(self.xors.iter(), ""))
     }
 }
\ No newline at end of file
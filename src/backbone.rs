@@ -0,0 +1,75 @@
+use itertools::Itertools;
+
+use crate::ast;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// The literals of the model which can be flipped without falsifying a clause,
+// so that the inverted literals hold in another model. Atoms of xors are
+// never rotatable.
+fn rotatable(cnf: &ast::Cnf, model: &ast::Asgmt) -> Vec<ast::Literal> {
+    let mut fixed = std::collections::HashSet::new();
+    for clause in cnf.clauses() {
+        let mut true_literals = clause.literals().filter(|lit| model.get(&lit.atom()) == Some(lit.phase())).unique();
+        if let (Some(&literal), None) = (true_literals.next(), true_literals.next()) {
+            fixed.insert(literal);
+        }
+    }
+    for xor in cnf.xors() {
+        for &atom in xor.atoms() {
+            fixed.insert(ast::Literal::new(model.get(&atom).unwrap_or(false), atom));
+        }
+    }
+    cnf.atoms()
+        .filter_map(|atom| model.get(&atom).map(|phase| ast::Literal::new(phase, atom)))
+        .filter(|literal| !fixed.contains(literal))
+        .collect()
+}
+
+// The backbone of the cnf: the literals true in every model, or None if it is
+// UNSAT. The candidates are the literals of a first model, and each is checked
+// by solving with its inversion assumed: if that is UNSAT, the literal is in
+// the backbone and is added as a unit, and otherwise the new model rules out
+// every candidate it falsifies, and optionally those it can rotate.
+pub fn backbone(cnf: &ast::Cnf, rotate: bool, verbosity: usize) -> Option<ast::Asgmt> {
+    let mut solver = dpll::Solver::new(cnf, verbosity.saturating_sub(1));
+    let model = solver.solve()?;
+    let mut candidates: Vec<ast::Literal> = cnf.atoms()
+        .map(|atom| ast::Literal::new(model.get(&atom).unwrap_or(false), atom))
+        .collect();
+    candidates.sort();
+    let (mut checks, mut filtered, mut rotated) = (1, 0, 0);
+    let mut filter = |candidates: &mut Vec<ast::Literal>, model: &ast::Asgmt| {
+        let len = candidates.len();
+        candidates.retain(|lit| model.get(&lit.atom()) == Some(lit.phase()));
+        filtered += len - candidates.len();
+        if rotate {
+            let len = candidates.len();
+            let rotatable = rotatable(cnf, model);
+            candidates.retain(|lit| !rotatable.contains(lit));
+            rotated += len - candidates.len();
+        }
+    };
+    filter(&mut candidates, &model);
+    let mut backbone = ast::Asgmt::new();
+    while let Some(literal) = candidates.pop() {
+        checks += 1;
+        match solver.solve_with(&[literal.inversion()]) {
+            None => {
+                if 1 < verbosity {
+                    log::info!("Backbone literal {}", literal);
+                }
+                backbone.insert(literal.atom(), literal.phase());
+                solver.add_clause(&ast::Clause::from(vec![literal]));
+            },
+            Some(model) => filter(&mut candidates, &model),
+        }
+    }
+    if 0 < verbosity {
+        log::info!("Backbone of {} literals after {} checks, ruling out {} candidates by models and {} by rotation",
+            backbone.atoms().len(), checks, filtered, rotated);
+    }
+    Some(backbone)
+}
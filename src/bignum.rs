@@ -0,0 +1,169 @@
+use std::{cmp, fmt, ops};
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// An arbitrary precision natural number
+// Invariant: little-endian base 2^32 digits, with no leading (last) zeros
+#[derive(Hash, PartialEq, Eq, Clone, Debug, Default)]
+pub struct BigUint (Vec<u32>);
+
+impl BigUint {
+    pub fn zero() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn one() -> Self {
+        Self::from(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // 2 to the given power
+    pub fn pow2(exponent: usize) -> Self {
+        Self::one() << exponent
+    }
+
+    fn trim(mut self) -> Self {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+        self
+    }
+
+    // Divides in place by a small divisor, returning the remainder
+    fn div_rem_small(&mut self, divisor: u32) -> u32 {
+        let mut remainder: u64 = 0;
+        for digit in self.0.iter_mut().rev() {
+            let current = (remainder << 32) | *digit as u64;
+            *digit = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        *self = std::mem::take(self).trim();
+        remainder as u32
+    }
+
+    // The nearest float, which is infinite for very large numbers
+    pub fn to_f64(&self) -> f64 {
+        self.0.iter().rev().fold(0.0, |acc, &digit| acc * 4294967296.0 + digit as f64)
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(n: u64) -> Self {
+        Self(vec![n as u32, (n >> 32) as u32]).trim()
+    }
+}
+
+impl ops::Add<&BigUint> for &BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: &BigUint) -> BigUint {
+        let len = self.0.len().max(other.0.len());
+        let mut digits = Vec::with_capacity(len + 1);
+        let mut carry = 0;
+        for i in 0..len {
+            let sum = *self.0.get(i).unwrap_or(&0) as u64 + *other.0.get(i).unwrap_or(&0) as u64 + carry;
+            digits.push(sum as u32);
+            carry = sum >> 32;
+        }
+        digits.push(carry as u32);
+        BigUint(digits).trim()
+    }
+}
+
+impl ops::Add for BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: BigUint) -> BigUint {
+        &self + &other
+    }
+}
+
+impl ops::AddAssign<&BigUint> for BigUint {
+    fn add_assign(&mut self, other: &BigUint) {
+        *self = &*self + other
+    }
+}
+
+impl ops::Mul<&BigUint> for &BigUint {
+    type Output = BigUint;
+
+    fn mul(self, other: &BigUint) -> BigUint {
+        let mut digits = vec![0_u32; self.0.len() + other.0.len()];
+        for (i, &a) in self.0.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &b) in other.0.iter().enumerate() {
+                let product = a as u64 * b as u64 + digits[i + j] as u64 + carry;
+                digits[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            digits[i + other.0.len()] = carry as u32;
+        }
+        BigUint(digits).trim()
+    }
+}
+
+impl ops::Mul for BigUint {
+    type Output = BigUint;
+
+    fn mul(self, other: BigUint) -> BigUint {
+        &self * &other
+    }
+}
+
+impl ops::Shl<usize> for BigUint {
+    type Output = BigUint;
+
+    fn shl(self, shift: usize) -> BigUint {
+        if self.is_zero() {
+            return self
+        }
+        let mut digits = vec![0_u32; shift / 32];
+        let bits = shift % 32;
+        let mut carry = 0;
+        for &digit in self.0.iter() {
+            let shifted = (digit as u64) << bits;
+            digits.push(shifted as u32 | carry);
+            carry = (shifted >> 32) as u32;
+        }
+        digits.push(carry);
+        BigUint(digits).trim()
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.0.len().cmp(&other.0.len())
+            .then_with(|| self.0.iter().rev().cmp(other.0.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Base 10^9 digits, least significant first
+        let mut chunks = Vec::new();
+        let mut n = self.clone();
+        while !n.is_zero() {
+            chunks.push(n.div_rem_small(1_000_000_000));
+        }
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((first, rest)) => {
+                write!(f, "{}", first)?;
+                for chunk in rest.iter().rev() {
+                    write!(f, "{:09}", chunk)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::ast;
+use crate::bv;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// A literal of an and-inverter graph, as in AIGER: twice the variable, plus
+// one if negated. Variable 0 is the constant false.
+pub type AigLiteral = u32;
+
+#[derive(Clone, Debug)]
+pub struct Latch {
+    pub literal: AigLiteral,
+    // The literal of its value at the next step
+    pub next: AigLiteral,
+    // Its initial value, or None if it is uninitialized
+    pub reset: Option<bool>,
+}
+
+// A sequential circuit of and gates and inverters, whose state is held by
+// latches. The bad states are those where a bad literal holds, or if there
+// are none, an output, and only the steps where every constraint holds count.
+#[derive(Clone, Debug)]
+pub struct Aig {
+    pub max_var: u32,
+    pub inputs: Vec<AigLiteral>,
+    pub latches: Vec<Latch>,
+    pub outputs: Vec<AigLiteral>,
+    pub bad: Vec<AigLiteral>,
+    pub constraints: Vec<AigLiteral>,
+    // Each gate's output and its two inputs
+    pub ands: Vec<(AigLiteral, AigLiteral, AigLiteral)>,
+}
+
+// A path to a bad state: the initial values of the latches, and the values of
+// the inputs at each step up to the bad one
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Trace {
+    // The index of the bad literal (or output) which holds at the last step
+    pub property: usize,
+    pub latches: Vec<bool>,
+    pub inputs: Vec<Vec<bool>>,
+}
+
+impl Aig {
+    pub fn properties(&self) -> &[AigLiteral] {
+        if self.bad.is_empty() { &self.outputs } else { &self.bad }
+    }
+
+    // The gates ordered so that each comes after those of its inputs, which
+    // AIGER only guarantees of binary files. Returns None for a cycle.
+    fn sorted_ands(&self) -> Option<Vec<(AigLiteral, AigLiteral, AigLiteral)>> {
+        let mut gate_of = vec![None; self.max_var as usize + 1];
+        for (index, &(lhs, _, _)) in self.ands.iter().enumerate() {
+            *gate_of.get_mut(lhs as usize / 2)? = Some(index);
+        }
+        // Unvisited, visiting, or visited
+        let mut state = vec![0; self.ands.len()];
+        let mut sorted = Vec::with_capacity(self.ands.len());
+        for root in 0..self.ands.len() {
+            let mut stack = vec![root];
+            while let Some(&index) = stack.last() {
+                if state[index] == 2 {
+                    stack.pop();
+                    continue
+                }
+                state[index] = 1;
+                let (_, rhs0, rhs1) = self.ands[index];
+                let pending = [rhs0, rhs1].into_iter()
+                    .filter_map(|rhs| *gate_of.get(rhs as usize / 2)?)
+                    .find(|&input| state[input] != 2);
+                match pending {
+                    Some(input) if state[input] == 1 => return None,
+                    Some(input) => stack.push(input),
+                    None => {
+                        state[index] = 2;
+                        sorted.push(self.ands[index]);
+                        stack.pop();
+                    },
+                }
+            }
+        }
+        Some(sorted)
+    }
+
+    // Whether the trace reaches a state where its property holds, by
+    // simulation
+    pub fn simulate(&self, trace: &Trace) -> Option<bool> {
+        let ands = self.sorted_ands()?;
+        let mut values = vec![false; self.max_var as usize + 1];
+        let value = |values: &[bool], literal: AigLiteral| values[literal as usize / 2] ^ (literal % 2 == 1);
+        for (latch, &initial) in self.latches.iter().zip(trace.latches.iter()) {
+            values[latch.literal as usize / 2] = latch.reset.unwrap_or(initial);
+        }
+        for (step, inputs) in trace.inputs.iter().enumerate() {
+            if 0 < step {
+                let next: Vec<bool> = self.latches.iter().map(|latch| value(&values, latch.next)).collect();
+                for (latch, next) in self.latches.iter().zip(next) {
+                    values[latch.literal as usize / 2] = next;
+                }
+            }
+            for (&input, &phase) in self.inputs.iter().zip(inputs.iter()) {
+                values[input as usize / 2] = phase;
+            }
+            for &(lhs, rhs0, rhs1) in ands.iter() {
+                values[lhs as usize / 2] = value(&values, rhs0) && value(&values, rhs1);
+            }
+            if !self.constraints.iter().all(|&constraint| value(&values, constraint)) {
+                return Some(false)
+            }
+        }
+        Some(!trace.inputs.is_empty() && value(&values, *self.properties().get(trace.property)?))
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// The circuit unrolled into a cnf, one copy (frame) of its gates per step,
+// with the latches of each frame taken from the next state literals of the
+// one before
+struct Unrolling<'a> {
+    aig: &'a Aig,
+    ands: Vec<(AigLiteral, AigLiteral, AigLiteral)>,
+    blaster: bv::Blaster,
+    // The literal of each variable, by frame
+    frames: Vec<Vec<ast::Literal>>,
+}
+
+impl Unrolling<'_> {
+    fn literal(&self, frame: usize, literal: AigLiteral) -> ast::Literal {
+        let var = self.frames[frame][literal as usize / 2];
+        if literal % 2 == 1 { var.inversion() } else { var }
+    }
+
+    // Encodes the gates of the next frame, by Tseitin's encoding
+    fn unroll(&mut self) {
+        let mut frame = vec![self.blaster.constant(false); self.aig.max_var as usize + 1];
+        for &input in self.aig.inputs.iter() {
+            frame[input as usize / 2] = self.blaster.fresh();
+        }
+        for latch in self.aig.latches.iter() {
+            frame[latch.literal as usize / 2] = match (self.frames.last(), latch.reset) {
+                (Some(_), _) => self.literal(self.frames.len() - 1, latch.next),
+                (None, Some(reset)) => self.blaster.constant(reset),
+                (None, None) => self.blaster.fresh(),
+            };
+        }
+        self.frames.push(frame);
+        let last = self.frames.len() - 1;
+        for &(lhs, rhs0, rhs1) in self.ands.iter() {
+            let (a, b) = (self.literal(last, rhs0), self.literal(last, rhs1));
+            self.frames[last][lhs as usize / 2] = self.blaster.and(a, b);
+        }
+    }
+}
+
+// Bounded model checking: unrolls the circuit a step at a time, and looks for
+// a path to a bad state at each depth, up to the bound. The frames are added
+// to a single solver, which is asked for a bad state at the last frame under
+// an assumption, so that what it learns carries over to the deeper searches.
+pub fn check(aig: &Aig, bound: usize, verbosity: usize) -> Result<Option<Trace>, String> {
+    let ands = aig.sorted_ands().ok_or("The and gates form a cycle.")?;
+    let mut unrolling = Unrolling {
+        aig,
+        ands,
+        blaster: bv::Blaster::new(ast::Cnf::new()),
+        frames: Vec::new(),
+    };
+    let mut solver = dpll::Solver::new(&ast::Cnf::new(), verbosity.saturating_sub(1));
+    let mut added = 0;
+    for depth in 0..=bound {
+        unrolling.unroll();
+        for &constraint in aig.constraints.iter() {
+            let literal = unrolling.literal(depth, constraint);
+            unrolling.blaster.cnf.add(ast::Clause::from(vec![literal]));
+        }
+        let bad: Vec<ast::Literal> = aig.properties().iter()
+            .map(|&property| unrolling.literal(depth, property))
+            .collect();
+        // An activation literal implying that some property is bad
+        let activation = unrolling.blaster.fresh();
+        unrolling.blaster.cnf.add(ast::Clause::from([activation.inversion()].into_iter()
+            .chain(bad.iter().copied())
+            .collect::<Vec<_>>()));
+        for clause in unrolling.blaster.cnf.clauses().skip(added) {
+            solver.add_clause(clause);
+        }
+        added = unrolling.blaster.cnf.len();
+        if 0 < verbosity {
+            log::info!("Checking depth {} of {} clauses", depth, added);
+        }
+        if let Some(asgmt) = solver.solve_with(&[activation]) {
+            let value = |literal: ast::Literal| asgmt.get(&literal.atom()) == Some(literal.phase());
+            return Ok(Some(Trace {
+                property: bad.iter().position(|&literal| value(literal)).unwrap_or(0),
+                latches: aig.latches.iter().map(|latch| value(unrolling.literal(0, latch.literal))).collect(),
+                inputs: (0..=depth)
+                    .map(|frame| aig.inputs.iter().map(|&input| value(unrolling.literal(frame, input))).collect())
+                    .collect(),
+            }))
+        }
+        // No bad state is reachable in exactly this many steps
+        for literal in bad {
+            solver.add_clause(&ast::Clause::from(vec![literal.inversion()]));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,311 @@
+use std::fmt;
+
+use crate::ast;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// A fixed-width bit-vector, as the literals of its bits, least significant
+// first
+pub type BitVec = Vec<ast::Literal>;
+
+// The value of a bit-vector in a model, least significant bit first
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Value(pub Vec<bool>);
+
+impl Value {
+    // The value as an unsigned integer, if it fits
+    pub fn to_u64(&self) -> Option<u64> {
+        if 64 < self.0.iter().rposition(|&bit| bit).map_or(0, |position| position + 1) {
+            return None
+        }
+        Some(self.0.iter().rev().fold(0, |value, &bit| (value << 1) | bit as u64))
+    }
+}
+
+// In the binary notation of SMT-LIB, e.g. #b0101
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#b")?;
+        for &bit in self.0.iter().rev() {
+            write!(f, "{}", bit as u8)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn decode(bits: &[ast::Literal], asgmt: &ast::Asgmt) -> Value {
+    Value(bits.iter().map(|literal| asgmt.get(&literal.atom()) == Some(literal.phase())).collect())
+}
+
+// The bits of the most significant first, then those of the least
+pub fn concat(high: &[ast::Literal], low: &[ast::Literal]) -> BitVec {
+    low.iter().chain(high.iter()).copied().collect()
+}
+
+// The bits from high down to low, inclusive
+pub fn extract(bits: &[ast::Literal], high: usize, low: usize) -> BitVec {
+    bits[low..=high].to_vec()
+}
+
+pub fn not(bits: &[ast::Literal]) -> BitVec {
+    bits.iter().map(|literal| literal.inversion()).collect()
+}
+
+// Translates bit-vector operations into gates whose outputs are literals
+// defined by clauses of the cnf (Tseitin's encoding). Gates with constant or
+// equal inputs are simplified away rather than encoded, so that operations on
+// constants, such as multiplication by one, cost little.
+#[derive(Clone, Debug)]
+pub struct Blaster {
+    pub cnf: ast::Cnf,
+    truth: ast::Literal,
+}
+
+impl Blaster {
+    pub fn new(mut cnf: ast::Cnf) -> Self {
+        let truth = ast::Literal::new(true, cnf.fresh_atom());
+        cnf.add(ast::Clause::from(vec![truth]));
+        Self { cnf, truth }
+    }
+
+    pub fn constant(&self, value: bool) -> ast::Literal {
+        if value { self.truth } else { self.truth.inversion() }
+    }
+
+    fn value(&self, literal: ast::Literal) -> Option<bool> {
+        if literal.atom() == self.truth.atom() {
+            Some(literal == self.truth)
+        } else {
+            None
+        }
+    }
+
+    pub fn fresh(&mut self) -> ast::Literal {
+        ast::Literal::new(true, self.cnf.fresh_atom())
+    }
+
+    pub fn fresh_bits(&mut self, width: usize) -> BitVec {
+        (0..width).map(|_| self.fresh()).collect()
+    }
+
+    // The constant of the given width whose bits are the low ones of the value
+    pub fn constant_bits(&self, width: usize, value: u64) -> BitVec {
+        (0..width).map(|i| self.constant(i < 64 && (value >> i) & 1 == 1)).collect()
+    }
+
+    fn define(&mut self, clauses: &[&[ast::Literal]]) {
+        for clause in clauses {
+            self.cnf.add(ast::Clause::from(clause.to_vec()));
+        }
+    }
+
+    pub fn and(&mut self, a: ast::Literal, b: ast::Literal) -> ast::Literal {
+        match (self.value(a), self.value(b)) {
+            (Some(false), _) | (_, Some(false)) => return self.constant(false),
+            (Some(true), _) => return b,
+            (_, Some(true)) => return a,
+            _ if a == b => return a,
+            _ if a == b.inversion() => return self.constant(false),
+            _ => (),
+        }
+        let out = self.fresh();
+        self.define(&[&[out.inversion(), a], &[out.inversion(), b], &[out, a.inversion(), b.inversion()]]);
+        out
+    }
+
+    pub fn or(&mut self, a: ast::Literal, b: ast::Literal) -> ast::Literal {
+        self.and(a.inversion(), b.inversion()).inversion()
+    }
+
+    pub fn xor(&mut self, a: ast::Literal, b: ast::Literal) -> ast::Literal {
+        match (self.value(a), self.value(b)) {
+            (Some(value), _) => return if value { b.inversion() } else { b },
+            (_, Some(value)) => return if value { a.inversion() } else { a },
+            _ if a == b => return self.constant(false),
+            _ if a == b.inversion() => return self.constant(true),
+            _ => (),
+        }
+        let out = self.fresh();
+        self.define(&[
+            &[out.inversion(), a, b],
+            &[out.inversion(), a.inversion(), b.inversion()],
+            &[out, a.inversion(), b],
+            &[out, a, b.inversion()],
+        ]);
+        out
+    }
+
+    pub fn iff(&mut self, a: ast::Literal, b: ast::Literal) -> ast::Literal {
+        self.xor(a, b).inversion()
+    }
+
+    // The first input if the condition holds, else the second
+    pub fn mux(&mut self, condition: ast::Literal, then: ast::Literal, otherwise: ast::Literal) -> ast::Literal {
+        match self.value(condition) {
+            Some(value) => return if value { then } else { otherwise },
+            None if then == otherwise => return then,
+            None => (),
+        }
+        let out = self.fresh();
+        self.define(&[
+            &[condition.inversion(), out.inversion(), then],
+            &[condition.inversion(), out, then.inversion()],
+            &[condition, out.inversion(), otherwise],
+            &[condition, out, otherwise.inversion()],
+        ]);
+        out
+    }
+
+    // Whether at least two of the three hold: the carry of a full adder
+    fn majority(&mut self, a: ast::Literal, b: ast::Literal, c: ast::Literal) -> ast::Literal {
+        for (x, y, z) in [(a, b, c), (b, c, a), (c, a, b)] {
+            match self.value(x) {
+                Some(true) => return self.or(y, z),
+                Some(false) => return self.and(y, z),
+                None => (),
+            }
+        }
+        let out = self.fresh();
+        for (x, y) in [(a, b), (a, c), (b, c)] {
+            self.define(&[&[x.inversion(), y.inversion(), out], &[x, y, out.inversion()]]);
+        }
+        out
+    }
+
+    pub fn all(&mut self, literals: &[ast::Literal]) -> ast::Literal {
+        literals.iter().fold(self.constant(true), |out, &literal| self.and(out, literal))
+    }
+
+    pub fn any(&mut self, literals: &[ast::Literal]) -> ast::Literal {
+        literals.iter().fold(self.constant(false), |out, &literal| self.or(out, literal))
+    }
+
+    pub fn bitwise<F>(&mut self, a: &[ast::Literal], b: &[ast::Literal], mut gate: F) -> BitVec
+    where F: FnMut(&mut Self, ast::Literal, ast::Literal) -> ast::Literal {
+        a.iter().zip(b.iter()).map(|(&x, &y)| gate(self, x, y)).collect()
+    }
+
+    pub fn ite(&mut self, condition: ast::Literal, then: &[ast::Literal], otherwise: &[ast::Literal]) -> BitVec {
+        self.bitwise(then, otherwise, |blaster, x, y| blaster.mux(condition, x, y))
+    }
+
+    // Ripple-carry addition of vectors of equal width, returning the sum and
+    // the carry out of the most significant bit
+    fn add_with_carry(&mut self, a: &[ast::Literal], b: &[ast::Literal], mut carry: ast::Literal) -> (BitVec, ast::Literal) {
+        let mut sum = Vec::with_capacity(a.len());
+        for (&x, &y) in a.iter().zip(b.iter()) {
+            let half = self.xor(x, y);
+            sum.push(self.xor(half, carry));
+            carry = self.majority(x, y, carry);
+        }
+        (sum, carry)
+    }
+
+    pub fn add(&mut self, a: &[ast::Literal], b: &[ast::Literal]) -> BitVec {
+        let carry = self.constant(false);
+        self.add_with_carry(a, b, carry).0
+    }
+
+    pub fn sub(&mut self, a: &[ast::Literal], b: &[ast::Literal]) -> BitVec {
+        let carry = self.constant(true);
+        self.add_with_carry(a, &not(b), carry).0
+    }
+
+    pub fn neg(&mut self, a: &[ast::Literal]) -> BitVec {
+        let zero = self.constant_bits(a.len(), 0);
+        self.sub(&zero, a)
+    }
+
+    // Shift-and-add multiplication, truncated to the width: each bit of the
+    // second vector adds the first shifted by its position, and the adders
+    // only span the bits the shifted vector reaches
+    pub fn mul(&mut self, a: &[ast::Literal], b: &[ast::Literal]) -> BitVec {
+        let width = a.len();
+        let mut product = self.constant_bits(width, 0);
+        for (shift, &bit) in b.iter().enumerate() {
+            let partial: BitVec = a[..width - shift].iter().map(|&x| self.and(x, bit)).collect();
+            let sum = self.add(&product[shift..], &partial);
+            product.splice(shift.., sum);
+        }
+        product
+    }
+
+    pub fn eq(&mut self, a: &[ast::Literal], b: &[ast::Literal]) -> ast::Literal {
+        let equal = self.bitwise(a, b, Self::iff);
+        self.all(&equal)
+    }
+
+    // Unsigned less than: a - b borrows, that is a + !b + 1 carries nothing
+    pub fn ult(&mut self, a: &[ast::Literal], b: &[ast::Literal]) -> ast::Literal {
+        let carry = self.constant(true);
+        self.add_with_carry(a, &not(b), carry).1.inversion()
+    }
+
+    pub fn ule(&mut self, a: &[ast::Literal], b: &[ast::Literal]) -> ast::Literal {
+        self.ult(b, a).inversion()
+    }
+
+    // Signed comparisons are unsigned ones with the sign bits flipped
+    pub fn slt(&mut self, a: &[ast::Literal], b: &[ast::Literal]) -> ast::Literal {
+        let flip = |bits: &[ast::Literal]| {
+            let mut bits = bits.to_vec();
+            if let Some(sign) = bits.last_mut() {
+                *sign = sign.inversion();
+            }
+            bits
+        };
+        self.ult(&flip(a), &flip(b))
+    }
+
+    pub fn sle(&mut self, a: &[ast::Literal], b: &[ast::Literal]) -> ast::Literal {
+        self.slt(b, a).inversion()
+    }
+
+    // Barrel shifter: each bit of the amount shifts by its power of two or
+    // not, and amounts of at least the width leave only the fill
+    fn shift(&mut self, a: &[ast::Literal], amount: &[ast::Literal], left: bool, fill: ast::Literal) -> BitVec {
+        let width = a.len();
+        if width == 0 {
+            return Vec::new()
+        }
+        let mut bits = a.to_vec();
+        let mut overflow = Vec::new();
+        for (k, &bit) in amount.iter().enumerate() {
+            // The distance 2^k is at least the width
+            if (width - 1).checked_shr(k as u32).unwrap_or(0) == 0 {
+                overflow.push(bit);
+                continue
+            }
+            let distance = 1 << k;
+            let shifted: BitVec = (0..width)
+                .map(|i| match left {
+                    true if distance <= i => bits[i - distance],
+                    false if i + distance < width => bits[i + distance],
+                    _ => fill,
+                })
+                .collect();
+            bits = self.ite(bit, &shifted, &bits);
+        }
+        let overflow = self.any(&overflow);
+        let filled = vec![fill; width];
+        self.ite(overflow, &filled, &bits)
+    }
+
+    pub fn shl(&mut self, a: &[ast::Literal], amount: &[ast::Literal]) -> BitVec {
+        let fill = self.constant(false);
+        self.shift(a, amount, true, fill)
+    }
+
+    pub fn lshr(&mut self, a: &[ast::Literal], amount: &[ast::Literal]) -> BitVec {
+        let fill = self.constant(false);
+        self.shift(a, amount, false, fill)
+    }
+
+    pub fn ashr(&mut self, a: &[ast::Literal], amount: &[ast::Literal]) -> BitVec {
+        match a.last() {
+            Some(&sign) => self.shift(a, amount, false, sign),
+            None => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use itertools::Itertools;
+
+use crate::ast;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// CNF encodings of cardinality constraints over a list of literals. Auxiliary
+// atoms are drawn from the cnf via fresh_atom.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Encoding {
+    // Choose an encoding by the estimated number of clauses
+    Auto,
+    // One clause per (k+1)-subset; no auxiliary atoms
+    Pairwise,
+    // Sinz's sequential counter
+    SequentialCounter,
+    // Bailleux and Boufkhad's totalizer
+    Totalizer,
+    // Asín et al.'s cardinality network (simplified merging networks)
+    CardinalityNetwork,
+    // Frisch and Giannaros's generalization of the commander encoding
+    Commander,
+}
+
+const ENCODINGS: [Encoding; 5] = [
+    Encoding::Pairwise,
+    Encoding::SequentialCounter,
+    Encoding::Totalizer,
+    Encoding::CardinalityNetwork,
+    Encoding::Commander,
+];
+
+fn fresh_literal(cnf: &mut ast::Cnf) -> ast::Literal {
+    ast::Literal::new(true, cnf.fresh_atom())
+}
+
+fn add_clause(cnf: &mut ast::Cnf, literals: Vec<ast::Literal>) {
+    cnf.add(ast::Clause::from(literals))
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if n < k {
+        return 0
+    }
+    let k = k.min(n - k);
+    (0..k).fold(1_usize, |acc, i| acc.saturating_mul(n - i) / (i + 1))
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Assumption: 0 < k < literals.len()
+fn pairwise(cnf: &mut ast::Cnf, literals: &[ast::Literal], k: usize) {
+    for subset in literals.iter().combinations(k + 1) {
+        add_clause(cnf, subset.into_iter().map(|lit| lit.inversion()).collect());
+    }
+}
+
+// Register s[i][j] is true when at least j+1 of the first i+1 literals are true.
+// Assumption: 0 < k < literals.len()
+fn sequential_counter(cnf: &mut ast::Cnf, literals: &[ast::Literal], k: usize) {
+    let n = literals.len();
+    let s: Vec<Vec<ast::Literal>> = (0..n - 1)
+        .map(|_| (0..k).map(|_| fresh_literal(cnf)).collect())
+        .collect();
+
+    add_clause(cnf, vec![literals[0].inversion(), s[0][0]]);
+    for reg in s[0].iter().skip(1) {
+        add_clause(cnf, vec![reg.inversion()]);
+    }
+    for i in 1..n - 1 {
+        let x = literals[i].inversion();
+        add_clause(cnf, vec![x, s[i][0]]);
+        add_clause(cnf, vec![s[i - 1][0].inversion(), s[i][0]]);
+        for j in 1..k {
+            add_clause(cnf, vec![x, s[i - 1][j - 1].inversion(), s[i][j]]);
+            add_clause(cnf, vec![s[i - 1][j].inversion(), s[i][j]]);
+        }
+        add_clause(cnf, vec![x, s[i - 1][k - 1].inversion()]);
+    }
+    add_clause(cnf, vec![literals[n - 1].inversion(), s[n - 2][k - 1].inversion()]);
+}
+
+// Returns unary outputs where the ith output is implied when at least i+1 of
+// the literals are true. Counting stops at bound.
+fn totalizer_tree(cnf: &mut ast::Cnf, literals: &[ast::Literal], bound: usize) -> Vec<ast::Literal> {
+    if literals.len() == 1 {
+        return literals.to_vec()
+    }
+    let (left, right) = literals.split_at(literals.len() / 2);
+    let left = totalizer_tree(cnf, left, bound);
+    let right = totalizer_tree(cnf, right, bound);
+    let outputs: Vec<ast::Literal> = (0..bound.min(left.len() + right.len()))
+        .map(|_| fresh_literal(cnf))
+        .collect();
+    for a in 0..=left.len() {
+        for b in 0..=right.len() {
+            let c = a + b;
+            if c == 0 || outputs.len() < c {
+                continue
+            }
+            let mut clause = vec![outputs[c - 1]];
+            if 0 < a {
+                clause.push(left[a - 1].inversion());
+            }
+            if 0 < b {
+                clause.push(right[b - 1].inversion());
+            }
+            add_clause(cnf, clause);
+        }
+    }
+    outputs
+}
+
+// Assumption: 0 < k < literals.len()
+fn totalizer(cnf: &mut ast::Cnf, literals: &[ast::Literal], k: usize) {
+    let outputs = totalizer_tree(cnf, literals, k + 1);
+    add_clause(cnf, vec![outputs[k].inversion()]);
+}
+
+// Outputs (max, min) of the two inputs, in the upward direction only: the
+// outputs are implied by the inputs, which suffices for at-most constraints.
+fn comparator(cnf: &mut ast::Cnf, a: ast::Literal, b: ast::Literal) -> (ast::Literal, ast::Literal) {
+    let max = fresh_literal(cnf);
+    let min = fresh_literal(cnf);
+    add_clause(cnf, vec![a.inversion(), max]);
+    add_clause(cnf, vec![b.inversion(), max]);
+    add_clause(cnf, vec![a.inversion(), b.inversion(), min]);
+    (max, min)
+}
+
+fn evens(literals: &[ast::Literal]) -> Vec<ast::Literal> {
+    literals.iter().step_by(2).cloned().collect()
+}
+
+fn odds(literals: &[ast::Literal]) -> Vec<ast::Literal> {
+    literals.iter().skip(1).step_by(2).cloned().collect()
+}
+
+// Merges two sorted sequences of the same power-of-two length
+fn half_merge(cnf: &mut ast::Cnf, a: &[ast::Literal], b: &[ast::Literal]) -> Vec<ast::Literal> {
+    let n = a.len();
+    if n == 1 {
+        let (max, min) = comparator(cnf, a[0], b[0]);
+        return vec![max, min]
+    }
+    let d = half_merge(cnf, &evens(a), &evens(b));
+    let e = half_merge(cnf, &odds(a), &odds(b));
+    let mut outputs = vec![d[0]];
+    for i in 0..n - 1 {
+        let (max, min) = comparator(cnf, d[i + 1], e[i]);
+        outputs.push(max);
+        outputs.push(min);
+    }
+    outputs.push(e[n - 1]);
+    outputs
+}
+
+// Sorts a sequence of power-of-two length
+fn half_sort(cnf: &mut ast::Cnf, literals: &[ast::Literal]) -> Vec<ast::Literal> {
+    if literals.len() == 1 {
+        return literals.to_vec()
+    }
+    let (left, right) = literals.split_at(literals.len() / 2);
+    let left = half_sort(cnf, left);
+    let right = half_sort(cnf, right);
+    half_merge(cnf, &left, &right)
+}
+
+// Merges two sorted sequences of the same power-of-two length n, keeping only
+// the first n+1 outputs
+fn simplified_merge(cnf: &mut ast::Cnf, a: &[ast::Literal], b: &[ast::Literal]) -> Vec<ast::Literal> {
+    let n = a.len();
+    if n == 1 {
+        let (max, min) = comparator(cnf, a[0], b[0]);
+        return vec![max, min]
+    }
+    let d = simplified_merge(cnf, &evens(a), &evens(b));
+    let e = simplified_merge(cnf, &odds(a), &odds(b));
+    let mut outputs = vec![d[0]];
+    for i in 0..n / 2 {
+        let (max, min) = comparator(cnf, d[i + 1], e[i]);
+        outputs.push(max);
+        outputs.push(min);
+    }
+    outputs
+}
+
+// Returns the first m outputs of a sorting network over the literals.
+// Assumption: m is a power of two, and literals.len() is a multiple of m
+fn card_network(cnf: &mut ast::Cnf, literals: &[ast::Literal], m: usize) -> Vec<ast::Literal> {
+    if literals.len() == m {
+        return half_sort(cnf, literals)
+    }
+    let (left, right) = literals.split_at(m);
+    let left = card_network(cnf, left, m);
+    let right = card_network(cnf, right, m);
+    let mut outputs = simplified_merge(cnf, &left, &right);
+    outputs.truncate(m);
+    outputs
+}
+
+// Assumption: 0 < k < literals.len()
+fn cardinality_network(cnf: &mut ast::Cnf, literals: &[ast::Literal], k: usize) {
+    let m = (k + 1).next_power_of_two();
+    let mut padded = literals.to_vec();
+    if !padded.len().is_multiple_of(m) {
+        let false_lit = fresh_literal(cnf);
+        add_clause(cnf, vec![false_lit.inversion()]);
+        padded.resize(padded.len().next_multiple_of(m), false_lit);
+    }
+    let outputs = card_network(cnf, &padded, m);
+    add_clause(cnf, vec![outputs[k].inversion()]);
+}
+
+// Each group of k+2 literals is summarized by k ordered commander literals,
+// which must be at least as many as the true literals of the group. The
+// constraint is then applied recursively to the commanders.
+// Assumption: 0 < k < literals.len()
+fn commander(cnf: &mut ast::Cnf, literals: &[ast::Literal], k: usize) {
+    let group_size = k + 2;
+    if literals.len() <= group_size {
+        pairwise(cnf, literals, k);
+        return
+    }
+    let mut commanders = Vec::new();
+    for group in literals.chunks(group_size) {
+        if group.len() <= k {
+            commanders.extend_from_slice(group);
+            continue
+        }
+        let group_commanders: Vec<ast::Literal> = (0..k).map(|_| fresh_literal(cnf)).collect();
+        let mut constrained = group.to_vec();
+        constrained.extend(group_commanders.iter().map(|lit| lit.inversion()));
+        pairwise(cnf, &constrained, k);
+        for (prev, next) in group_commanders.iter().tuple_windows() {
+            add_clause(cnf, vec![next.inversion(), *prev]);
+        }
+        commanders.extend(group_commanders);
+    }
+    if k < commanders.len() {
+        commander(cnf, &commanders, k);
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn totalizer_size(n: usize, bound: usize) -> (usize, usize) {
+    if n == 1 {
+        return (1, 0)
+    }
+    let (left_outputs, left_clauses) = totalizer_size(n / 2, bound);
+    let (right_outputs, right_clauses) = totalizer_size(n - n / 2, bound);
+    let outputs = bound.min(left_outputs + right_outputs);
+    let clauses = (0..=left_outputs)
+        .map(|a| (0..=right_outputs).filter(|b| 0 < a + b && a + b <= outputs).count())
+        .sum::<usize>();
+    (outputs, left_clauses + right_clauses + clauses)
+}
+
+fn half_merge_size(n: usize) -> usize {
+    if n == 1 { 3 } else { 2 * half_merge_size(n / 2) + 3 * (n - 1) }
+}
+
+fn half_sort_size(n: usize) -> usize {
+    if n == 1 { 0 } else { 2 * half_sort_size(n / 2) + half_merge_size(n / 2) }
+}
+
+fn simplified_merge_size(n: usize) -> usize {
+    if n == 1 { 3 } else { 2 * simplified_merge_size(n / 2) + 3 * (n / 2) }
+}
+
+fn commander_size(n: usize, k: usize) -> usize {
+    let group_size = k + 2;
+    if n <= group_size {
+        return binomial(n, k + 1)
+    }
+    let full_groups = n / group_size;
+    let rest = n % group_size;
+    let (rest_clauses, rest_commanders) = if rest <= k {
+        (0, rest)
+    } else {
+        (binomial(rest + k, k + 1) + k - 1, k)
+    };
+    let commanders = full_groups * k + rest_commanders;
+    let clauses = full_groups.saturating_mul(binomial(group_size + k, k + 1) + k - 1) + rest_clauses;
+    clauses.saturating_add(if k < commanders { commander_size(commanders, k) } else { 0 })
+}
+
+// Estimated number of clauses needed to encode an at-most-k constraint over n
+// literals.
+// Assumption: 0 < k < n
+pub fn estimate_clauses(encoding: Encoding, n: usize, k: usize) -> usize {
+    match encoding {
+        Encoding::Auto => estimate_clauses(choose_encoding(n, k), n, k),
+        Encoding::Pairwise => binomial(n, k + 1),
+        Encoding::SequentialCounter => 2 * n * k + n - 3 * k - 1,
+        Encoding::Totalizer => totalizer_size(n, k + 1).1 + 1,
+        Encoding::CardinalityNetwork => {
+            let m = (k + 1).next_power_of_two();
+            let blocks = n.div_ceil(m);
+            let padding = if n.is_multiple_of(m) { 0 } else { 1 };
+            blocks * half_sort_size(m) + (blocks - 1) * simplified_merge_size(m) + padding + 1
+        },
+        Encoding::Commander => commander_size(n, k),
+    }
+}
+
+// Chooses the encoding with the fewest estimated clauses, preferring encodings
+// with fewer auxiliary atoms on ties.
+// Assumption: 0 < k < n
+pub fn choose_encoding(n: usize, k: usize) -> Encoding {
+    ENCODINGS.into_iter()
+        .min_by_key(|&encoding| estimate_clauses(encoding, n, k))
+        .unwrap()
+}
+
+// Adds clauses which are satisfiable exactly when at most k of the literals are
+// true (when projected onto the atoms of the literals).
+pub fn at_most_k(cnf: &mut ast::Cnf, literals: &[ast::Literal], k: usize, encoding: Encoding) {
+    for literal in literals {
+        cnf.declare_atom(literal.atom());
+    }
+    let n = literals.len();
+    if n <= k {
+        return
+    }
+    if k == 0 {
+        for literal in literals {
+            add_clause(cnf, vec![literal.inversion()]);
+        }
+        return
+    }
+    let encoding = match encoding {
+        Encoding::Auto => choose_encoding(n, k),
+        encoding => encoding,
+    };
+    match encoding {
+        Encoding::Auto => unreachable!(),
+        Encoding::Pairwise => pairwise(cnf, literals, k),
+        Encoding::SequentialCounter => sequential_counter(cnf, literals, k),
+        Encoding::Totalizer => totalizer(cnf, literals, k),
+        Encoding::CardinalityNetwork => cardinality_network(cnf, literals, k),
+        Encoding::Commander => commander(cnf, literals, k),
+    }
+}
+
+// At least k literals are true exactly when at most n-k inversions are true
+pub fn at_least_k(cnf: &mut ast::Cnf, literals: &[ast::Literal], k: usize, encoding: Encoding) {
+    if literals.len() < k {
+        for literal in literals {
+            cnf.declare_atom(literal.atom());
+        }
+        add_clause(cnf, Vec::new());
+        return
+    }
+    let inversions: Vec<ast::Literal> = literals.iter().map(|lit| lit.inversion()).collect();
+    at_most_k(cnf, &inversions, literals.len() - k, encoding)
+}
+
+pub fn exactly_k(cnf: &mut ast::Cnf, literals: &[ast::Literal], k: usize, encoding: Encoding) {
+    at_most_k(cnf, literals, k, encoding);
+    at_least_k(cnf, literals, k, encoding)
+}
+
+pub fn at_most_one(cnf: &mut ast::Cnf, literals: &[ast::Literal], encoding: Encoding) {
+    at_most_k(cnf, literals, 1, encoding)
+}
+
+pub fn exactly_one(cnf: &mut ast::Cnf, literals: &[ast::Literal], encoding: Encoding) {
+    exactly_k(cnf, literals, 1, encoding)
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::ast;
+use crate::bignum::BigUint;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+enum Constraint {
+    // Without duplicate literals or tautologies
+    Clause(Vec<ast::Literal>),
+    Xor(Vec<ast::Atom>, bool),
+}
+
+enum Status {
+    Satisfied,
+    Conflict,
+    Unit(ast::Literal),
+    // Not yet satisfied, with at least two unassigned atoms. The parity is that
+    // of the remaining atoms of an xor, and always false for a clause.
+    Open(bool),
+}
+
+// Counts models by DPLL search, as in sharpSAT: after propagation, the open
+// constraints are split into components sharing no atoms, whose counts are
+// multiplied, and the count of each component is cached by its atoms and
+// constraints. Branches without models are pruned by the CDCL solver, which
+// keeps its learned clauses between calls.
+struct Counter {
+    solver: dpll::Solver,
+    constraints: Vec<Constraint>,
+    // Constraints by the index of an atom they contain
+    occurs: Vec<Vec<usize>>,
+    values: Vec<Option<bool>>,
+    trail: Vec<ast::Atom>,
+    // Marks for finding components, by atom and constraint index
+    atom_seen: Vec<bool>,
+    constraint_seen: Vec<bool>,
+    cache: HashMap<Vec<u32>, BigUint>,
+    decisions: usize,
+    cache_hits: usize,
+    verbosity: usize,
+}
+
+impl Counter {
+    fn new(cnf: &ast::Cnf, verbosity: usize) -> Self {
+        let mut constraints = Vec::new();
+        for clause in cnf.clauses() {
+            let literals: Vec<ast::Literal> = clause.literals().cloned().sorted().dedup().collect();
+            if literals.iter().tuple_windows().any(|(a, b)| a.atom() == b.atom()) {
+                continue
+            }
+            constraints.push(Constraint::Clause(literals));
+        }
+        for xor in cnf.xors() {
+            constraints.push(Constraint::Xor(xor.atoms().cloned().collect(), xor.parity()));
+        }
+        let atoms = cnf.declared_atoms().chain(cnf.atoms()).map(|atom| atom.index() + 1).max().unwrap_or(0);
+        let mut occurs = vec![Vec::new(); atoms];
+        for (i, constraint) in constraints.iter().enumerate() {
+            let constraint_atoms: Vec<ast::Atom> = match constraint {
+                Constraint::Clause(literals) => literals.iter().map(|lit| lit.atom()).collect(),
+                Constraint::Xor(atoms, _) => atoms.clone(),
+            };
+            for atom in constraint_atoms {
+                occurs[atom.index()].push(i);
+            }
+        }
+        Self {
+            solver: dpll::Solver::new(cnf, verbosity.saturating_sub(1)),
+            atom_seen: vec![false; atoms],
+            constraint_seen: vec![false; constraints.len()],
+            constraints,
+            occurs,
+            values: vec![None; atoms],
+            trail: Vec::new(),
+            cache: HashMap::new(),
+            decisions: 0,
+            cache_hits: 0,
+            verbosity,
+        }
+    }
+
+    fn status(&self, i: usize) -> Status {
+        match &self.constraints[i] {
+            Constraint::Clause(literals) => {
+                let mut unassigned = None;
+                let mut count = 0;
+                for &literal in literals {
+                    match self.values[literal.atom().index()] {
+                        Some(phase) if phase == literal.phase() => return Status::Satisfied,
+                        Some(_) => (),
+                        None => {
+                            unassigned = Some(literal);
+                            count += 1;
+                        },
+                    }
+                }
+                match (count, unassigned) {
+                    (0, _) => Status::Conflict,
+                    (1, Some(literal)) => Status::Unit(literal),
+                    _ => Status::Open(false),
+                }
+            },
+            Constraint::Xor(atoms, parity) => {
+                let mut parity = *parity;
+                let mut unassigned = None;
+                let mut count = 0;
+                for &atom in atoms {
+                    match self.values[atom.index()] {
+                        Some(phase) => parity ^= phase,
+                        None => {
+                            unassigned = Some(atom);
+                            count += 1;
+                        },
+                    }
+                }
+                match (count, unassigned) {
+                    (0, _) if parity => Status::Conflict,
+                    (0, _) => Status::Satisfied,
+                    (1, Some(atom)) => Status::Unit(ast::Literal::new(parity, atom)),
+                    _ => Status::Open(parity),
+                }
+            },
+        }
+    }
+
+    fn is_open(&self, i: usize) -> bool {
+        matches!(self.status(i), Status::Open(_))
+    }
+
+    fn assign(&mut self, literal: ast::Literal) {
+        self.values[literal.atom().index()] = Some(literal.phase());
+        self.trail.push(literal.atom());
+    }
+
+    fn undo(&mut self, position: usize) {
+        for atom in self.trail.drain(position..) {
+            self.values[atom.index()] = None;
+        }
+    }
+
+    // Propagates the atoms assigned from the trail position onwards. Returns
+    // false on a conflict.
+    fn propagate(&mut self, mut position: usize) -> bool {
+        while position < self.trail.len() {
+            let atom = self.trail[position];
+            position += 1;
+            for k in 0..self.occurs[atom.index()].len() {
+                let i = self.occurs[atom.index()][k];
+                match self.status(i) {
+                    Status::Conflict => return false,
+                    Status::Unit(literal) => self.assign(literal),
+                    _ => (),
+                }
+            }
+        }
+        true
+    }
+
+    // Splits the open constraints among those given into components, each with
+    // its (sorted) unassigned atoms and constraints
+    fn components(&mut self, constraints: &[usize]) -> Vec<(Vec<ast::Atom>, Vec<usize>)> {
+        let mut components = Vec::new();
+        for &start in constraints {
+            if self.constraint_seen[start] || !self.is_open(start) {
+                continue
+            }
+            self.constraint_seen[start] = true;
+            let mut component_atoms = Vec::new();
+            let mut component_constraints = vec![start];
+            let mut k = 0;
+            while k < component_constraints.len() {
+                let i = component_constraints[k];
+                k += 1;
+                let atoms: Vec<ast::Atom> = match &self.constraints[i] {
+                    Constraint::Clause(literals) => literals.iter().map(|lit| lit.atom()).collect(),
+                    Constraint::Xor(atoms, _) => atoms.clone(),
+                };
+                for atom in atoms {
+                    if self.values[atom.index()].is_some() || self.atom_seen[atom.index()] {
+                        continue
+                    }
+                    self.atom_seen[atom.index()] = true;
+                    component_atoms.push(atom);
+                    for k in 0..self.occurs[atom.index()].len() {
+                        let j = self.occurs[atom.index()][k];
+                        if !self.constraint_seen[j] && self.is_open(j) {
+                            self.constraint_seen[j] = true;
+                            component_constraints.push(j);
+                        }
+                    }
+                }
+            }
+            components.push((component_atoms, component_constraints));
+        }
+        for (atoms, constraints) in components.iter_mut() {
+            for atom in atoms.iter() {
+                self.atom_seen[atom.index()] = false;
+            }
+            for &i in constraints.iter() {
+                self.constraint_seen[i] = false;
+            }
+            atoms.sort();
+            constraints.sort();
+        }
+        components
+    }
+
+    // The number of assignments to the atoms satisfying the open constraints
+    // among those given, where the atoms include every unassigned atom of the
+    // constraints
+    fn count_open(&mut self, atoms: &[ast::Atom], constraints: &[usize]) -> BigUint {
+        let components = self.components(constraints);
+        // Components are independent, so when there is a model every component
+        // has one, and otherwise nothing is cached
+        if !components.is_empty() {
+            let assumptions: Vec<ast::Literal> = self.trail.iter()
+                .map(|&atom| ast::Literal::new(self.values[atom.index()].unwrap(), atom))
+                .collect();
+            if self.solver.solve_with(&assumptions).is_none() {
+                return BigUint::zero()
+            }
+        }
+        let constrained: usize = components.iter().map(|(atoms, _)| atoms.len()).sum();
+        let unassigned = atoms.iter().filter(|atom| self.values[atom.index()].is_none()).count();
+        let mut count = BigUint::pow2(unassigned - constrained);
+        for (atoms, constraints) in components {
+            count = &count * &self.count_component(atoms, constraints);
+            if count.is_zero() {
+                break
+            }
+        }
+        count
+    }
+
+    fn count_component(&mut self, atoms: Vec<ast::Atom>, constraints: Vec<usize>) -> BigUint {
+        // Open xors also depend on the parity of their assigned atoms
+        let key: Vec<u32> = atoms.iter().map(|atom| atom.index() as u32)
+            .chain(std::iter::once(u32::MAX))
+            .chain(constraints.iter().map(|&i| match self.status(i) {
+                Status::Open(parity) => 2 * i as u32 + parity as u32,
+                _ => 2 * i as u32,
+            }))
+            .collect();
+        if let Some(count) = self.cache.get(&key) {
+            self.cache_hits += 1;
+            return count.clone()
+        }
+
+        // Branch on the atom occurring in the most open constraints
+        let mut occurrences: HashMap<ast::Atom, usize> = HashMap::new();
+        for &i in constraints.iter() {
+            match &self.constraints[i] {
+                Constraint::Clause(literals) => for literal in literals {
+                    *occurrences.entry(literal.atom()).or_default() += 1;
+                },
+                Constraint::Xor(xor_atoms, _) => for &atom in xor_atoms {
+                    *occurrences.entry(atom).or_default() += 1;
+                },
+            }
+        }
+        let branch = *atoms.iter().max_by_key(|&atom| (occurrences.get(atom), std::cmp::Reverse(atom))).unwrap();
+
+        self.decisions += 1;
+        if 2 < self.verbosity {
+            log::info!("Branching on {} in a component of {} atoms", branch, atoms.len());
+        }
+        let mut count = BigUint::zero();
+        for phase in [false, true] {
+            let position = self.trail.len();
+            self.assign(ast::Literal::new(phase, branch));
+            if self.propagate(position) {
+                count += &self.count_open(&atoms, &constraints);
+            }
+            self.undo(position);
+        }
+        self.cache.insert(key, count.clone());
+        count
+    }
+}
+
+// Counts the models of the cnf over its declared atoms (see Cnf::declared_atoms)
+pub fn count(cnf: &ast::Cnf, verbosity: usize) -> BigUint {
+    let mut counter = Counter::new(cnf, verbosity);
+    let atoms: Vec<ast::Atom> = cnf.declared_atoms().chain(cnf.atoms()).sorted().dedup().collect();
+    let constraints: Vec<usize> = (0..counter.constraints.len()).collect();
+    // Start with the constraints which are units (or empty) already
+    for &i in constraints.iter() {
+        match counter.status(i) {
+            Status::Conflict => return BigUint::zero(),
+            Status::Unit(literal) => counter.assign(literal),
+            _ => (),
+        }
+    }
+    if !counter.propagate(0) {
+        return BigUint::zero()
+    }
+    let count = counter.count_open(&atoms, &constraints);
+    if 0 < verbosity {
+        log::info!("Counted {} models with {} decisions, {} cache hits and {} cached components",
+            count, counter.decisions, counter.cache_hits, counter.cache.len());
+    }
+    count
+}
@@ -3,8 +3,22 @@ use std::io::BufRead;
 
 use itertools::Itertools;
 
+use crate::approxmc;
 use crate::ast;
+use crate::backbone;
+use crate::bignum;
+use crate::count;
 use crate::dpll;
+use crate::enumerate;
+use crate::lookahead;
+use crate::mcs;
+use crate::minimize;
+use crate::mus;
+use crate::portfolio;
+use crate::qbf;
+use crate::sample;
+use crate::simplify;
+use crate::sls;
 
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -14,7 +28,7 @@ where P: AsRef<path::Path> {
     let file = fs::File::open(path).ok()?;
     let mut lines = io::BufReader::new(file)
         .lines()
-        .flatten()
+        .map_while(Result::ok)
         .filter(|str| str.chars().next().is_some_and(|c| c != 'c'));
     // let line = lines.next()?.ok()?;
     let line = lines.next()?;
@@ -31,11 +45,24 @@ where P: AsRef<path::Path> {
         log::info!("num_vars: {}, num_clauses: {}", num_vars, num_clauses);
     }
     let mut clauses: ast::Cnf = ast::Cnf::new();
+    // Atoms which occur in no clause still count towards the models
+    if 0 < num_vars {
+        clauses.declare_atom(ast::Atom::new(num_vars));
+    }
     for line in lines {
         if num_clauses == 0 {
             return Some(clauses)
         }
         num_clauses -= 1;
+        // CryptoMiniSat-style xor constraints, e.g. "x1 -2 3 0"
+        if let Some(line) = line.strip_prefix('x') {
+            let xor = ast::Xor::from_literals(parse_literals(line)?);
+            if 3 < verbosity {
+                log::info!("Adding xor: {}", xor);
+            };
+            clauses.add_xor(xor);
+            continue
+        }
         let mut clause = ast::Clause::new();
         for lit_str in line.split_whitespace() {
             let num = lit_str.parse::<i32>().ok()?;
@@ -77,4 +104,445 @@ where P: AsRef<path::Path> {
         println!("UNSAT");
     }
     Ok(asgmt)
-}
\ No newline at end of file
+}
+
+// Prints a model which is subset-minimal, or reduced to a prime implicant, or
+// both
+pub fn read_dimacs_minimize_and_print<P>(path: P, minimal: bool, prime: bool, verbosity: usize) -> Result<Option<ast::Asgmt>, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let asgmt = if minimal {
+        minimize::minimal_model(&cnf, verbosity)
+    } else {
+        dpll::sat(&mut cnf.clone(), verbosity)
+    };
+    let asgmt = asgmt.map(|asgmt| if prime { minimize::prime_implicant(&cnf, &asgmt) } else { asgmt });
+    if let Some(asgmt) = &asgmt {
+        println!("SAT: {}", asgmt);
+    } else {
+        println!("UNSAT");
+    }
+    Ok(asgmt)
+}
+
+pub fn read_dimacs_lookahead_and_print<P>(path: P, verbosity: usize) -> Result<Option<ast::Asgmt>, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let asgmt = lookahead::sat(&cnf, verbosity);
+    if let Some(asgmt) = &asgmt {
+        println!("SAT: {}", asgmt);
+    } else {
+        println!("UNSAT");
+    }
+    Ok(asgmt)
+}
+
+pub fn read_dimacs_portfolio_and_print<P>(path: P, threads: usize, share: bool, verbosity: usize) -> Result<Option<ast::Asgmt>, String>
+where P: AsRef<path::Path> {
+    let mut cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let asgmt = portfolio::sat(&mut cnf, threads, share, verbosity);
+    if let Some(asgmt) = &asgmt {
+        println!("SAT: {}", asgmt);
+    } else {
+        println!("UNSAT");
+    }
+    Ok(asgmt)
+}
+
+// Parses literals up to a terminating 0
+fn parse_literals(line: &str) -> Option<Vec<ast::Literal>> {
+    let mut literals = Vec::new();
+    for lit_str in line.split_whitespace() {
+        let num = lit_str.parse::<i32>().ok()?;
+        if num == 0 {
+            break
+        }
+        literals.push(ast::Literal::new(0 < num, ast::Atom::new(num.unsigned_abs())));
+    }
+    Some(literals)
+}
+
+fn literals_to_string<'a>(literals: impl Iterator<Item = &'a ast::Literal>) -> String {
+    literals.map(|literal| format!("{}{} ", if literal.phase() { "" } else { "-" }, literal.atom())).join("")
+}
+
+// Writes the clauses and xors of the cnf, without a header
+fn write_constraints<W>(writer: &mut W, cnf: &ast::Cnf) -> io::Result<()>
+where W: io::Write {
+    for clause in cnf.clauses() {
+        writeln!(writer, "{}0", literals_to_string(clause.literals()))?;
+    }
+    for xor in cnf.xors() {
+        // An even parity is written by negating the first atom
+        let literals: Vec<ast::Literal> = xor.atoms()
+            .enumerate()
+            .map(|(k, &atom)| ast::Literal::new(0 < k || xor.parity(), atom))
+            .collect();
+        match literals[..] {
+            [] if xor.parity() => writeln!(writer, "0")?,
+            [] => (),
+            _ => writeln!(writer, "x{}0", literals_to_string(literals.iter()))?,
+        }
+    }
+    Ok(())
+}
+
+// Writes the cnf in DIMACS format, declaring all of its atoms
+pub fn write_dimacs<W>(writer: &mut W, cnf: &ast::Cnf) -> io::Result<()>
+where W: io::Write {
+    let num_vars = cnf.declared_atoms().last().map_or(0, |atom| atom.index());
+    writeln!(writer, "p cnf {} {}", num_vars, cnf.len() + cnf.xors().len())?;
+    write_constraints(writer, cnf)
+}
+
+// Writes the cnf and cubes in the iCNF format, where each cube is a line of
+// assumptions, e.g. "a 1 -2 0"
+pub fn write_icnf<W>(writer: &mut W, cnf: &ast::Cnf, cubes: &[Vec<ast::Literal>]) -> io::Result<()>
+where W: io::Write {
+    writeln!(writer, "p inccnf")?;
+    write_constraints(writer, cnf)?;
+    for cube in cubes {
+        writeln!(writer, "a {}0", literals_to_string(cube.iter()))?;
+    }
+    Ok(())
+}
+
+// Reads a cnf and its cubes in the iCNF format
+pub fn read_icnf<P>(path: P, verbosity: usize) -> Option<(ast::Cnf, Vec<Vec<ast::Literal>>)>
+where P: AsRef<path::Path> {
+    let file = fs::File::open(path).ok()?;
+    let mut lines = io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|str| str.chars().next().is_some_and(|c| c != 'c'));
+    if lines.next()?.split_whitespace().collect_tuple::<(&str, &str)>()? != ("p", "inccnf") {
+        return None
+    }
+    let mut cnf = ast::Cnf::new();
+    let mut cubes = Vec::new();
+    for line in lines {
+        if let Some(line) = line.strip_prefix('a') {
+            cubes.push(parse_literals(line)?);
+        } else if let Some(line) = line.strip_prefix('x') {
+            cnf.add_xor(ast::Xor::from_literals(parse_literals(line)?));
+        } else {
+            let mut clause = ast::Clause::new();
+            for literal in parse_literals(&line)? {
+                clause.add(literal);
+            }
+            cnf.add(clause);
+        }
+    }
+    if 3 < verbosity {
+        log::info!("Read {} cubes of CNF: {}", cubes.len(), cnf);
+    }
+    Some((cnf, cubes))
+}
+
+pub fn read_dimacs_cube_and_print<P>(path: P, depth: Option<usize>, limit: usize, verbosity: usize) -> Result<usize, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let cubes = lookahead::cubes(&cnf, depth, limit, verbosity);
+    write_icnf(&mut io::stdout().lock(), &cnf, &cubes).map_err(|err| err.to_string())?;
+    Ok(cubes.len())
+}
+
+pub fn read_dimacs_preprocess_and_print<P>(path: P, steps: &[simplify::Step], verbosity: usize) -> Result<ast::Cnf, String>
+where P: AsRef<path::Path> {
+    let mut cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    if dpll::preprocess(&mut cnf, steps, verbosity).is_none() {
+        // Only the empty clause remains
+        cnf.retain_clauses(|_| false);
+        cnf.take_xors();
+        cnf.add(ast::Clause::new());
+    }
+    write_dimacs(&mut io::stdout().lock(), &cnf).map_err(|err| err.to_string())?;
+    Ok(cnf)
+}
+
+// Prints a minimal unsatisfiable subset of the clauses in DIMACS format,
+// preceded by a comment line listing their indices in the input, from 1
+pub fn read_dimacs_mus_and_print<P>(path: P, algorithm: mus::Algorithm, verbosity: usize) -> Result<Option<Vec<usize>>, String>
+where P: AsRef<path::Path> {
+    let mut cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let Some(mus) = mus::mus(&cnf, algorithm, verbosity) else {
+        println!("SAT");
+        return Ok(None)
+    };
+    let mut index = 0;
+    cnf.retain_clauses(|_| {
+        index += 1;
+        mus.binary_search(&(index - 1)).is_ok()
+    });
+    println!("c mus {}", mus.iter().map(|index| index + 1).join(" "));
+    write_dimacs(&mut io::stdout().lock(), &cnf).map_err(|err| err.to_string())?;
+    Ok(Some(mus))
+}
+
+// Reads a cnf of groups of clauses in the GCNF format, where each clause is
+// tagged with its group, e.g. "{2} 1 -3 0", and group 0 holds the hard
+// clauses. Returns these and the groups, in order of their ids from 1.
+pub fn read_gcnf<P>(path: P, verbosity: usize) -> Option<(ast::Cnf, Vec<Vec<ast::Clause>>)>
+where P: AsRef<path::Path> {
+    let file = fs::File::open(path).ok()?;
+    let mut lines = io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|str| str.chars().next().is_some_and(|c| c != 'c'));
+    let (num_vars, num_groups) = match lines.next()?.split_whitespace().collect_tuple()? {
+        ("p", "gcnf", num_vars, _, num_groups) =>
+            (num_vars.parse::<u32>().ok()?,
+             num_groups.parse::<usize>().ok()?),
+        _ => return None,
+    };
+    let mut hard = ast::Cnf::new();
+    if 0 < num_vars {
+        hard.declare_atom(ast::Atom::new(num_vars));
+    }
+    let mut groups = vec![Vec::new(); num_groups];
+    for line in lines {
+        let (group, line) = line.trim_start().strip_prefix('{')?.split_once('}')?;
+        let group = group.trim().parse::<usize>().ok()?;
+        let clause = ast::Clause::from(parse_literals(line)?);
+        if group == 0 {
+            hard.add(clause);
+        } else {
+            groups.get_mut(group - 1)?.push(clause);
+        }
+    }
+    if 3 < verbosity {
+        log::info!("Read {} groups with hard clauses: {}", groups.len(), hard);
+    }
+    Some((hard, groups))
+}
+
+// Reads a qbf in the QDIMACS format: DIMACS with quantifier blocks between the
+// header and the clauses, e.g. "a 1 2 0" and "e 3 0"
+pub fn read_qdimacs<P>(path: P, verbosity: usize) -> Option<qbf::Qbf>
+where P: AsRef<path::Path> {
+    let file = fs::File::open(path).ok()?;
+    let mut lines = io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|str| str.chars().next().is_some_and(|c| c != 'c'))
+        .peekable();
+    let num_vars = match lines.next()?.split_whitespace().collect_tuple()? {
+        ("p", "cnf", num_vars, _) => num_vars.parse::<u32>().ok()?,
+        _ => return None,
+    };
+    let mut prefix = Vec::new();
+    while let Some(line) = lines.next_if(|line| line.starts_with(['a', 'e'])) {
+        let quantifier = if line.starts_with('a') { qbf::Quantifier::Forall } else { qbf::Quantifier::Exists };
+        let atoms = parse_literals(&line[1..])?.iter().map(|literal| literal.atom()).collect();
+        prefix.push((quantifier, atoms));
+    }
+    let mut matrix = ast::Cnf::new();
+    if 0 < num_vars {
+        matrix.declare_atom(ast::Atom::new(num_vars));
+    }
+    for line in lines {
+        matrix.add(ast::Clause::from(parse_literals(&line)?));
+    }
+    if 3 < verbosity {
+        log::info!("Read QBF with prefix {:?} and matrix: {}", prefix, matrix);
+    }
+    Some(qbf::Qbf { prefix, matrix })
+}
+
+// Prints the value of the qbf, along with a winning move for the outermost
+// block (its certificate) if the player of that block wins
+pub fn read_qdimacs_solve_and_print<P>(path: P, verbosity: usize) -> Result<bool, String>
+where P: AsRef<path::Path> {
+    let qbf = read_qdimacs(path, verbosity).ok_or("Error parsing QDIMACS file.")?;
+    let (value, certificate) = qbf::solve(&qbf, verbosity);
+    let value_str = if value { "TRUE" } else { "FALSE" };
+    if let Some(certificate) = certificate {
+        println!("{}: {}", value_str, certificate);
+    } else {
+        println!("{}", value_str);
+    }
+    Ok(value)
+}
+
+// Writes the hard clauses and the groups of the given ids in the GCNF format
+fn write_gcnf<W>(writer: &mut W, hard: &ast::Cnf, groups: &[Vec<ast::Clause>], ids: &[usize]) -> io::Result<()>
+where W: io::Write {
+    let num_vars = hard.declared_atoms().last().map_or(0, |atom| atom.index());
+    let num_clauses = hard.len() + ids.iter().map(|&id| groups[id - 1].len()).sum::<usize>();
+    writeln!(writer, "p gcnf {} {} {}", num_vars, num_clauses, groups.len())?;
+    for clause in hard.clauses() {
+        writeln!(writer, "{{0}} {}0", literals_to_string(clause.literals()))?;
+    }
+    for &id in ids {
+        for clause in groups[id - 1].iter() {
+            writeln!(writer, "{{{}}} {}0", id, literals_to_string(clause.literals()))?;
+        }
+    }
+    Ok(())
+}
+
+// Prints a minimal unsatisfiable set of the groups in GCNF format, along with
+// the hard clauses, preceded by a comment line listing the group ids
+pub fn read_gcnf_mus_and_print<P>(path: P, algorithm: mus::Algorithm, verbosity: usize) -> Result<Option<Vec<usize>>, String>
+where P: AsRef<path::Path> {
+    let (hard, groups) = read_gcnf(path, verbosity).ok_or("Error parsing GCNF file.")?;
+    let Some(mus) = mus::Extractor::new(&hard, groups.clone(), verbosity).mus(algorithm) else {
+        println!("SAT");
+        return Ok(None)
+    };
+    let ids: Vec<usize> = mus.into_iter().map(|group| group + 1).collect();
+    println!("c mus {}", ids.iter().join(" "));
+    write_gcnf(&mut io::stdout().lock(), &hard, &groups, &ids).map_err(|err| err.to_string())?;
+    Ok(Some(ids))
+}
+
+pub fn read_dimacs_backbone_and_print<P>(path: P, rotate: bool, verbosity: usize) -> Result<Option<ast::Asgmt>, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let backbone = backbone::backbone(&cnf, rotate, verbosity);
+    if let Some(backbone) = &backbone {
+        println!("BACKBONE: {}", backbone);
+    } else {
+        println!("UNSAT");
+    }
+    Ok(backbone)
+}
+
+// Prints each minimal correction set, of clause indices or group ids from 1,
+// and then their number
+fn print_mcses(mcses: mcs::Mcses, limit: Option<usize>) -> usize {
+    let mut count = 0;
+    for mcs in mcses.take(limit.unwrap_or(usize::MAX)) {
+        println!("MCS: {}", mcs.iter().map(|index| index + 1).join(" "));
+        count += 1;
+    }
+    println!("MCSES: {}", count);
+    count
+}
+
+pub fn read_dimacs_mcs_and_print<P>(path: P, algorithm: mcs::Algorithm, limit: Option<usize>, verbosity: usize) -> Result<usize, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    Ok(print_mcses(mcs::mcses(&cnf, algorithm, verbosity), limit))
+}
+
+pub fn read_gcnf_mcs_and_print<P>(path: P, algorithm: mcs::Algorithm, limit: Option<usize>, verbosity: usize) -> Result<usize, String>
+where P: AsRef<path::Path> {
+    let (hard, groups) = read_gcnf(path, verbosity).ok_or("Error parsing GCNF file.")?;
+    Ok(print_mcses(mcs::Mcses::new(&hard, groups, algorithm, verbosity), limit))
+}
+
+// Solves each cube in turn under assumptions, by one solver which keeps its
+// learned clauses between them, until one is satisfiable. Refuted cubes are
+// left out by cube, so no cubes at all means UNSAT.
+pub fn read_icnf_conquer_and_print<P>(path: P, verbosity: usize) -> Result<Option<ast::Asgmt>, String>
+where P: AsRef<path::Path> {
+    let (cnf, cubes) = read_icnf(path, verbosity).ok_or("Error parsing iCNF file.")?;
+    let mut solver = dpll::Solver::new(&cnf, verbosity.saturating_sub(1));
+    let mut asgmt = None;
+    for (k, cube) in cubes.iter().enumerate() {
+        asgmt = solver.solve_with(cube);
+        if 0 < verbosity {
+            log::info!("Cube {} of {} is {}", k + 1, cubes.len(), if asgmt.is_some() { "SAT" } else { "UNSAT" });
+        }
+        if asgmt.is_some() {
+            break
+        }
+    }
+    if let Some(asgmt) = &asgmt {
+        println!("SAT: {}", asgmt);
+    } else {
+        println!("UNSAT");
+    }
+    Ok(asgmt)
+}
+
+// Reads the atoms of "c ind" comment lines, which list the atoms to project
+// models onto (as in the sampling sets of model counters), or None if there
+// are no such lines
+pub fn read_dimacs_projection<P>(path: P) -> Option<Vec<ast::Atom>>
+where P: AsRef<path::Path> {
+    let file = fs::File::open(path).ok()?;
+    let mut projection: Option<Vec<ast::Atom>> = None;
+    for line in io::BufReader::new(file).lines().map_while(Result::ok) {
+        let Some(line) = line.strip_prefix("c ind ") else {
+            continue
+        };
+        let atoms = projection.get_or_insert_with(Vec::new);
+        for num in line.split_whitespace().map_while(|str| str.parse::<u32>().ok()) {
+            if num == 0 {
+                break
+            }
+            atoms.push(ast::Atom::new(num));
+        }
+    }
+    projection
+}
+
+// Prints the models of the file (up to the limit, if any) projected onto the
+// given atoms, or else onto the atoms of its "c ind" lines, if any. Returns the
+// number of models printed.
+pub fn read_dimacs_enumerate_and_print<P>(path: P, limit: Option<usize>, projection: Option<Vec<ast::Atom>>, verbosity: usize) -> Result<usize, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(&path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let projection = projection.or_else(|| read_dimacs_projection(&path));
+    let mut count = 0;
+    for model in enumerate::models(&cnf, projection, verbosity).take(limit.unwrap_or(usize::MAX)) {
+        println!("MODEL: {}", model);
+        count += 1;
+    }
+    println!("MODELS: {}", count);
+    Ok(count)
+}
+
+// Prints the number of models of the file over its num_vars atoms
+pub fn read_dimacs_count_and_print<P>(path: P, verbosity: usize) -> Result<bignum::BigUint, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let count = count::count(&cnf, verbosity);
+    println!("COUNT: {}", count);
+    Ok(count)
+}
+
+// Prints an estimate of the number of models of the file, projected onto the
+// given atoms, or else onto the atoms of its "c ind" lines, if any
+pub fn read_dimacs_approx_count_and_print<P>(path: P, projection: Option<Vec<ast::Atom>>, epsilon: f64, delta: f64, seed: u64, verbosity: usize) -> Result<bignum::BigUint, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(&path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let projection = projection.or_else(|| read_dimacs_projection(&path));
+    let count = approxmc::approx_count(&cnf, projection, epsilon, delta, seed, verbosity)?;
+    println!("APPROXIMATE COUNT: {}", count);
+    Ok(count)
+}
+
+// Prints samples of the models of the file, projected onto the given atoms, or
+// else onto the atoms of its "c ind" lines, if any. Returns the number of
+// samples printed, which is less than requested if sampling fails.
+pub fn read_dimacs_sample_and_print<P>(path: P, samples: usize, projection: Option<Vec<ast::Atom>>, epsilon: f64, seed: u64, verbosity: usize) -> Result<usize, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(&path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let projection = projection.or_else(|| read_dimacs_projection(&path));
+    let mut count = 0;
+    for sample in sample::samples(&cnf, projection, epsilon, seed, verbosity)?.take(samples) {
+        println!("SAMPLE: {}", sample);
+        count += 1;
+    }
+    if count == 0 {
+        println!("UNSAT");
+    }
+    Ok(count)
+}
+
+// Prints a model found by local search, or UNKNOWN if none is found within the
+// limits
+pub fn read_dimacs_local_search_and_print<P>(path: P, params: &sls::Params, verbosity: usize) -> Result<Option<ast::Asgmt>, String>
+where P: AsRef<path::Path> {
+    let cnf: ast::Cnf = read_dimacs(path, verbosity).ok_or("Error parsing DIMACs file.")?;
+    let asgmt = sls::solve(&cnf, params, verbosity);
+    if let Some(asgmt) = &asgmt {
+        println!("SAT: {}", asgmt);
+    } else {
+        println!("UNKNOWN");
+    }
+    Ok(asgmt)
+}
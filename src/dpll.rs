@@ -1,455 +1,1347 @@
-use std::collections::{HashSet, HashMap};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use itertools::Itertools;
 
 use crate::ast;
+use crate::simplify;
+use crate::util;
+use crate::xor;
 
 
 ////////////////////////////////////////////////////////////////////////////////
 
-enum EvalResult {
-    Sat,
-    Unsat,
-    Unknown,
+// Simplifies the cnf in place, returning the simplifier which extends models of
+// the result to models of the original, or None if it is found UNSAT
+pub fn preprocess(cnf: &mut ast::Cnf, steps: &[simplify::Step], verbosity: usize) -> Option<simplify::Simplifier> {
+    let mut simplifier = simplify::Simplifier::new(cnf, verbosity);
+    simplifier.run(steps);
+    if simplifier.is_unsat() {
+        return None
+    }
+    *cnf = simplifier.cnf();
+    Some(simplifier)
 }
 
-// Assumption: clause is normal
-fn get_literal_when_unit(clause: &ast::Clause, asgmt: &ast::Asgmt) -> Result<ast::Literal, EvalResult> {
-    let mut unit : Option<ast::Literal> = None;
-    for literal in clause.literals() {
-        match asgmt.get(&literal.atom()) {
-            Some(phase) => {
-                if phase == literal.phase() {
-                    return Err(EvalResult::Sat);
-                }
-            },
-            None => match unit {
-                Some(_) => return Err(EvalResult::Unknown),
-                None => unit = Some(literal.clone()),
-            },
+fn jeroslow_wang_score(cnf: &ast::Cnf) -> Vec<ast::Literal> {
+    let mut scores: HashMap<ast::Literal, u64> = HashMap::new();
+    for clause in cnf.clauses() {
+        for literal in clause.literals() {
+            let incr = 2_u64.pow(32 - (clause.len().min(32) as u32));
+            match scores.get_mut(literal) {
+                Some(score) => *score += incr,
+                None => {
+                    scores.insert(*literal, incr);
+                },
+            }
         }
     };
-    unit.ok_or(EvalResult::Unsat)
+    scores.into_iter()
+        .sorted_by(|(lit1, score1), (lit2, score2)| score2.cmp(score1).then(lit1.cmp(lit2)))
+        .map(|p| p.0)
+        .fold(Vec::new(), |mut vec, literal| {
+            if vec.iter().all(|higher_literal: &ast::Literal| literal.atom() != higher_literal.atom()) {
+                vec.push(literal)
+            };
+            vec
+        })
 }
 
-fn try_find_propagate_unit(cnf: &ast::Cnf, asgmt: &mut ast::Asgmt, verbosity: usize) -> Option<ast::Atom> {
-    for clause in cnf.clauses() {
-        if let Ok(literal) = get_literal_when_unit(&clause, asgmt) {
-            let atom = literal.atom();
-            let phase = literal.phase();
-            if 0 < verbosity {
-                log::info!("Unit propagating {}", literal);
-            };
-            asgmt.insert(atom, phase);
-            return Some(atom)
-        }
-    }
-    None
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Why an atom was assigned
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Reason {
+    // Decisions, and units at level zero
+    Decision,
+    // Implied by the clause at this index, in which it is the first literal
+    Clause(usize),
+    // Implied by Gauss-Jordan elimination, explained by a clause stored with the
+    // atom
+    Xor,
+    // Implied by the theory, explained likewise
+    Theory,
 }
 
-fn unit_propagate_all(cnf: &ast::Cnf, asgmt: &mut ast::Asgmt, verbosity: usize) -> HashSet<ast::Atom> {
-    let mut atoms: HashSet<ast::Atom> = HashSet::new();
-    while let Some(atom) = try_find_propagate_unit(cnf, asgmt, verbosity) {
-        atoms.insert(atom);
-    }
-    atoms
+#[derive(Clone, Debug)]
+struct StoredClause {
+    literals: Vec<ast::Literal>,
+    learned: bool,
+    // Literal block distance: the number of decision levels among the literals
+    // when learned
+    lbd: usize,
+    deleted: bool,
+    vivified: bool,
 }
 
-// Assumption: atom is not bound
-// Assumption: cnf is normal
-fn purity(atom: ast::Atom, cnf: &ast::Cnf) -> Option<bool> {
-    let mut occurs_pos = false;
-    let mut occurs_neg = false;
-    for clause in cnf.clauses() {
-        for literal in clause.literals() {
-            if literal.atom() == atom {
-                if literal.phase() {
-                    if occurs_neg {
-                        return None
-                    }
-                    occurs_pos = true;
-                } else {
-                    if occurs_pos {
-                        return None
-                    }
-                    occurs_neg = true;
-                }
-                break;
-            }
-        }
+// Conflicts between restarts are this unit times the Luby sequence, or times
+// a geometric sequence of this ratio
+const RESTART_UNIT: usize = 100;
+const RESTART_RATIO: f64 = 1.5;
+const ACTIVITY_DECAY: f64 = 0.95;
+// Learned clauses with at most this LBD are never deleted
+const GLUE_LBD: usize = 2;
+// Conflicts between inprocessing of the learned clauses
+const INPROCESS_INTERVAL: usize = 5000;
+// Propagations spent on vivification at a restart, relative to those of the
+// search since the last
+const VIVIFY_EFFORT: f64 = 0.1;
+// Propagations spent on probing, relative to the number of clauses and atoms
+const PROBE_EFFORT: usize = 10;
+// Hyper-binary resolvents kept by a round of probing at most
+const MAX_PROBE_RESOLVENTS: usize = 10000;
+
+// The ith element (from 1) of the Luby sequence 1, 1, 2, 1, 1, 2, 4, ...
+fn luby(i: usize) -> usize {
+    let mut k = 1;
+    while (1 << k) - 1 < i {
+        k += 1;
     }
-    if occurs_pos {
-        Some(true)
-    } else if occurs_neg {
-        Some(false)
+    if i == (1 << k) - 1 {
+        1 << (k - 1)
     } else {
-        // If we want to add the assumption that atom is bound, then this should
-        // instead panic (due to assumption violation).
-        None
+        luby(i - (1 << (k - 1)) + 1)
     }
 }
 
-fn try_find_eliminate_pure_literal(cnf: &ast::Cnf, asgmt: &mut ast::Asgmt, verbosity: usize) -> Option<ast::Atom> {
-    let free = cnf.free_atoms(asgmt);
-    for atom in free {
-        if let Some(phase) = purity(atom, cnf) {
-            if 0 < verbosity {
-                log::info!("Atom: {} found to have purity: {}", atom, phase);
-            };
-            asgmt.insert(atom, phase);
-            return Some(atom)
-        }
-    };
-    None
+#[derive(Clone, Copy, Debug)]
+pub enum Restarts {
+    Luby,
+    Geometric,
 }
 
-fn pure_literal_elimination(cnf: &ast::Cnf, asgmt: &mut ast::Asgmt, verbosity: usize) -> HashSet<ast::Atom> {
-    let mut atoms: HashSet<ast::Atom> = HashSet::new();
-    while let Some(atom) = try_find_eliminate_pure_literal(cnf, asgmt, verbosity) {
-        atoms.insert(atom);
+// Heuristic settings of a solver, which the workers of a portfolio vary
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub restarts: Restarts,
+    pub restart_unit: usize,
+    pub activity_decay: f64,
+    // Probability that a decision is on a random atom rather than by activity
+    pub random_decisions: f64,
+    // The initial phase of every atom, or else that of its literal with the
+    // better Jeroslow-Wang score
+    pub initial_phase: Option<bool>,
+    pub seed: u64,
+    // Propagations spent on vivifying learned clauses at each restart, relative
+    // to those of the search since the last, or zero to disable it, and whether
+    // to vivify the original clauses as well
+    pub vivify_effort: f64,
+    pub vivify_irredundant: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            restarts: Restarts::Luby,
+            restart_unit: RESTART_UNIT,
+            activity_decay: ACTIVITY_DECAY,
+            random_decisions: 0.0,
+            initial_phase: None,
+            seed: 1,
+            vivify_effort: VIVIFY_EFFORT,
+            vivify_irredundant: false,
+        }
     }
-    atoms
 }
 
+// Clauses by the id of the solver which learned them, dropped once every
+// solver has imported them
+#[derive(Clone, Debug)]
+pub struct SharedClauses {
+    clauses: VecDeque<(usize, Vec<ast::Literal>)>,
+    // The number of clauses dropped from the front so far
+    dropped: usize,
+    // The number of clauses each solver has imported so far, by id
+    imported: Vec<usize>,
+}
+
+pub type Pool = Arc<Mutex<SharedClauses>>;
 
-fn bool_propagate(cnf: &ast::Cnf, asgmt: &mut ast::Asgmt, verbosity: usize) -> HashSet<ast::Atom> {
-    unit_propagate_all(cnf, asgmt, verbosity)
+impl SharedClauses {
+    // An empty pool for the given number of solvers, whose ids are below it
+    pub fn pool(solvers: usize) -> Pool {
+        Arc::new(Mutex::new(Self { clauses: VecDeque::new(), dropped: 0, imported: vec![0; solvers] }))
+    }
+
+    // The clauses exported by the other solvers since the solver last imported
+    fn import(&mut self, id: usize) -> Vec<Vec<ast::Literal>> {
+        let clauses = self.clauses.range(self.imported[id] - self.dropped..)
+            .filter(|(from, _)| *from != id)
+            .map(|(_, literals)| literals.clone())
+            .collect();
+        self.imported[id] = self.dropped + self.clauses.len();
+        let seen = self.imported.iter().min().unwrap() - self.dropped;
+        self.clauses.drain(..seen);
+        self.dropped += seen;
+        clauses
+    }
 }
 
-fn preprocess(cnf: &ast::Cnf, asgmt: &mut ast::Asgmt, verbosity: usize) {
-    pure_literal_elimination(cnf, asgmt, verbosity);
+// A pool of learned clauses shared between the solvers of a portfolio, along
+// with a flag to stop them all once one finishes. Each solver exports its
+// learned clauses of at most max_len literals, and imports those of the others
+// when it restarts.
+#[derive(Clone, Debug)]
+pub struct Exchange {
+    pool: Pool,
+    stop: Arc<AtomicBool>,
+    id: usize,
+    max_len: usize,
 }
 
-// TODO: consider assignment
-fn jeroslow_wang_score(cnf: &ast::Cnf, _asgmt: &ast::Asgmt) -> Vec<ast::Literal> {
-    let mut scores: HashMap<ast::Literal, u64> = HashMap::new();
-    for clause in cnf.clauses() {
-        for literal in clause.literals() {
-            let incr = (2 as u64).pow(32 - (clause.len() as u32));
-            match scores.get_mut(literal) {
-                Some(score) => *score += incr,
-                None => {
-                    scores.insert(literal.clone(), incr);
-                },
-            }
-        }
-    };
-    scores.into_iter()
-        .sorted_by(|(_, score1), (_, score2)| score2.cmp(score1))
-        .map(|p| p.0)
-        .fold(Vec::new(), |mut vec, literal| {
-            if vec.iter().all(|higher_literal| literal.atom() != higher_literal.atom()) {
-                vec.push(literal)
-            };
-            vec
-        })
+impl Exchange {
+    pub fn new(pool: Pool, stop: Arc<AtomicBool>, id: usize, max_len: usize) -> Self {
+        Self { pool, stop, id, max_len }
+    }
 }
 
-fn jw_choose_literal(asgmt: &mut ast::Asgmt, ranking: &Vec<ast::Literal>) -> ast::Literal {
-    let bound = asgmt.atoms();
-    // TODO: Perhaps we should maintain a slice into rankings where we left off, instead of going through the top choices
-    //   repeatedly after assignment
-    ranking.iter()
-        .filter(|literal| !bound.contains(&literal.atom()))
-        .cloned()
-        .next()
-        .unwrap()
+// The outcome of propagating a theory (or xors) on the current assignment.
+// Explanations are clauses whose literals are all false except for the
+// implied literal (which comes first).
+pub enum Propagation {
+    Implied(Vec<(ast::Literal, Vec<ast::Literal>)>),
+    Conflict(Vec<ast::Literal>),
 }
 
-// Assumption: There exists at least one literal in cnf
-fn _choose_literal(cnf: &ast::Cnf, asgmt: &mut ast::Asgmt) -> ast::Literal {
-    // This is of course the spot to try heuristics. For now, we arbitrarily
-    // choose the first literal we come across.
-    let bound = asgmt.atoms();
-    cnf.clauses()
-        .flat_map(|clause| clause.literals().filter(|literal| !bound.contains(&literal.atom())))
-        .cloned()
-        .next()
-        .unwrap()
+// Constraints beyond the clauses, which the search consults as it goes (as in
+// DPLL(T)). The theory is told of the literals of the trail in order, and of
+// their undoing on backtracking, and may imply literals or report a conflict.
+pub trait Theory {
+    fn assign(&mut self, literal: ast::Literal) -> Propagation;
+
+    // Forgets all but the first so many literals assigned
+    fn backtrack(&mut self, assigned: usize);
+
+    // Called once every atom is assigned, before the model is returned
+    fn final_check(&mut self) -> Option<Vec<ast::Literal>>;
 }
 
-// Assumption: clause has at least one unassigned literal
-fn _get_unassigned_literal(clause: &ast::Clause, asgmt: &ast::Asgmt) -> ast::Literal {
-    clause.unassigned_literals(asgmt).next().unwrap().clone()
+// The theory of plain SAT, which constrains nothing
+#[derive(Clone, Debug, Default)]
+pub struct NoTheory;
+
+impl Theory for NoTheory {
+    fn assign(&mut self, _: ast::Literal) -> Propagation {
+        Propagation::Implied(Vec::new())
+    }
+
+    fn backtrack(&mut self, _: usize) {}
+
+    fn final_check(&mut self) -> Option<Vec<ast::Literal>> {
+        None
+    }
 }
 
+// A conflict-driven clause learning solver. Clauses watch their first two
+// literals, and decisions are made by activity (VSIDS), initially ordered by
+// the Jeroslow-Wang score.
+#[derive(Clone, Debug)]
+pub struct Solver<T = NoTheory> {
+    clauses: Vec<StoredClause>,
+    // Clauses by the index of a literal they watch
+    watches: Vec<Vec<usize>>,
+    gauss: xor::Gauss,
+    theory: T,
+    // The literals of the trail the theory has been told of
+    theory_assigned: usize,
+
+    // Per-atom state, by index
+    values: Vec<Option<bool>>,
+    levels: Vec<usize>,
+    reasons: Vec<Reason>,
+    explanations: Vec<Vec<ast::Literal>>,
+    activity: Vec<f64>,
+    phases: Vec<bool>,
+    // Atoms decided before all others
+    priority: Vec<bool>,
+    known: Vec<bool>,
+    seen: Vec<bool>,
+
+    trail: Vec<ast::Literal>,
+    // Trail positions at which each decision level begins
+    trail_lim: Vec<usize>,
+    propagated: usize,
+    // Literals propagated in total, and when vivification last finished
+    propagations: usize,
+    last_vivified: usize,
+
+    // Unassigned atoms by priority and activity, lazily: entries may be stale
+    heap: BinaryHeap<(bool, u64, usize)>,
+    activity_inc: f64,
 
-struct Watchers<'a> {
-    clauses: HashMap<ast::Literal, HashSet<&'a ast::Clause>>,
-    // Invariant: watchers map is total
-    watchers: HashMap<&'a ast::Clause, (ast::Literal, ast::Literal)>,
+    unsat: bool,
+    conflicts: usize,
+    restarts: usize,
+    learned: usize,
+    max_learned: usize,
+    next_inprocess: usize,
+    config: Config,
+    rng: util::Rng,
+    exchange: Option<Exchange>,
+    // The assumptions of the last call to solve_with which made it UNSAT
+    core: Vec<ast::Literal>,
+    verbosity: usize,
 }
 
-// Assumption: clause has at least two unassigned literals
-fn choose_watched_literals(clause: &ast::Clause, asgmt: &ast::Asgmt) -> (ast::Literal, ast::Literal) {
-    let mut iter = clause.unassigned_literals(asgmt);
-    let lit1 = iter.next().unwrap().inversion();
-    let lit2 = iter.next().unwrap().inversion();
-    (lit1, lit2)
+impl Solver {
+    pub fn new(cnf: &ast::Cnf, verbosity: usize) -> Self {
+        Self::with_theory(cnf, NoTheory, verbosity)
+    }
 }
 
-impl<'a> Watchers<'a> {
-    fn new(cnf: &'a ast::Cnf, asgmt: &ast::Asgmt, verbosity: usize) -> Self {
-        let mut watchers = Self {
-            clauses: HashMap::new(),
-            watchers: HashMap::new(),
+impl<T: Theory> Solver<T> {
+    pub fn with_theory(cnf: &ast::Cnf, theory: T, verbosity: usize) -> Self {
+        let mut solver = Self {
+            clauses: Vec::new(),
+            watches: Vec::new(),
+            gauss: xor::Gauss::default(),
+            theory,
+            theory_assigned: 0,
+            values: Vec::new(),
+            levels: Vec::new(),
+            reasons: Vec::new(),
+            explanations: Vec::new(),
+            activity: Vec::new(),
+            phases: Vec::new(),
+            priority: Vec::new(),
+            known: Vec::new(),
+            seen: Vec::new(),
+            trail: Vec::new(),
+            trail_lim: Vec::new(),
+            propagated: 0,
+            propagations: 0,
+            last_vivified: 0,
+            heap: BinaryHeap::new(),
+            activity_inc: 1.0,
+            unsat: false,
+            conflicts: 0,
+            restarts: 0,
+            learned: 0,
+            max_learned: cnf.len() / 3 + 1000,
+            next_inprocess: INPROCESS_INTERVAL,
+            config: Config::default(),
+            rng: util::Rng::new(Config::default().seed),
+            exchange: None,
+            core: Vec::new(),
+            verbosity,
         };
+        let ranking = jeroslow_wang_score(cnf);
+        for (rank, literal) in ranking.iter().enumerate() {
+            let atom = literal.atom();
+            solver.reserve(atom);
+            solver.activity[atom.index()] = (ranking.len() - rank) as f64 / ranking.len() as f64;
+            solver.phases[atom.index()] = literal.phase();
+        }
         for clause in cnf.clauses() {
-            let (lit1, lit2) = choose_watched_literals(clause, asgmt);
-            watchers.set(lit1, lit2, clause);
-            if 2 < verbosity {
-                log::info!("Watching literal {} and {} for clause {}", lit1, lit2, clause);
-            }
+            solver.add_clause(clause);
+        }
+        for xor in cnf.xors() {
+            solver.add_xor(xor);
+        }
+        solver
+    }
+
+    pub fn theory(&self) -> &T {
+        &self.theory
+    }
+
+    pub fn configure(&mut self, config: Config) {
+        if let Some(phase) = config.initial_phase {
+            self.phases.fill(phase);
+        }
+        self.rng = util::Rng::new(config.seed);
+        self.config = config;
+    }
+
+    pub fn share(&mut self, exchange: Exchange) {
+        self.exchange = Some(exchange);
+    }
+
+    // Adds the clauses exported by the other solvers since the last import
+    fn import(&mut self) {
+        let Some(exchange) = &self.exchange else {
+            return
         };
-        watchers
+        let clauses = exchange.pool.lock().unwrap().import(exchange.id);
+        if 1 < self.verbosity && !clauses.is_empty() {
+            log::info!("Importing {} shared clauses", clauses.len());
+        }
+        for literals in clauses {
+            self.add_clause(&ast::Clause::from(literals));
+        }
     }
 
-    fn clauses(&self, literal: ast::Literal) -> Option<&HashSet<&'a ast::Clause>> {
-        self.clauses.get(&literal)
+    fn export(&self, literals: &[ast::Literal]) {
+        if let Some(exchange) = &self.exchange {
+            if literals.len() <= exchange.max_len {
+                exchange.pool.lock().unwrap().clauses.push_back((exchange.id, literals.to_vec()));
+            }
+        }
     }
 
-    // Dangerous, could be used to violate invariant
-    fn _clauses_mut(&mut self, literal: ast::Literal) -> Option<&mut HashSet<&'a ast::Clause>> {
-        self.clauses.get_mut(&literal)
+    fn stopped(&self) -> bool {
+        self.exchange.as_ref().is_some_and(|exchange| exchange.stop.load(Ordering::Relaxed))
     }
 
-    fn watchers(&self, clause: &'a ast::Clause) -> (ast::Literal, ast::Literal) {
-        self.watchers.get(&clause).unwrap().clone()
+    fn restart_limit(&self) -> usize {
+        match self.config.restarts {
+            Restarts::Luby => self.config.restart_unit * luby(self.restarts + 1),
+            Restarts::Geometric => (self.config.restart_unit as f64 * RESTART_RATIO.powi(self.restarts as i32)) as usize,
+        }
     }
 
-    // Dangerous, could be used to violate invariant
-    fn _watchers_mut(&mut self, clause: &'a ast::Clause) -> &mut (ast::Literal, ast::Literal) {
-        self.watchers.get_mut(&clause).unwrap()
+    // Makes the atom known to the solver, growing the per-atom state as needed
+    fn reserve(&mut self, atom: ast::Atom) {
+        let index = atom.index();
+        if self.values.len() <= index {
+            self.values.resize(index + 1, None);
+            self.levels.resize(index + 1, 0);
+            self.reasons.resize(index + 1, Reason::Decision);
+            self.explanations.resize(index + 1, Vec::new());
+            self.activity.resize(index + 1, 0.0);
+            self.phases.resize(index + 1, false);
+            self.priority.resize(index + 1, false);
+            self.known.resize(index + 1, false);
+            self.seen.resize(index + 1, false);
+            self.watches.resize(2 * (index + 1), Vec::new());
+        }
+        if !self.known[index] {
+            self.known[index] = true;
+            self.heap.push(self.heap_entry(index));
+        }
     }
 
-    fn set(&mut self, lit1: ast::Literal, lit2: ast::Literal, clause: &'a ast::Clause) {
-        match self.clauses.get_mut(&lit1) {
-            Some(clauses) => {
-                clauses.insert(clause);
-            },
-            None => {
-                self.clauses.insert(lit1, HashSet::from([clause]));
-            },
-        };
-        match self.clauses.get_mut(&lit2) {
-            Some(clauses) => {
-                clauses.insert(clause);
-            },
-            None => {
-                self.clauses.insert(lit2, HashSet::from([clause]));
+    fn heap_entry(&self, index: usize) -> (bool, u64, usize) {
+        (self.priority[index], self.activity[index].to_bits(), index)
+    }
+
+    fn value(&self, literal: ast::Literal) -> Option<bool> {
+        self.values[literal.atom().index()].map(|phase| phase == literal.phase())
+    }
+
+    fn level(&self, literal: ast::Literal) -> usize {
+        self.levels[literal.atom().index()]
+    }
+
+    fn decision_level(&self) -> usize {
+        self.trail_lim.len()
+    }
+
+    fn enqueue(&mut self, literal: ast::Literal, reason: Reason) {
+        let index = literal.atom().index();
+        self.values[index] = Some(literal.phase());
+        self.levels[index] = self.decision_level();
+        self.reasons[index] = reason;
+        self.trail.push(literal);
+        self.gauss.touch(literal.atom());
+    }
+
+    // The clause which implied the (assigned) atom, with the implied literal
+    // first
+    fn reason_literals(&self, atom: ast::Atom) -> &[ast::Literal] {
+        match self.reasons[atom.index()] {
+            Reason::Decision => &[],
+            Reason::Clause(i) => &self.clauses[i].literals,
+            Reason::Xor | Reason::Theory => &self.explanations[atom.index()],
+        }
+    }
+
+    // Adds a clause, which may mention new atoms. Returns false if the solver is
+    // now known to be UNSAT.
+    pub fn add_clause(&mut self, clause: &ast::Clause) -> bool {
+        self.backtrack(0);
+        if self.unsat {
+            return false
+        }
+        let mut clause = clause.clone();
+        let tautology = !clause.normalize();
+        let mut literals: Vec<ast::Literal> = clause.into_iter().collect();
+        for literal in literals.iter() {
+            self.reserve(literal.atom());
+        }
+        if tautology {
+            return true
+        }
+        if literals.iter().any(|&lit| self.value(lit) == Some(true)) {
+            return true
+        }
+        literals.retain(|&lit| self.value(lit).is_none());
+        match literals.len() {
+            0 => {
+                self.unsat = true;
+                false
             },
-        };
-        match self.watchers.get_mut(clause) {
-            Some(literals) => {
-                *literals = (lit1, lit2);
+            1 => {
+                self.enqueue(literals[0], Reason::Decision);
+                true
             },
-            None => {
-                self.watchers.insert(clause, (lit1, lit2));
+            _ => {
+                self.store_clause(literals, false, 0);
+                true
             },
         }
     }
 
-    fn replace(&mut self, current: ast::Literal, new: ast::Literal, clause: &'a ast::Clause) {
-        if let Some(clauses) = self.clauses.get_mut(&current) {
-            clauses.remove(clause);
-        };
-        match self.clauses.get_mut(&new) {
-            Some(clauses) => {
-                clauses.insert(clause);
-            },
-            None => {
-                self.clauses.insert(new, HashSet::from([clause]));
-            },
-        };
-        match self.watchers.get_mut(clause) {
-            Some((lit1, lit2)) => {
-                if *lit1 == current {
-                    *lit1 = new;
-                } else if *lit2 == current {
-                    *lit2 = new;
-                } else {
-                    panic!()
+    pub fn add_xor(&mut self, xor: &ast::Xor) -> bool {
+        self.backtrack(0);
+        for &atom in xor.atoms() {
+            self.reserve(atom);
+        }
+        self.gauss.add(xor);
+        !self.unsat
+    }
+
+    // Assumption: the first two literals are unassigned, or the first is the
+    // implied literal of a learned clause and the second has the highest level
+    // of the rest
+    fn store_clause(&mut self, literals: Vec<ast::Literal>, learned: bool, lbd: usize) -> usize {
+        let index = self.clauses.len();
+        self.watches[literals[0].index()].push(index);
+        self.watches[literals[1].index()].push(index);
+        self.clauses.push(StoredClause { literals, learned, lbd, deleted: false, vivified: false });
+        if learned {
+            self.learned += 1;
+        }
+        index
+    }
+
+    // Returns a conflicting clause (all literals false) if one is found
+    fn propagate(&mut self) -> Option<Vec<ast::Literal>> {
+        loop {
+            while self.propagated < self.trail.len() {
+                let literal = self.trail[self.propagated];
+                self.propagated += 1;
+                self.propagations += 1;
+                if let Some(conflict) = self.propagate_literal(literal) {
+                    return Some(conflict)
                 }
-            },
-            None => {
-                panic!()
-            },
+            }
+            if !self.gauss.is_empty() {
+                match self.gauss.propagate(&self.values) {
+                    Propagation::Conflict(explanation) => {
+                        if 2 < self.verbosity {
+                            log::info!("Xor conflict: {}", ast::Clause::from(explanation.clone()));
+                        }
+                        return Some(explanation)
+                    },
+                    Propagation::Implied(implied) => {
+                        for (literal, explanation) in implied.iter() {
+                            if 2 < self.verbosity {
+                                log::info!("Unit propagating {} (by xor)", literal);
+                            }
+                            self.explanations[literal.atom().index()] = explanation.clone();
+                            self.enqueue(*literal, Reason::Xor);
+                        }
+                        if !implied.is_empty() {
+                            continue
+                        }
+                    },
+                }
+            }
+            let assigned = self.trail.len();
+            if let Some(conflict) = self.propagate_theory() {
+                return Some(conflict)
+            }
+            if self.trail.len() == assigned {
+                return None
+            }
         }
     }
-}
 
+    // Tells the theory of the literals of the trail it has not yet seen,
+    // returning a conflicting clause if one is found
+    fn propagate_theory(&mut self) -> Option<Vec<ast::Literal>> {
+        while self.theory_assigned < self.trail.len() {
+            let literal = self.trail[self.theory_assigned];
+            self.theory_assigned += 1;
+            let implied = match self.theory.assign(literal) {
+                Propagation::Conflict(explanation) => {
+                    if 2 < self.verbosity {
+                        log::info!("Theory conflict: {}", ast::Clause::from(explanation.clone()));
+                    }
+                    return Some(explanation)
+                },
+                Propagation::Implied(implied) => implied,
+            };
+            for (literal, explanation) in implied {
+                self.reserve(literal.atom());
+                match self.value(literal) {
+                    Some(true) => continue,
+                    // All of the explanation is false then
+                    Some(false) => return Some(explanation),
+                    None => (),
+                }
+                if 2 < self.verbosity {
+                    log::info!("Unit propagating {} (by theory)", literal);
+                }
+                self.explanations[literal.atom().index()] = explanation;
+                self.enqueue(literal, Reason::Theory);
+            }
+        }
+        None
+    }
+
+    // Visits the clauses watching the inversion of the newly true literal
+    fn propagate_literal(&mut self, literal: ast::Literal) -> Option<Vec<ast::Literal>> {
+        let false_lit = literal.inversion();
+        let watching = std::mem::take(&mut self.watches[false_lit.index()]);
+        let mut kept = Vec::with_capacity(watching.len());
+        let mut conflict = None;
+        let mut iter = watching.into_iter();
+        for index in iter.by_ref() {
+            if self.clauses[index].deleted {
+                continue
+            }
+            let literals = &mut self.clauses[index].literals;
+            if literals[0] == false_lit {
+                literals.swap(0, 1);
+            }
+            let first = literals[0];
+            if self.values[first.atom().index()] == Some(first.phase()) {
+                kept.push(index);
+                continue
+            }
+            let replacement = (2..literals.len()).find(|&k| {
+                let lit = literals[k];
+                self.values[lit.atom().index()] != Some(!lit.phase())
+            });
+            match replacement {
+                Some(k) => {
+                    literals.swap(1, k);
+                    let new_watch = literals[1];
+                    if 3 < self.verbosity {
+                        log::info!("Replacing watcher {} with {} (in clause {})", false_lit, new_watch, index);
+                    }
+                    self.watches[new_watch.index()].push(index);
+                },
+                None => {
+                    kept.push(index);
+                    if self.value(first) == Some(false) {
+                        conflict = Some(self.clauses[index].literals.clone());
+                        break
+                    }
+                    if 2 < self.verbosity {
+                        log::info!("Unit propagating {} (by clause {})", first, ast::Clause::from(self.clauses[index].literals.clone()));
+                    }
+                    self.enqueue(first, Reason::Clause(index));
+                },
+            }
+        }
+        kept.extend(iter);
+        self.watches[false_lit.index()] = kept;
+        conflict
+    }
 
-// true means that unsat clause was found
-fn propagate_with_watcher(_cnf: &ast::Cnf, asgmt: &mut ast::Asgmt, literal: ast::Literal, watchers: &mut Watchers, verbosity: usize)
-    -> (HashSet<ast::Atom>, bool)
-{
-    let mut acc = HashSet::new();
-    let mut new_lits: HashSet::<ast::Literal> = HashSet::from([literal]);
+    fn backtrack(&mut self, level: usize) {
+        if self.decision_level() <= level {
+            return
+        }
+        let start = self.trail_lim[level];
+        let mut trail = std::mem::take(&mut self.trail);
+        for literal in trail.drain(start..) {
+            let index = literal.atom().index();
+            self.values[index] = None;
+            self.phases[index] = literal.phase();
+            self.heap.push(self.heap_entry(index));
+        }
+        self.trail = trail;
+        self.trail_lim.truncate(level);
+        self.propagated = self.trail.len();
+        self.gauss.touch_all();
+        if self.trail.len() < self.theory_assigned {
+            self.theory_assigned = self.trail.len();
+            self.theory.backtrack(self.theory_assigned);
+        }
+        // Drop the stale entries once they dominate the heap
+        if 1000 + 10 * self.values.len() < self.heap.len() {
+            self.rebuild_heap();
+        }
+    }
 
-    loop {
-        if new_lits.is_empty() {
-            return (acc, false);
+    fn bump(&mut self, atom: ast::Atom) {
+        let index = atom.index();
+        self.activity[index] += self.activity_inc;
+        if 1e100 < self.activity[index] {
+            for activity in self.activity.iter_mut() {
+                *activity *= 1e-100;
+            }
+            self.activity_inc *= 1e-100;
+            self.rebuild_heap();
+        } else if self.values[index].is_none() {
+            self.heap.push(self.heap_entry(index));
         }
+    }
 
-        acc.extend(new_lits.iter().map(|lit| lit.atom()));
-        let seen_clauses: Vec<_> = new_lits.into_iter()
-            .flat_map(|lit|
-                match watchers.clauses(lit.clone()) {
-                    Some(clauses) => {
-                        itertools::Either::Left(std::iter::zip(std::iter::repeat(lit), clauses.iter()))
-                    },
-                    None => itertools::Either::Right(std::iter::empty()),
-                })
-            .map(|(literal, &clause)| (literal, clause))
+    fn rebuild_heap(&mut self) {
+        self.heap = (0..self.values.len())
+            .filter(|&index| self.known[index] && self.values[index].is_none())
+            .map(|index| self.heap_entry(index))
             .collect();
-        new_lits = HashSet::new();
+    }
 
-        for (watched_lit, clause) in seen_clauses {
-            if 3 < verbosity {
-                log::info!("Considering clause {}, watched by {}", clause, watched_lit)
+    // First UIP conflict analysis. Returns the learned clause, with the asserting
+    // literal first and a literal of the backjump level second, and the backjump
+    // level.
+    // Assumption: some literal of the conflict is at the current decision level
+    fn analyze(&mut self, conflict: Vec<ast::Literal>) -> (Vec<ast::Literal>, usize) {
+        let level = self.decision_level();
+        let mut learned = vec![conflict[0]];
+        let mut pending = 0;
+        let mut reason = conflict;
+        let mut index = self.trail.len();
+        let uip = loop {
+            for &literal in reason.iter() {
+                let atom = literal.atom();
+                if self.seen[atom.index()] || self.level(literal) == 0 {
+                    continue
+                }
+                self.seen[atom.index()] = true;
+                self.bump(atom);
+                if self.level(literal) == level {
+                    pending += 1;
+                } else {
+                    learned.push(literal);
+                }
+            }
+            let literal = loop {
+                index -= 1;
+                if self.seen[self.trail[index].atom().index()] {
+                    break self.trail[index]
+                }
             };
-            match get_literal_when_unit(clause, asgmt) {
-                Ok(lit) => {
-                    let atom = lit.atom();
-                    asgmt.insert(atom, lit.phase());
-                    if 1 < verbosity {
-                        log::info!("Unit propagating {} (by clause {})", lit, clause)
-                    } else if 0 < verbosity {
-                        log::info!("Unit propagating {}", lit)
-                    };
-                    new_lits.insert(lit);
+            self.seen[literal.atom().index()] = false;
+            pending -= 1;
+            if pending == 0 {
+                break literal
+            }
+            // The implied literal comes first, and was marked seen already
+            reason = self.reason_literals(literal.atom())[1..].to_vec();
+        };
+        learned[0] = uip.inversion();
+
+        // Drop literals implied by other literals of the clause
+        let marked: Vec<ast::Literal> = learned[1..].to_vec();
+        learned.retain(|&literal| {
+            literal == uip.inversion()
+                || self.reasons[literal.atom().index()] == Reason::Decision
+                || self.reason_literals(literal.atom())[1..].iter()
+                    .any(|&lit| !self.seen[lit.atom().index()] && 0 < self.level(lit))
+        });
+        for literal in marked {
+            self.seen[literal.atom().index()] = false;
+        }
+
+        let mut backjump = 0;
+        if 1 < learned.len() {
+            let (position, _) = learned.iter().enumerate().skip(1).max_by_key(|(_, &lit)| self.level(lit)).unwrap();
+            learned.swap(1, position);
+            backjump = self.level(learned[1]);
+        }
+        (learned, backjump)
+    }
+
+    fn lbd(&self, literals: &[ast::Literal]) -> usize {
+        literals.iter().map(|&lit| self.level(lit)).unique().count()
+    }
+
+    // Deletes the less useful half of the learned clauses
+    fn reduce(&mut self) {
+        let locked = |solver: &Self, index: usize| {
+            let first = solver.clauses[index].literals[0];
+            solver.value(first) == Some(true) && solver.reasons[first.atom().index()] == Reason::Clause(index)
+        };
+        let candidates: Vec<usize> = (0..self.clauses.len())
+            .filter(|&i| {
+                let clause = &self.clauses[i];
+                clause.learned && !clause.deleted && GLUE_LBD < clause.lbd && !locked(self, i)
+            })
+            .sorted_by_key(|&i| std::cmp::Reverse((self.clauses[i].lbd, self.clauses[i].literals.len())))
+            .collect();
+        let deleted = candidates.len() / 2;
+        for &i in candidates.iter().take(deleted) {
+            self.delete(i);
+        }
+        if 0 < self.verbosity {
+            log::info!("Deleted {} learned clauses", deleted);
+        }
+    }
+
+    fn delete(&mut self, index: usize) {
+        if self.clauses[index].learned {
+            self.learned -= 1;
+        }
+        self.clauses[index].deleted = true;
+        self.clauses[index].literals = Vec::new();
+    }
+
+    // Assignments at level 0 are permanent, so no longer need reasons
+    // Assumption: at decision level 0
+    fn forget_root_reasons(&mut self) {
+        for index in 0..self.trail.len() {
+            self.reasons[self.trail[index].atom().index()] = Reason::Decision;
+        }
+    }
+
+    // Simplifies the clauses by the units, removes learned clauses subsumed by
+    // others, and strengthens learned clauses by self-subsuming resolution.
+    // Each clause is checked against the learned clauses containing its least
+    // frequent atom, whose signatures allow it.
+    // Assumption: at decision level 0, after propagation
+    fn inprocess(&mut self) {
+        self.forget_root_reasons();
+        for index in 0..self.clauses.len() {
+            if self.clauses[index].deleted {
+                continue
+            }
+            if self.clauses[index].literals.iter().any(|&lit| self.value(lit) == Some(true)) {
+                self.delete(index);
+                continue
+            }
+            let values = &self.values;
+            self.clauses[index].literals.retain(|lit| values[lit.atom().index()].is_none());
+        }
+        let learned: Vec<usize> = (0..self.clauses.len())
+            .filter(|&index| self.clauses[index].learned && !self.clauses[index].deleted)
+            .collect();
+        let mut signatures = vec![0; self.clauses.len()];
+        let mut occurs: Vec<Vec<usize>> = vec![Vec::new(); self.values.len()];
+        for (index, clause) in self.clauses.iter_mut().enumerate() {
+            if !clause.deleted {
+                clause.literals.sort_by_key(|lit| (lit.atom(), lit.phase()));
+                signatures[index] = simplify::signature(&clause.literals);
+            }
+        }
+        for &index in learned.iter() {
+            for literal in self.clauses[index].literals.iter() {
+                occurs[literal.atom().index()].push(index);
+            }
+        }
+        let (mut subsumed, mut strengthened) = (0, 0);
+        let order: Vec<usize> = (0..self.clauses.len())
+            .filter(|&index| !self.clauses[index].deleted)
+            .sorted_by_key(|&index| self.clauses[index].literals.len())
+            .collect();
+        for index in order {
+            if self.clauses[index].deleted || self.clauses[index].literals.len() < 2 {
+                continue
+            }
+            let atom = self.clauses[index].literals.iter()
+                .map(|lit| lit.atom())
+                .min_by_key(|atom| occurs[atom.index()].len())
+                .unwrap();
+            for &other in occurs[atom.index()].iter() {
+                if other == index || self.clauses[other].deleted || signatures[index] & !signatures[other] != 0 {
+                    continue
+                }
+                match simplify::subsumes(&self.clauses[index].literals, &self.clauses[other].literals) {
+                    None => (),
+                    Some(None) => {
+                        self.delete(other);
+                        subsumed += 1;
+                    },
+                    Some(Some(flipped)) => {
+                        let clause = &mut self.clauses[other];
+                        clause.literals.retain(|&lit| lit != flipped);
+                        clause.lbd = clause.lbd.min(clause.literals.len());
+                        signatures[other] = simplify::signature(&clause.literals);
+                        strengthened += 1;
+                    },
+                }
+            }
+        }
+        // Clauses may have lost their watched literals, so watch them afresh
+        for watches in self.watches.iter_mut() {
+            watches.clear();
+        }
+        for index in 0..self.clauses.len() {
+            if self.clauses[index].deleted {
+                continue
+            }
+            match self.clauses[index].literals[..] {
+                [] => self.unsat = true,
+                [literal] => {
+                    self.delete(index);
+                    if self.value(literal).is_none() {
+                        self.enqueue(literal, Reason::Decision);
+                    }
                 },
-                Err(EvalResult::Sat) => continue,
-                Err(EvalResult::Unsat) => {
-                    if 3 < verbosity {
-                        log::info!("Clause {} is false!", clause);
-                        log::info!("Assignment: {}", asgmt)
-                    };
-                    acc.extend(new_lits.iter().map(|lit| lit.atom()));
-                    return (acc, true)
+                [first, second, ..] => {
+                    self.watches[first.index()].push(index);
+                    self.watches[second.index()].push(index);
                 },
-                // We always need two unassigned watchers, so since this watcher was
-                // assigned but the clause was not a unit, we need to replace it
-                // with a new watcher (one is guaranteed to exist since the clause
-                // is not a unit and not unsat).
-                Err(EvalResult::Unknown) => {
-                    match clause.unassigned_literals(asgmt)
-                        .map(|lit| lit.inversion())
-                        .filter(|&lit| {
-                            let (lit1, lit2) = watchers.watchers(clause);
-                            lit != lit1 && lit != lit2
-                        })
-                        .next()
-                    {
-                        Some(new_watcher) => {
-                            watchers.replace(watched_lit.clone(), new_watcher, clause);
-                            if 2 < verbosity {
-                                log::info!("Replacing watcher {} with {} (in clause {})", watched_lit, new_watcher, clause);
-                            }
-                        },
-                        None => {
-                            // Should be unreachable
-                            panic!()
-                        },
+            }
+        }
+        if 0 < self.verbosity {
+            log::info!("Inprocessing removed {} subsumed clauses and strengthened {}", subsumed, strengthened);
+        }
+    }
+
+    // Vivifies the clause: its literals are assumed false in turn and
+    // propagated, until a conflict or a true literal shows that the literals so
+    // far suffice, while literals found false are dropped. The clause itself
+    // takes part, which keeps this sound though it then only implies its own
+    // last literal. Returns the number of literals removed.
+    // Assumption: at decision level 0, after propagation
+    fn vivify_clause(&mut self, index: usize) -> usize {
+        self.clauses[index].vivified = true;
+        if self.clauses[index].literals.iter().any(|&lit| self.value(lit) == Some(true)) {
+            self.delete(index);
+            return 0
+        }
+        let literals = self.clauses[index].literals.clone();
+        let mut vivified = Vec::new();
+        for &literal in literals.iter() {
+            match self.value(literal) {
+                Some(false) => continue,
+                Some(true) => {
+                    vivified.push(literal);
+                    break
+                },
+                None => {
+                    vivified.push(literal);
+                    self.trail_lim.push(self.trail.len());
+                    self.enqueue(literal.inversion(), Reason::Decision);
+                    if self.propagate().is_some() {
+                        break
                     }
                 },
             }
-        };
+        }
+        self.backtrack(0);
+        let removed = literals.len() - vivified.len();
+        if removed == 0 {
+            return 0
+        }
+        if 2 < self.verbosity {
+            log::info!("Vivified clause {} to {}", ast::Clause::from(literals), ast::Clause::from(vivified.clone()));
+        }
+        // Propagation may have moved the watches of the clause
+        let watched = [self.clauses[index].literals[0], self.clauses[index].literals[1]];
+        for literal in watched {
+            self.watches[literal.index()].retain(|&other| other != index);
+        }
+        match vivified[..] {
+            [] => self.unsat = true,
+            [literal] => {
+                self.delete(index);
+                self.enqueue(literal, Reason::Decision);
+                if self.propagate().is_some() {
+                    self.unsat = true;
+                }
+            },
+            [first, second, ..] => {
+                self.watches[first.index()].push(index);
+                self.watches[second.index()].push(index);
+                let clause = &mut self.clauses[index];
+                clause.lbd = clause.lbd.min(vivified.len());
+                clause.literals = vivified;
+            },
+        }
+        removed
     }
-}
 
-// Returns true if successfully backtracked. If false, the CNF is UNSAT (because we backtracked to zero assumptions).
-fn dpll_backtrack(
-    cnf: &ast::Cnf,
-    asgmt: &mut ast::Asgmt,
-    stack: &mut Vec<(ast::Literal, HashSet<ast::Atom>)>,
-    watchers: &mut Watchers,
-    falsified: &mut bool,
-    verbosity: usize)
-    -> bool
-{
-    match stack.pop() {
-        None => return false,
-        Some((assumed, consquences)) => {
-            if 0 < verbosity {
-                log::info!("Assumption {} failed, assuming its inverse", assumed);
-            }
-            // Note: there is not a good way to log when an assumption
-            // fails in both directions, as this is implicit; the second
-            // assignment is treated as a consquence like the propagate
-            // variables.
-            for new in consquences {
-                if 3 < verbosity {
-                    log::info!("Removing consequent {}", new);
-                };
-                asgmt.remove(&new);
-            };
-            if 3 < verbosity {
-                log::info!("Assignment after rolling back changes and assuming inverse: {}", asgmt);
-            }
-            let assumed_atom = assumed.atom();
-            asgmt.insert(assumed_atom, !assumed.phase());
-
-            let (prop_consequences, prop_falsified) =
-                propagate_with_watcher(cnf, asgmt, assumed.inversion(), watchers, verbosity);
-            *falsified = prop_falsified;
-            if let Some((_, prev_consequences)) = stack.last_mut() {
-                prev_consequences.insert(assumed_atom);
-                prev_consequences.extend(prop_consequences)
+    // Vivifies the learned clauses not yet vivified, those of lowest LBD
+    // first, and optionally the original ones after them, until the
+    // propagation budget is spent
+    // Assumption: at decision level 0, after propagation
+    fn vivify(&mut self) {
+        self.forget_root_reasons();
+        let candidates: Vec<usize> = (0..self.clauses.len())
+            .filter(|&index| {
+                let clause = &self.clauses[index];
+                !clause.deleted && !clause.vivified && (clause.learned || self.config.vivify_irredundant)
+            })
+            .sorted_by_key(|&index| {
+                let clause = &self.clauses[index];
+                (!clause.learned, clause.lbd, clause.literals.len())
+            })
+            .collect();
+        if candidates.is_empty() {
+            self.last_vivified = self.propagations;
+            return
+        }
+        // Vivification would otherwise overwrite the saved phases
+        let phases = self.phases.clone();
+        let searched = self.propagations - self.last_vivified;
+        let budget = self.propagations + (self.config.vivify_effort * searched as f64) as usize;
+        let (mut vivified, mut strengthened, mut removed) = (0, 0, 0);
+        for index in candidates {
+            if self.unsat || budget <= self.propagations {
+                break
+            }
+            vivified += 1;
+            let literals = self.vivify_clause(index);
+            if 0 < literals {
+                strengthened += 1;
+                removed += literals;
+            }
+        }
+        self.phases = phases;
+        self.last_vivified = self.propagations;
+        if 1 < self.verbosity {
+            log::info!("Vivified {} clauses, strengthening {} by {} literals", vivified, strengthened, removed);
+        }
+    }
+
+    // The hyper-binary resolvents of the implications of the probe at level 1,
+    // whose trail begins at the position. Literals implied by a binary clause
+    // form a tree under the probe; a literal implied by a longer clause is
+    // implied as well by the lowest common ancestor (the dominator) of its
+    // antecedents, by a resolvent of the clauses along the way.
+    fn hyper_binary_resolvents(&self, start: usize) -> Vec<ast::Clause> {
+        let root = self.trail[start];
+        // Parent and depth in the tree, by atom
+        let mut tree: HashMap<ast::Atom, (ast::Literal, usize)> = HashMap::new();
+        tree.insert(root.atom(), (root, 0));
+        let ancestor = |tree: &HashMap<ast::Atom, (ast::Literal, usize)>, mut a: ast::Literal, mut b: ast::Literal| {
+            while a != b {
+                let (parent_a, depth_a) = tree[&a.atom()];
+                let (parent_b, depth_b) = tree[&b.atom()];
+                if depth_b <= depth_a {
+                    a = parent_a;
+                }
+                if depth_a <= depth_b {
+                    b = parent_b;
+                }
+            }
+            a
+        };
+        let mut resolvents = Vec::new();
+        for &literal in self.trail[start + 1..].iter() {
+            let reason = self.reason_literals(literal.atom());
+            let antecedents: Vec<ast::Literal> = reason.iter().skip(1)
+                .filter(|&&lit| 0 < self.level(lit))
+                .map(|lit| lit.inversion())
+                .collect();
+            let parent = match antecedents[..] {
+                [] => root,
+                [antecedent] => antecedent,
+                [first, ref rest @ ..] => {
+                    let dominator = rest.iter().fold(first, |a, &b| ancestor(&tree, a, b));
+                    resolvents.push(ast::Clause::from(vec![dominator.inversion(), literal]));
+                    dominator
+                },
             };
-            return true
-        },
+            let depth = tree[&parent.atom()].1 + 1;
+            tree.insert(literal.atom(), (parent, depth));
+        }
+        resolvents
     }
-}
 
-fn dpll(cnf: &ast::Cnf, asgmt: &mut ast::Asgmt, verbosity: usize) -> bool {
-    let mut stack: Vec<(ast::Literal, HashSet<ast::Atom>)> = Vec::new();
-    let mut falsified = false;
+    // Failed literal probing: assigns each literal in turn at level 1 and
+    // propagates it, and if that leads to a conflict, fixes its inversion at
+    // level 0. Adds the hyper-binary resolvents of the implications of the
+    // probes, up to a limit. Stops once the propagation budget is spent.
+    // Returns the literals fixed at level 0 and the resolvents, or None if the
+    // solver is UNSAT.
+    pub fn probe(&mut self) -> Option<(Vec<ast::Literal>, Vec<ast::Clause>)> {
+        self.backtrack(0);
+        if self.unsat || self.propagate().is_some() {
+            self.unsat = true;
+            return None
+        }
+        let budget = self.propagations + PROBE_EFFORT * (self.clauses.len() + self.values.len());
+        let mut failed = 0;
+        let mut resolvents = Vec::new();
+        'probing: for index in 0..self.values.len() {
+            for phase in [true, false] {
+                if budget <= self.propagations {
+                    if 0 < self.verbosity {
+                        log::info!("Probing spent its budget after {} of {} atoms", index, self.values.len());
+                    }
+                    break 'probing
+                }
+                if !self.known[index] || self.values[index].is_some() {
+                    break
+                }
+                let probe = ast::Literal::new(phase, ast::Atom::new(index as u32));
+                let start = self.trail.len();
+                self.trail_lim.push(start);
+                self.enqueue(probe, Reason::Decision);
+                let conflict = self.propagate();
+                if conflict.is_none() && resolvents.len() < MAX_PROBE_RESOLVENTS {
+                    resolvents.extend(self.hyper_binary_resolvents(start));
+                }
+                self.backtrack(0);
+                if conflict.is_some() {
+                    if 2 < self.verbosity {
+                        log::info!("Failed literal {}", probe);
+                    }
+                    failed += 1;
+                    self.enqueue(probe.inversion(), Reason::Decision);
+                    if self.propagate().is_some() {
+                        self.unsat = true;
+                        return None
+                    }
+                }
+            }
+        }
+        let resolvents: Vec<ast::Clause> = resolvents.into_iter()
+            .filter(|clause| clause.literals().all(|&lit| self.value(lit).is_none()))
+            .map(|mut clause| {
+                clause.normalize();
+                clause
+            })
+            .unique()
+            .take(MAX_PROBE_RESOLVENTS)
+            .collect();
+        for clause in resolvents.iter() {
+            self.add_clause(clause);
+        }
+        if 0 < self.verbosity {
+            log::info!("Probing found {} failed literals and {} hyper-binary resolvents", failed, resolvents.len());
+        }
+        Some((self.trail.clone(), resolvents))
+    }
 
-    preprocess(cnf, asgmt, verbosity);
-    bool_propagate(cnf, asgmt, verbosity);
+    // Returns false if the solver is UNSAT
+    fn handle_conflict(&mut self, conflict: Vec<ast::Literal>) -> bool {
+        self.conflicts += 1;
+        let max_level = conflict.iter().map(|&lit| self.level(lit)).max().unwrap_or(0);
+        if max_level == 0 {
+            return false
+        }
+        // Conflicts from elimination may only involve earlier levels
+        self.backtrack(max_level);
+        let (learned, backjump) = self.analyze(conflict);
+        if 1 < self.verbosity {
+            log::info!("Learned clause {}, backjumping to level {}", ast::Clause::from(learned.clone()), backjump);
+        }
+        self.backtrack(backjump);
+        self.export(&learned);
+        if learned.len() == 1 {
+            self.enqueue(learned[0], Reason::Decision);
+        } else {
+            let lbd = self.lbd(&learned);
+            let asserting = learned[0];
+            let index = self.store_clause(learned, true, lbd);
+            self.enqueue(asserting, Reason::Clause(index));
+        }
+        self.activity_inc /= self.config.activity_decay;
+        true
+    }
 
-    let ranking = jeroslow_wang_score(cnf, asgmt);
+    fn decide(&mut self) -> Option<ast::Literal> {
+        if 0.0 < self.config.random_decisions && self.rng.next_f64() < self.config.random_decisions {
+            let index = self.rng.below(self.values.len().max(1));
+            if index < self.values.len() && self.known[index] && self.values[index].is_none() {
+                return Some(ast::Literal::new(self.phases[index], ast::Atom::new(index as u32)))
+            }
+        }
+        while let Some(key @ (_, _, index)) = self.heap.pop() {
+            if self.values[index].is_none() && key == self.heap_entry(index) {
+                return Some(ast::Literal::new(self.phases[index], ast::Atom::new(index as u32)))
+            }
+        }
+        None
+    }
 
-    let mut watchers = Watchers::new(cnf, asgmt, verbosity);
+    fn model(&self) -> ast::Asgmt {
+        let mut asgmt = ast::Asgmt::new();
+        for (index, value) in self.values.iter().enumerate() {
+            if let (true, Some(phase)) = (self.known[index], value) {
+                asgmt.insert(ast::Atom::new(index as u32), *phase);
+            }
+        }
+        asgmt
+    }
 
-    loop {
-        if falsified {
-            falsified = false;
-            if !dpll_backtrack(cnf, asgmt, &mut stack, &mut watchers, &mut falsified, verbosity) {
-                return false
-            };
-            continue
-        } else if let Some(phase) = cnf.eval(asgmt) {
-            if phase {
-                return true
-            };
-            if !dpll_backtrack(cnf, asgmt, &mut stack, &mut watchers, &mut falsified, verbosity) {
-                return false
-            };
-            continue
+    // Makes the solver decide the given atoms before any others
+    pub fn prioritize(&mut self, atoms: &[ast::Atom]) {
+        for &atom in atoms {
+            self.reserve(atom);
+            self.priority[atom.index()] = true;
+        }
+        self.rebuild_heap();
+    }
+
+    // Returns a clause ruling out the projection of the model just found onto
+    // the given atoms, and nothing else. When the projected atoms were decided
+    // first, this is the negation of the decisions up to the last level
+    // assigning one of them, since propagation determined the rest.
+    // Assumption: solve has just returned a model
+    pub fn blocking_clause(&self, projection: &[ast::Atom]) -> ast::Clause {
+        let last_level = projection.iter()
+            .map(|atom| self.levels[atom.index()])
+            .max()
+            .unwrap_or(0);
+        let decisions: Vec<ast::Literal> = self.trail_lim[..last_level].iter()
+            .map(|&position| self.trail[position])
+            .collect();
+        let literals: Vec<ast::Literal> = if decisions.iter().all(|lit| self.priority[lit.atom().index()]) {
+            decisions
+        } else {
+            projection.iter()
+                .map(|&atom| ast::Literal::new(self.values[atom.index()].unwrap(), atom))
+                .collect()
         };
+        ast::Clause::from(literals.into_iter().map(|lit| lit.inversion()).collect::<Vec<_>>())
+    }
 
-        let literal = jw_choose_literal(asgmt, &ranking);
-        let atom = literal.atom();
-        let phase = literal.phase();
+    // The assumptions which imply the inversion of the (false) assumption, found
+    // by following the reasons back along the trail to the decisions, which
+    // are all assumptions, along with the assumption itself
+    fn analyze_final(&mut self, literal: ast::Literal) -> Vec<ast::Literal> {
+        let mut core = vec![literal];
+        if self.level(literal) == 0 {
+            return core
+        }
+        self.seen[literal.atom().index()] = true;
+        for position in (self.trail_lim[0]..self.trail.len()).rev() {
+            let atom = self.trail[position].atom();
+            if !self.seen[atom.index()] {
+                continue
+            }
+            self.seen[atom.index()] = false;
+            match self.reasons[atom.index()] {
+                Reason::Decision => core.push(self.trail[position]),
+                _ => {
+                    let reason = self.reason_literals(atom).to_vec();
+                    for other in reason {
+                        if other.atom() != atom && 0 < self.level(other) {
+                            self.seen[other.atom().index()] = true;
+                        }
+                    }
+                },
+            }
+        }
+        core.sort();
+        core.dedup();
+        core
+    }
 
-        if 0 < verbosity {
-            log::info!("Adding assumption: {}", literal);
-        };
-        asgmt.insert(atom, phase);
-        let (prop_consequences, prop_falsified) = propagate_with_watcher(cnf, asgmt, literal, &mut watchers, verbosity);
-        stack.push((literal, prop_consequences));
-        falsified = prop_falsified
+    // The assumptions which made the last call to solve_with UNSAT, which are
+    // none if the solver is UNSAT regardless
+    pub fn core(&self) -> &[ast::Literal] {
+        &self.core
+    }
+
+    pub fn solve(&mut self) -> Option<ast::Asgmt> {
+        self.solve_with(&[])
+    }
+
+    // Solves with the given literals assumed true, which unlike added unit
+    // clauses only hold for this call. Also returns None when stopped by its
+    // exchange.
+    pub fn solve_with(&mut self, assumptions: &[ast::Literal]) -> Option<ast::Asgmt> {
+        for literal in assumptions {
+            self.reserve(literal.atom());
+        }
+        self.core.clear();
+        self.backtrack(0);
+        self.import();
+        self.rebuild_heap();
+        let mut restart_limit = self.restart_limit();
+        let mut restart_conflicts = 0;
+        loop {
+            if self.unsat {
+                return None
+            }
+            if let Some(conflict) = self.propagate() {
+                if !self.handle_conflict(conflict) {
+                    if 0 < self.verbosity {
+                        log::info!("UNSAT after {} conflicts", self.conflicts);
+                    }
+                    self.unsat = true;
+                    return None
+                }
+                restart_conflicts += 1;
+                if self.stopped() {
+                    return None
+                }
+                continue
+            }
+            if restart_limit <= restart_conflicts {
+                self.restarts += 1;
+                restart_limit = self.restart_limit();
+                restart_conflicts = 0;
+                if 0 < self.verbosity {
+                    log::info!("Restarting after {} conflicts", self.conflicts);
+                }
+                self.backtrack(0);
+                self.import();
+                if self.next_inprocess <= self.conflicts {
+                    // A conflict at level 0 leaves the solver UNSAT
+                    match self.propagate() {
+                        Some(_) => self.unsat = true,
+                        None => self.inprocess(),
+                    }
+                    self.next_inprocess = self.conflicts + INPROCESS_INTERVAL;
+                }
+                if 0.0 < self.config.vivify_effort && !self.unsat {
+                    match self.propagate() {
+                        Some(_) => self.unsat = true,
+                        None => self.vivify(),
+                    }
+                }
+                continue
+            }
+            if self.max_learned + self.trail.len() <= self.learned {
+                self.reduce();
+                self.max_learned += self.max_learned / 10;
+            }
+            // Each assumption is decided at its own level, before any others
+            let mut assumption = None;
+            while assumption.is_none() && self.decision_level() < assumptions.len() {
+                let literal = assumptions[self.decision_level()];
+                match self.value(literal) {
+                    Some(true) => self.trail_lim.push(self.trail.len()),
+                    Some(false) => {
+                        if 0 < self.verbosity {
+                            log::info!("UNSAT under assumptions after {} conflicts", self.conflicts);
+                        }
+                        self.core = self.analyze_final(literal);
+                        return None
+                    },
+                    None => assumption = Some(literal),
+                }
+            }
+            match assumption.or_else(|| self.decide()) {
+                None => {
+                    if let Some(conflict) = self.theory.final_check() {
+                        if 1 < self.verbosity {
+                            log::info!("Theory final check failed: {}", ast::Clause::from(conflict.clone()));
+                        }
+                        if !self.handle_conflict(conflict) {
+                            self.unsat = true;
+                            return None
+                        }
+                        continue
+                    }
+                    if 0 < self.verbosity {
+                        log::info!("SAT after {} conflicts", self.conflicts);
+                    }
+                    return Some(self.model())
+                },
+                Some(literal) => {
+                    if 1 < self.verbosity {
+                        log::info!("Adding assumption: {}", literal);
+                    }
+                    self.trail_lim.push(self.trail.len());
+                    self.enqueue(literal, Reason::Decision);
+                },
+            }
+        }
     }
 }
 
 
+// A solver for the preprocessed cnf, along with the simplifier extending its
+// models, or None if it is already UNSAT
+pub fn solver(cnf: &mut ast::Cnf, verbosity: usize) -> Option<(Solver, simplify::Simplifier)> {
+    let xors = xor::recover(cnf, verbosity);
+    if 0 < verbosity && 0 < xors {
+        log::info!("Recovered {} xors from their clauses", xors);
+    };
+    let simplifier = preprocess(cnf, &simplify::STEPS, verbosity)?;
+    Some((Solver::new(cnf, verbosity), simplifier))
+}
+
 pub fn sat(cnf: &mut ast::Cnf, verbosity: usize) -> Option<ast::Asgmt> {
-    let mut asgmt = cnf.normalize()?;
-    if dpll(cnf, &mut asgmt, verbosity) {
-        Some(asgmt)
-    } else {
-        None
-    }
-}
\ No newline at end of file
+    let (mut solver, simplifier) = solver(cnf, verbosity)?;
+    let mut model = solver.solve()?;
+    simplifier.extend(&mut model);
+    Some(model)
+}
@@ -0,0 +1,44 @@
+use crate::ast;
+use crate::dpll;
+use crate::xor;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// The models of a cnf projected onto some of its atoms, each projection
+// produced once. The projected atoms are decided first, so each model is
+// blocked by a clause of just its decisions on them.
+pub struct Models {
+    solver: dpll::Solver,
+    projection: Vec<ast::Atom>,
+}
+
+impl Iterator for Models {
+    type Item = ast::Asgmt;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let model = self.solver.solve()?;
+        let blocking = self.solver.blocking_clause(&self.projection);
+        self.solver.add_clause(&blocking);
+        let mut projected = ast::Asgmt::new();
+        for &atom in self.projection.iter() {
+            projected.insert(atom, model.get(&atom).unwrap());
+        }
+        Some(projected)
+    }
+}
+
+// Enumerates the models of the cnf, projected onto the given atoms, or else
+// onto its declared atoms (see Cnf::declared_atoms). Projected atoms need not
+// occur in the cnf, in which case they take both values.
+pub fn models(cnf: &ast::Cnf, projection: Option<Vec<ast::Atom>>, verbosity: usize) -> Models {
+    let mut projection = projection.unwrap_or_else(|| cnf.declared_atoms().chain(cnf.atoms()).collect());
+    projection.sort();
+    projection.dedup();
+    // Unlike pure literal elimination, recovering xors preserves every model
+    let mut cnf = cnf.clone();
+    xor::recover(&mut cnf, verbosity);
+    let mut solver = dpll::Solver::new(&cnf, verbosity);
+    solver.prioritize(&projection);
+    Models { solver, projection }
+}
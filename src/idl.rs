@@ -0,0 +1,257 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::ast;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// An integer variable, by index
+pub type Var = usize;
+
+// The difference constraint x - y <= bound
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct Constraint {
+    pub x: Var,
+    pub y: Var,
+    pub bound: i64,
+}
+
+impl Constraint {
+    // The constraint holding exactly when this one does not, over the integers
+    pub fn negation(&self) -> Self {
+        Self { x: self.y, y: self.x, bound: -self.bound - 1 }
+    }
+
+    pub fn eval(&self, values: &[i64]) -> bool {
+        values[self.x] - values[self.y] <= self.bound
+    }
+}
+
+// The constraint x - y <= bound as an edge from y to x, asserted by the literal
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    from: Var,
+    to: Var,
+    weight: i64,
+    literal: ast::Literal,
+}
+
+// Integer difference logic: atoms stand for difference constraints, and those
+// assigned must have a solution, which is the case when their graph has no
+// negative cycle. A potential of each variable is kept which satisfies the
+// edges; adding an edge repairs it by a Dijkstra-like search (Cotton and
+// Maler), which finds a negative cycle through the edge if there is one. The
+// atoms implied by paths through the new edge are then propagated.
+#[derive(Clone, Debug)]
+pub struct DifferenceLogic {
+    constraints: HashMap<ast::Atom, Constraint>,
+    potentials: Vec<i64>,
+    edges: Vec<Edge>,
+    // Edges by the variable they leave and enter, in the order they were added
+    outgoing: Vec<Vec<usize>>,
+    incoming: Vec<Vec<usize>>,
+    // The literals told by the solver, and those of them over constraints
+    trail: Vec<ast::Literal>,
+    assigned: HashSet<ast::Atom>,
+    verbosity: usize,
+}
+
+impl DifferenceLogic {
+    pub fn new(vars: usize, verbosity: usize) -> Self {
+        Self {
+            constraints: HashMap::new(),
+            potentials: vec![0; vars],
+            edges: Vec::new(),
+            outgoing: vec![Vec::new(); vars],
+            incoming: vec![Vec::new(); vars],
+            trail: Vec::new(),
+            assigned: HashSet::new(),
+            verbosity,
+        }
+    }
+
+    // Makes the atom stand for the constraint, whose variables must be fewer
+    // than those of the theory
+    pub fn define(&mut self, atom: ast::Atom, constraint: Constraint) {
+        self.constraints.insert(atom, constraint);
+    }
+
+    // A solution of the constraints assigned, in which variable 0 is zero
+    pub fn model(&self) -> Vec<i64> {
+        let zero = self.potentials.first().copied().unwrap_or(0);
+        self.potentials.iter().map(|potential| potential - zero).collect()
+    }
+
+    // Restores the potential after the edge is added, or else returns the
+    // literals of a negative cycle through it, leaving the potential as it was
+    fn repair(&mut self, added: usize) -> Option<Vec<ast::Literal>> {
+        let edge = self.edges[added];
+        if self.potentials[edge.to] <= self.potentials[edge.from] + edge.weight {
+            return None
+        }
+        if edge.from == edge.to {
+            return Some(vec![edge.literal])
+        }
+        let vars = self.potentials.len();
+        let mut deltas = vec![0; vars];
+        let mut preds = vec![added; vars];
+        let mut done = vec![false; vars];
+        let mut changed = Vec::new();
+        let mut heap = BinaryHeap::new();
+        deltas[edge.to] = self.potentials[edge.from] + edge.weight - self.potentials[edge.to];
+        heap.push((Reverse(deltas[edge.to]), edge.to));
+        while let Some((Reverse(delta), var)) = heap.pop() {
+            if done[var] || delta != deltas[var] {
+                continue
+            }
+            done[var] = true;
+            changed.push((var, self.potentials[var]));
+            self.potentials[var] += delta;
+            for &index in self.outgoing[var].iter() {
+                let next = self.edges[index];
+                let delta = self.potentials[var] + next.weight - self.potentials[next.to];
+                if done[next.to] || deltas[next.to] <= delta {
+                    continue
+                }
+                if next.to == edge.from {
+                    // The path back to the added edge closes a negative cycle
+                    for (var, potential) in changed {
+                        self.potentials[var] = potential;
+                    }
+                    preds[next.to] = index;
+                    let mut cycle = Vec::new();
+                    let mut var = edge.from;
+                    loop {
+                        cycle.push(self.edges[preds[var]].literal);
+                        if preds[var] == added {
+                            return Some(cycle)
+                        }
+                        var = self.edges[preds[var]].from;
+                    }
+                }
+                deltas[next.to] = delta;
+                preds[next.to] = index;
+                heap.push((Reverse(delta), next.to));
+            }
+        }
+        None
+    }
+
+    // Shortest paths from (or, backwards, to) the source, by the weights less
+    // the difference of the potentials, which are nonnegative. Returns the
+    // distances, along with the edge reaching each variable along the way.
+    fn distances(&self, source: Var, forward: bool) -> (Vec<Option<i64>>, Vec<usize>) {
+        let vars = self.potentials.len();
+        let mut distances = vec![None; vars];
+        let mut preds = vec![0; vars];
+        let mut done = vec![false; vars];
+        let mut heap = BinaryHeap::new();
+        distances[source] = Some(0);
+        heap.push((Reverse(0), source));
+        while let Some((Reverse(distance), var)) = heap.pop() {
+            if done[var] {
+                continue
+            }
+            done[var] = true;
+            let edges = if forward { &self.outgoing[var] } else { &self.incoming[var] };
+            for &index in edges {
+                let edge = self.edges[index];
+                let next = if forward { edge.to } else { edge.from };
+                let reduced = self.potentials[edge.from] + edge.weight - self.potentials[edge.to];
+                if distances[next].is_none_or(|other| distance + reduced < other) {
+                    distances[next] = Some(distance + reduced);
+                    preds[next] = index;
+                    heap.push((Reverse(distance + reduced), next));
+                }
+            }
+        }
+        (distances, preds)
+    }
+
+    // The literals of the unassigned atoms implied by a path through the added
+    // edge, each with the inversions of those of the path
+    fn implied(&self, added: usize) -> Vec<(ast::Literal, Vec<ast::Literal>)> {
+        let edge = self.edges[added];
+        let (before, before_preds) = self.distances(edge.from, false);
+        let (after, after_preds) = self.distances(edge.to, true);
+        // The weight of the shortest path from one variable to another through
+        // the edge, if there is one
+        let reduced = self.potentials[edge.from] + edge.weight - self.potentials[edge.to];
+        let weight = |from: Var, to: Var| -> Option<i64> {
+            Some(before[from]? + reduced + after[to]? - self.potentials[from] + self.potentials[to])
+        };
+        let explanation = |literal: ast::Literal, from: Var, to: Var| {
+            let mut explanation = vec![literal, edge.literal.inversion()];
+            let mut var = from;
+            while var != edge.from {
+                let pred = self.edges[before_preds[var]];
+                explanation.push(pred.literal.inversion());
+                var = pred.to;
+            }
+            let mut var = to;
+            while var != edge.to {
+                let pred = self.edges[after_preds[var]];
+                explanation.push(pred.literal.inversion());
+                var = pred.from;
+            }
+            explanation
+        };
+        let mut implied = Vec::new();
+        for (&atom, constraint) in self.constraints.iter() {
+            if self.assigned.contains(&atom) {
+                continue
+            }
+            let negation = constraint.negation();
+            for (phase, constraint) in [(true, constraint), (false, &negation)] {
+                if weight(constraint.y, constraint.x).is_some_and(|weight| weight <= constraint.bound) {
+                    let literal = ast::Literal::new(phase, atom);
+                    implied.push((literal, explanation(literal, constraint.y, constraint.x)));
+                    break
+                }
+            }
+        }
+        implied
+    }
+}
+
+impl dpll::Theory for DifferenceLogic {
+    fn assign(&mut self, literal: ast::Literal) -> dpll::Propagation {
+        self.trail.push(literal);
+        let Some(constraint) = self.constraints.get(&literal.atom()) else {
+            return dpll::Propagation::Implied(Vec::new())
+        };
+        let constraint = if literal.phase() { *constraint } else { constraint.negation() };
+        self.assigned.insert(literal.atom());
+        let index = self.edges.len();
+        self.edges.push(Edge { from: constraint.y, to: constraint.x, weight: constraint.bound, literal });
+        self.outgoing[constraint.y].push(index);
+        self.incoming[constraint.x].push(index);
+        if let Some(cycle) = self.repair(index) {
+            if 2 < self.verbosity {
+                log::info!("Negative cycle of {} edges", cycle.len());
+            }
+            return dpll::Propagation::Conflict(cycle.into_iter().map(|literal| literal.inversion()).collect())
+        }
+        dpll::Propagation::Implied(self.implied(index))
+    }
+
+    fn backtrack(&mut self, assigned: usize) {
+        while assigned < self.trail.len() {
+            let literal = self.trail.pop().unwrap();
+            if self.assigned.remove(&literal.atom()) {
+                // Edges are removed in the reverse of the order they were added
+                let edge = self.edges.pop().unwrap();
+                self.outgoing[edge.from].pop();
+                self.incoming[edge.to].pop();
+            }
+        }
+    }
+
+    // Conflicts are found as the edges are added, so the full assignment is
+    // consistent
+    fn final_check(&mut self) -> Option<Vec<ast::Literal>> {
+        None
+    }
+}
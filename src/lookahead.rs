@@ -0,0 +1,370 @@
+use std::collections::VecDeque;
+
+use itertools::Itertools;
+
+use crate::ast;
+use crate::xor;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Candidates looked ahead on at each node are at least this many, or this
+// fraction of the unassigned atoms
+const MIN_CANDIDATES: usize = 20;
+const CANDIDATE_FRACTION: f64 = 0.1;
+
+// A clause reduced to this many unassigned literals has weight BASE^(2 - len),
+// so that new binary clauses count the most
+const REDUCTION_BASE: f64 = 5.0;
+
+// The outcome of looking ahead at a node
+enum Node {
+    Refuted,
+    Satisfied,
+    // The literal to branch on first
+    Split(ast::Literal),
+}
+
+// A DPLL solver in the style of march and kcnfs. At each node the candidate
+// atoms are assigned both ways in turn and propagated: a polarity leading to a
+// conflict is a failed literal, whose inversion is then implied, and otherwise
+// the atom is scored by how much both polarities reduce the clauses. It then
+// branches on the best atom, first on the polarity reducing less, which is
+// more likely to be satisfiable.
+pub struct Lookahead {
+    clauses: Vec<Vec<ast::Literal>>,
+    // Clauses by the index of a literal they watch, and of a literal they
+    // contain
+    watches: Vec<Vec<usize>>,
+    occurs: Vec<Vec<usize>>,
+    atoms: Vec<ast::Atom>,
+    // The product of the occurrences of each polarity, by atom index, which
+    // preselects the candidates
+    static_score: Vec<usize>,
+
+    values: Vec<Option<bool>>,
+    trail: Vec<ast::Literal>,
+    // Marks clauses counted in a reduction, by the lookahead which counted them
+    stamps: Vec<usize>,
+    stamp: usize,
+
+    unsat: bool,
+    nodes: usize,
+    failed: usize,
+    verbosity: usize,
+}
+
+impl Lookahead {
+    pub fn new(cnf: &ast::Cnf, verbosity: usize) -> Self {
+        // Only the atoms of the cnf are branched on, as those of the expanded
+        // xors follow from them by propagation
+        let atoms: Vec<ast::Atom> = cnf.declared_atoms().chain(cnf.atoms()).sorted().dedup().collect();
+        let mut cnf = cnf.clone();
+        xor::expand(&mut cnf);
+        let size = cnf.declared_atoms().chain(cnf.atoms()).map(|atom| atom.index() + 1).max().unwrap_or(0);
+        let mut lookahead = Self {
+            clauses: Vec::new(),
+            watches: vec![Vec::new(); 2 * size],
+            occurs: vec![Vec::new(); 2 * size],
+            atoms,
+            static_score: vec![0; size],
+            values: vec![None; size],
+            trail: Vec::new(),
+            stamps: Vec::new(),
+            stamp: 0,
+            unsat: false,
+            nodes: 0,
+            failed: 0,
+            verbosity,
+        };
+        for clause in cnf.clauses() {
+            let literals: Vec<ast::Literal> = clause.literals().cloned().sorted().dedup().collect();
+            if literals.iter().tuple_windows().any(|(a, b)| a.atom() == b.atom()) {
+                continue
+            }
+            lookahead.add_clause(literals);
+        }
+        for atom in lookahead.atoms.iter() {
+            let positive = lookahead.occurs[ast::Literal::new(true, *atom).index()].len();
+            let negative = lookahead.occurs[ast::Literal::new(false, *atom).index()].len();
+            lookahead.static_score[atom.index()] = (positive + 1) * (negative + 1);
+        }
+        lookahead.unsat = lookahead.unsat || !lookahead.propagate(0);
+        lookahead
+    }
+
+    fn add_clause(&mut self, literals: Vec<ast::Literal>) {
+        match literals[..] {
+            [] => self.unsat = true,
+            [literal] => match self.value(literal) {
+                Some(false) => self.unsat = true,
+                Some(true) => (),
+                None => self.assign(literal),
+            },
+            _ => {
+                let index = self.clauses.len();
+                self.watches[literals[0].index()].push(index);
+                self.watches[literals[1].index()].push(index);
+                for literal in literals.iter() {
+                    self.occurs[literal.index()].push(index);
+                }
+                self.clauses.push(literals);
+                self.stamps.push(0);
+            },
+        }
+    }
+
+    fn value(&self, literal: ast::Literal) -> Option<bool> {
+        self.values[literal.atom().index()].map(|phase| phase == literal.phase())
+    }
+
+    fn assign(&mut self, literal: ast::Literal) {
+        self.values[literal.atom().index()] = Some(literal.phase());
+        self.trail.push(literal);
+    }
+
+    fn undo(&mut self, position: usize) {
+        for literal in self.trail.drain(position..) {
+            self.values[literal.atom().index()] = None;
+        }
+    }
+
+    // Propagates the literals of the trail from the position onwards. Returns
+    // false on a conflict.
+    fn propagate(&mut self, mut position: usize) -> bool {
+        while position < self.trail.len() {
+            let false_lit = self.trail[position].inversion();
+            position += 1;
+            let watching = std::mem::take(&mut self.watches[false_lit.index()]);
+            let mut kept = Vec::with_capacity(watching.len());
+            let mut conflict = false;
+            let mut iter = watching.into_iter();
+            for index in iter.by_ref() {
+                let clause = &mut self.clauses[index];
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                if self.values[first.atom().index()] == Some(first.phase()) {
+                    kept.push(index);
+                    continue
+                }
+                let replacement = (2..clause.len()).find(|&k| {
+                    self.values[clause[k].atom().index()] != Some(!clause[k].phase())
+                });
+                match replacement {
+                    Some(k) => {
+                        clause.swap(1, k);
+                        let new_watch = clause[1];
+                        self.watches[new_watch.index()].push(index);
+                    },
+                    None => {
+                        kept.push(index);
+                        if self.value(first) == Some(false) {
+                            conflict = true;
+                            break
+                        }
+                        self.assign(first);
+                    },
+                }
+            }
+            kept.extend(iter);
+            self.watches[false_lit.index()] = kept;
+            if conflict {
+                return false
+            }
+        }
+        true
+    }
+
+    // Assigns and propagates the literal, returning the weighted reduction of
+    // the clauses, or None on a conflict. Undoes the assignments.
+    fn look(&mut self, literal: ast::Literal) -> Option<f64> {
+        let position = self.trail.len();
+        self.assign(literal);
+        if !self.propagate(position) {
+            self.undo(position);
+            return None
+        }
+        self.stamp += 1;
+        let mut reduction = 0.0;
+        for k in position..self.trail.len() {
+            let false_lit = self.trail[k].inversion();
+            for m in 0..self.occurs[false_lit.index()].len() {
+                let index = self.occurs[false_lit.index()][m];
+                if self.stamps[index] == self.stamp {
+                    continue
+                }
+                self.stamps[index] = self.stamp;
+                let clause = &self.clauses[index];
+                if clause.iter().any(|&lit| self.value(lit) == Some(true)) {
+                    continue
+                }
+                let unassigned = clause.iter().filter(|&&lit| self.value(lit).is_none()).count();
+                reduction += REDUCTION_BASE.powi(2 - unassigned as i32);
+            }
+        }
+        self.undo(position);
+        Some(reduction)
+    }
+
+    fn candidates(&self) -> Vec<ast::Atom> {
+        let unassigned: Vec<ast::Atom> = self.atoms.iter()
+            .filter(|atom| self.values[atom.index()].is_none())
+            .cloned()
+            .collect();
+        let count = MIN_CANDIDATES.max((CANDIDATE_FRACTION * unassigned.len() as f64) as usize);
+        unassigned.into_iter()
+            .sorted_by_key(|atom| std::cmp::Reverse(self.static_score[atom.index()]))
+            .take(count)
+            .collect()
+    }
+
+    // Looks ahead on the candidates at the current assignment, assigning any
+    // failed literals' inversions, which the caller must undo
+    fn node(&mut self) -> Node {
+        let best = loop {
+            let candidates = self.candidates();
+            if candidates.is_empty() {
+                return Node::Satisfied
+            }
+            let mut best: Option<(f64, ast::Atom, f64, f64)> = None;
+            let mut failed = false;
+            for atom in candidates {
+                if self.values[atom.index()].is_some() {
+                    continue
+                }
+                let positive = ast::Literal::new(true, atom);
+                match (self.look(positive), self.look(positive.inversion())) {
+                    (None, None) => return Node::Refuted,
+                    (Some(pos), Some(neg)) => {
+                        let score = 1024.0 * pos * neg + pos + neg;
+                        if best.is_none_or(|(best_score, ..)| best_score < score) {
+                            best = Some((score, atom, pos, neg));
+                        }
+                    },
+                    (pos, _) => {
+                        let implied = if pos.is_none() { positive.inversion() } else { positive };
+                        if 2 < self.verbosity {
+                            log::info!("Failed literal {}", implied.inversion());
+                        }
+                        self.failed += 1;
+                        failed = true;
+                        let position = self.trail.len();
+                        self.assign(implied);
+                        if !self.propagate(position) {
+                            return Node::Refuted
+                        }
+                    },
+                }
+            }
+            // Failed literals change the reductions, so look again
+            if !failed {
+                break best
+            }
+        };
+        match best {
+            Some((_, atom, pos, neg)) => Node::Split(ast::Literal::new(pos <= neg, atom)),
+            None => Node::Satisfied,
+        }
+    }
+
+    // Searches below the current assignment. Returns true when every atom is
+    // assigned without conflict, and otherwise leaves the assignments made at
+    // this node for the caller to undo.
+    fn search(&mut self) -> bool {
+        self.nodes += 1;
+        let first = match self.node() {
+            Node::Refuted => return false,
+            Node::Satisfied => return true,
+            Node::Split(literal) => literal,
+        };
+        for literal in [first, first.inversion()] {
+            if 1 < self.verbosity {
+                log::info!("Branching on {}", literal);
+            }
+            let position = self.trail.len();
+            self.assign(literal);
+            if self.propagate(position) && self.search() {
+                return true
+            }
+            self.undo(position);
+        }
+        false
+    }
+
+    // Splits the search into cubes breadth first, so that they are balanced,
+    // stopping at the depth or at the number of cubes. Refuted cubes are
+    // dropped, so the cnf is satisfiable exactly when some cube extends to a
+    // model.
+    pub fn cubes(&mut self, depth: Option<usize>, limit: usize) -> Vec<Vec<ast::Literal>> {
+        if self.unsat {
+            return Vec::new()
+        }
+        let mut queue: VecDeque<Vec<ast::Literal>> = VecDeque::from([Vec::new()]);
+        let mut cubes = Vec::new();
+        while let Some(cube) = queue.pop_front() {
+            if depth.is_some_and(|depth| depth <= cube.len()) || limit <= queue.len() + cubes.len() + 1 {
+                cubes.push(cube);
+                continue
+            }
+            self.nodes += 1;
+            let position = self.trail.len();
+            let mut consistent = true;
+            for &literal in cube.iter() {
+                match self.value(literal) {
+                    Some(true) => (),
+                    Some(false) => consistent = false,
+                    None => self.assign(literal),
+                }
+            }
+            let node = if consistent && self.propagate(position) { self.node() } else { Node::Refuted };
+            self.undo(position);
+            match node {
+                Node::Refuted => {
+                    if 1 < self.verbosity {
+                        log::info!("Refuted cube of {} literals", cube.len());
+                    }
+                },
+                Node::Satisfied => cubes.push(cube),
+                Node::Split(literal) => {
+                    let mut other = cube.clone();
+                    other.push(literal.inversion());
+                    let mut cube = cube;
+                    cube.push(literal);
+                    queue.push_back(cube);
+                    queue.push_back(other);
+                },
+            }
+        }
+        if 0 < self.verbosity {
+            log::info!("Split into {} cubes after {} nodes and {} failed literals", cubes.len(), self.nodes, self.failed);
+        }
+        cubes
+    }
+
+    pub fn solve(&mut self) -> Option<ast::Asgmt> {
+        if self.unsat || !self.search() {
+            if 0 < self.verbosity {
+                log::info!("UNSAT after {} nodes and {} failed literals", self.nodes, self.failed);
+            }
+            return None
+        }
+        if 0 < self.verbosity {
+            log::info!("SAT after {} nodes and {} failed literals", self.nodes, self.failed);
+        }
+        let mut asgmt = ast::Asgmt::new();
+        for &atom in self.atoms.iter() {
+            asgmt.insert(atom, self.values[atom.index()].unwrap_or(false));
+        }
+        Some(asgmt)
+    }
+}
+
+pub fn sat(cnf: &ast::Cnf, verbosity: usize) -> Option<ast::Asgmt> {
+    Lookahead::new(cnf, verbosity).solve()
+}
+
+pub fn cubes(cnf: &ast::Cnf, depth: Option<usize>, limit: usize, verbosity: usize) -> Vec<Vec<ast::Literal>> {
+    Lookahead::new(cnf, verbosity).cubes(depth, limit)
+}
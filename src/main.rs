@@ -1,21 +1,249 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
+pub mod aiger;
+pub mod approxmc;
 pub mod ast;
+pub mod backbone;
+pub mod bignum;
+pub mod bmc;
+pub mod bv;
+pub mod card;
+pub mod count;
 pub mod dimacs;
 pub mod dpll;
+pub mod enumerate;
+pub mod idl;
+pub mod lookahead;
+pub mod mcs;
+pub mod minimize;
+pub mod mus;
+pub mod opb;
+pub mod pb;
+pub mod portfolio;
+pub mod qbf;
+pub mod sample;
+pub mod simplify;
+pub mod sls;
+pub mod smtlib;
 mod util;
+pub mod xor;
 
 
 /// Satellite is a toy SAT solver
 #[derive(Parser, Debug)]
-#[command(about, long_about = None)]
+#[command(about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
     /// Verbosity, 0-4
-    #[arg(short, long, default_value_t = 1)]
+    #[arg(short, long, global = true, default_value_t = 1)]
     verbosity: usize,
 
-    /// DIMACS file
-    file: std::path::PathBuf,
+    /// Encoding of pseudo-Boolean constraints into CNF
+    #[arg(long, value_enum, default_value_t = pb::Encoding::Auto)]
+    pb_encoding: pb::Encoding,
+
+    /// Solve by lookahead DPLL instead of CDCL
+    #[arg(long, conflicts_with = "sls")]
+    lookahead: bool,
+
+    /// Solve by a portfolio of differently configured solvers in parallel
+    #[arg(long, default_value_t = 1, conflicts_with_all = ["sls", "lookahead"])]
+    threads: usize,
+
+    /// Share short learned clauses between the solvers of the portfolio
+    #[arg(long)]
+    share: bool,
+
+    /// Reduce the model to a prime implicant: a partial assignment satisfying
+    /// every clause, none of whose literals can be dropped
+    #[arg(long, conflicts_with_all = ["sls", "lookahead", "threads"])]
+    prime: bool,
+
+    /// Find a subset-minimal model: no other model has only some of its true
+    /// atoms
+    #[arg(long, conflicts_with_all = ["sls", "lookahead", "threads"])]
+    minimal: bool,
+
+    /// Search for a model by (incomplete) local search instead
+    #[arg(long, value_enum)]
+    sls: Option<sls::Algorithm>,
+
+    /// Probability of a random walk step in WalkSAT
+    #[arg(long, default_value_t = sls::Params::default().noise)]
+    noise: f64,
+
+    /// Flips per try of local search
+    #[arg(long, default_value_t = sls::Params::default().max_flips)]
+    max_flips: usize,
+
+    /// Tries (from random assignments) of local search
+    #[arg(long, default_value_t = sls::Params::default().max_tries)]
+    max_tries: usize,
+
+    /// Seed of local search
+    #[arg(long, default_value_t = sls::Params::default().seed)]
+    seed: u64,
+
+    /// DIMACS file, or OPB file if the extension is .opb, or QDIMACS file if
+    /// it is .qdimacs, or SMT-LIB file of the QF_IDL or QF_BV logic if it is
+    /// .smt2
+    #[arg(required = true)]
+    file: Option<std::path::PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print every model of a DIMACS file
+    Enumerate {
+        /// Stop after this many models
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+
+        /// Atoms to project models onto, e.g. 1,2,3 (by default, those of any
+        /// "c ind" lines, or else every atom)
+        #[arg(short, long, value_delimiter = ',')]
+        project: Option<Vec<u32>>,
+
+        /// DIMACS file
+        file: std::path::PathBuf,
+    },
+
+    /// Print the number of models of a DIMACS file
+    Count {
+        /// DIMACS file
+        file: std::path::PathBuf,
+    },
+
+    /// Print an estimate of the number of models of a DIMACS file, by hashing
+    ApproxCount {
+        /// Atoms to project models onto, e.g. 1,2,3 (by default, those of any
+        /// "c ind" lines, or else every atom)
+        #[arg(short, long, value_delimiter = ',')]
+        project: Option<Vec<u32>>,
+
+        /// Tolerance: the estimate is within a factor of 1 + epsilon of the count
+        #[arg(short, long, default_value_t = 0.8)]
+        epsilon: f64,
+
+        /// Confidence: the estimate is within tolerance with probability 1 - delta
+        #[arg(short, long, default_value_t = 0.2)]
+        delta: f64,
+
+        /// Seed of the random hashes
+        #[arg(short, long, default_value_t = 1)]
+        seed: u64,
+
+        /// DIMACS file
+        file: std::path::PathBuf,
+    },
+
+    /// Print near-uniform samples of the models of a DIMACS file
+    Sample {
+        /// Number of samples
+        #[arg(short = 'n', long, default_value_t = 1)]
+        samples: usize,
+
+        /// Atoms to project models onto, e.g. 1,2,3 (by default, those of any
+        /// "c ind" lines, or else every atom)
+        #[arg(short, long, value_delimiter = ',')]
+        project: Option<Vec<u32>>,
+
+        /// Tolerance: each model is sampled with probability within a factor of
+        /// 1 + epsilon of uniform (greater than 1.71)
+        #[arg(short, long, default_value_t = 16.0)]
+        epsilon: f64,
+
+        /// Seed of the random hashes and choices
+        #[arg(short, long, default_value_t = 1)]
+        seed: u64,
+
+        /// DIMACS file
+        file: std::path::PathBuf,
+    },
+
+    /// Split a DIMACS file into cubes by lookahead, printed in iCNF format
+    Cube {
+        /// Stop splitting cubes of this many literals
+        #[arg(short, long)]
+        depth: Option<usize>,
+
+        /// Stop splitting at this many cubes
+        #[arg(short = 'n', long, default_value_t = 1000)]
+        limit: usize,
+
+        /// DIMACS file
+        file: std::path::PathBuf,
+    },
+
+    /// Solve the cubes of an iCNF file in turn, under assumptions
+    Conquer {
+        /// iCNF file
+        file: std::path::PathBuf,
+    },
+
+    /// Print the simplification of a DIMACS file, in DIMACS format
+    Preprocess {
+        /// Steps to run, in order, e.g. eliminate,subsume (by default,
+        /// probe,substitute,eliminate,subsume,block)
+        #[arg(short, long, value_enum, value_delimiter = ',')]
+        steps: Option<Vec<simplify::Step>>,
+
+        /// DIMACS file
+        file: std::path::PathBuf,
+    },
+
+    /// Print a minimal unsatisfiable subset of the clauses of a DIMACS file, in
+    /// DIMACS format, with their indices in the file, or of the groups of a
+    /// GCNF file if the extension is .gcnf, with their ids
+    Mus {
+        /// Algorithm shrinking the UNSAT core to a minimal one
+        #[arg(short, long, value_enum, default_value_t = mus::Algorithm::Deletion)]
+        algorithm: mus::Algorithm,
+
+        /// DIMACS or GCNF file
+        file: std::path::PathBuf,
+    },
+
+    /// Print the minimal correction sets of the clauses of a DIMACS file, by
+    /// their indices in the file, or of the groups of a GCNF file if the
+    /// extension is .gcnf, by their ids
+    Mcs {
+        /// Algorithm growing the groups a model satisfies to a maximal set
+        #[arg(short, long, value_enum, default_value_t = mcs::Algorithm::Cld)]
+        algorithm: mcs::Algorithm,
+
+        /// Stop after this many correction sets
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+
+        /// DIMACS or GCNF file
+        file: std::path::PathBuf,
+    },
+
+    /// Print the backbone of a DIMACS file: the literals true in every model
+    Backbone {
+        /// Rule out as well the literals each model found can flip without
+        /// falsifying a clause
+        #[arg(short, long)]
+        rotate: bool,
+
+        /// DIMACS file
+        file: std::path::PathBuf,
+    },
+
+    /// Search an AIGER circuit for a path to a bad state of at most so many
+    /// steps, by bounded model checking, printing it in the AIGER witness
+    /// format
+    Bmc {
+        /// Steps to unroll the circuit to
+        #[arg(short = 'k', long, default_value_t = 20)]
+        bound: usize,
+
+        /// AIGER file, in ASCII (.aag) or binary (.aig)
+        file: std::path::PathBuf,
+    },
 }
 
 
@@ -24,7 +252,81 @@ fn main() {
 
     let args = Args::parse();
 
-    if let Err(err) = dimacs::read_dimacs_check_sat_and_print(args.file, args.verbosity) {
+    let result = match args.command {
+        Some(Command::Enumerate { limit, project, file }) => {
+            let projection = project.map(|atoms| atoms.into_iter().map(ast::Atom::new).collect());
+            dimacs::read_dimacs_enumerate_and_print(file, limit, projection, args.verbosity).map(|_| ())
+        },
+        Some(Command::Count { file }) => {
+            dimacs::read_dimacs_count_and_print(file, args.verbosity).map(|_| ())
+        },
+        Some(Command::ApproxCount { project, epsilon, delta, seed, file }) => {
+            let projection = project.map(|atoms| atoms.into_iter().map(ast::Atom::new).collect());
+            dimacs::read_dimacs_approx_count_and_print(file, projection, epsilon, delta, seed, args.verbosity).map(|_| ())
+        },
+        Some(Command::Sample { samples, project, epsilon, seed, file }) => {
+            let projection = project.map(|atoms| atoms.into_iter().map(ast::Atom::new).collect());
+            dimacs::read_dimacs_sample_and_print(file, samples, projection, epsilon, seed, args.verbosity).map(|_| ())
+        },
+        Some(Command::Cube { depth, limit, file }) => {
+            dimacs::read_dimacs_cube_and_print(file, depth, limit, args.verbosity).map(|_| ())
+        },
+        Some(Command::Conquer { file }) => {
+            dimacs::read_icnf_conquer_and_print(file, args.verbosity).map(|_| ())
+        },
+        Some(Command::Preprocess { steps, file }) => {
+            let steps = steps.unwrap_or(simplify::STEPS.to_vec());
+            dimacs::read_dimacs_preprocess_and_print(file, &steps, args.verbosity).map(|_| ())
+        },
+        Some(Command::Mus { algorithm, file }) => {
+            if file.extension().is_some_and(|ext| ext == "gcnf") {
+                dimacs::read_gcnf_mus_and_print(file, algorithm, args.verbosity).map(|_| ())
+            } else {
+                dimacs::read_dimacs_mus_and_print(file, algorithm, args.verbosity).map(|_| ())
+            }
+        },
+        Some(Command::Backbone { rotate, file }) => {
+            dimacs::read_dimacs_backbone_and_print(file, rotate, args.verbosity).map(|_| ())
+        },
+        Some(Command::Mcs { algorithm, limit, file }) => {
+            if file.extension().is_some_and(|ext| ext == "gcnf") {
+                dimacs::read_gcnf_mcs_and_print(file, algorithm, limit, args.verbosity).map(|_| ())
+            } else {
+                dimacs::read_dimacs_mcs_and_print(file, algorithm, limit, args.verbosity).map(|_| ())
+            }
+        },
+        Some(Command::Bmc { bound, file }) => {
+            aiger::read_aiger_bmc_and_print(file, bound, args.verbosity).map(|_| ())
+        },
+        None => {
+            let file = args.file.unwrap();
+            if file.extension().is_some_and(|ext| ext == "opb") {
+                opb::read_opb_minimize_and_print(file, args.pb_encoding, args.verbosity).map(|_| ())
+            } else if file.extension().is_some_and(|ext| ext == "qdimacs") {
+                dimacs::read_qdimacs_solve_and_print(file, args.verbosity).map(|_| ())
+            } else if file.extension().is_some_and(|ext| ext == "smt2") {
+                smtlib::read_smtlib_solve_and_print(file, args.verbosity).map(|_| ())
+            } else if let Some(algorithm) = args.sls {
+                let params = sls::Params {
+                    algorithm,
+                    noise: args.noise,
+                    max_flips: args.max_flips,
+                    max_tries: args.max_tries,
+                    seed: args.seed,
+                };
+                dimacs::read_dimacs_local_search_and_print(file, &params, args.verbosity).map(|_| ())
+            } else if args.prime || args.minimal {
+                dimacs::read_dimacs_minimize_and_print(file, args.minimal, args.prime, args.verbosity).map(|_| ())
+            } else if args.lookahead {
+                dimacs::read_dimacs_lookahead_and_print(file, args.verbosity).map(|_| ())
+            } else if 1 < args.threads {
+                dimacs::read_dimacs_portfolio_and_print(file, args.threads, args.share, args.verbosity).map(|_| ())
+            } else {
+                dimacs::read_dimacs_check_sat_and_print(file, args.verbosity).map(|_| ())
+            }
+        },
+    };
+    if let Err(err) = result {
         log::error!("{}", err)
     }
 }
@@ -92,9 +394,10 @@ fn trivial_noncontradiction() {
     } else {
         println!("unsat");
     }
-    assert!(result == None);
+    assert!(result.is_none());
 }
 
+#[cfg(test)]
 fn dimacs_test<P>(path: P, sat: bool, verbosity: usize) where P: AsRef<std::path::Path> {
     // env_logger::builder().filter_level(log::LevelFilter::Info).init();
 
@@ -107,8 +410,24 @@ fn dimacs_test<P>(path: P, sat: bool, verbosity: usize) where P: AsRef<std::path
     if sat {
         assert!(result.is_some())
     } else {
-        assert!(result == None)
+        assert!(result.is_none())
+    }
+}
+
+// The literal of a DIMACS number
+#[cfg(test)]
+fn literal(num: i32) -> ast::Literal {
+    ast::Literal::new(0 < num, ast::Atom::new(num.unsigned_abs()))
+}
+
+// The cnf of clauses of DIMACS numbers
+#[cfg(test)]
+fn cnf_of(clauses: &[&[i32]]) -> ast::Cnf {
+    let mut cnf = ast::Cnf::new();
+    for clause in clauses {
+        cnf.add(ast::Clause::from(clause.iter().copied().map(literal).collect::<Vec<_>>()));
     }
+    cnf
 }
 
 #[test]
@@ -144,4 +463,920 @@ fn dimacs_uuf100_01() {
 #[test]
 fn dimacs_uf250_01() {
     dimacs_test("tests/uf250/uf250-01.cnf", true, 0)
-}
\ No newline at end of file
+}
+
+// Checks that for every assignment to the constrained atoms, the encoding is
+// satisfiable exactly when the constraint holds.
+#[cfg(test)]
+fn card_test(encoding: card::Encoding) {
+    type Encode = fn(&mut ast::Cnf, &[ast::Literal], usize, card::Encoding);
+    type Holds = fn(usize, usize) -> bool;
+    let constraints: [(Encode, Holds); 3] = [
+        (card::at_most_k, |count, k| count <= k),
+        (card::at_least_k, |count, k| k <= count),
+        (card::exactly_k, |count, k| count == k),
+    ];
+    for n in 0..=5 {
+        let literals: Vec<ast::Literal> = (1..=n)
+            .map(|i| ast::Literal::new(i % 2 == 0, ast::Atom::new(i)))
+            .collect();
+        for k in 0..=n as usize + 1 {
+            for (encode, holds) in constraints {
+                let mut encoded = ast::Cnf::new();
+                encode(&mut encoded, &literals, k, encoding);
+                for bits in 0..(1 << n) {
+                    let mut cnf = encoded.clone();
+                    let mut count = 0;
+                    for (i, literal) in literals.iter().enumerate() {
+                        let phase = bits & (1 << i) != 0;
+                        if phase == literal.phase() {
+                            count += 1;
+                        }
+                        cnf.add(ast::Clause::from(vec![ast::Literal::new(phase, literal.atom())]));
+                    }
+                    let result = dpll::sat(&mut cnf, 0);
+                    assert_eq!(holds(count, k), result.is_some(), "n: {}, k: {}, count: {}, encoding: {:?}", n, k, count, encoding);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn card_pairwise() {
+    card_test(card::Encoding::Pairwise)
+}
+
+#[test]
+fn card_sequential_counter() {
+    card_test(card::Encoding::SequentialCounter)
+}
+
+#[test]
+fn card_totalizer() {
+    card_test(card::Encoding::Totalizer)
+}
+
+#[test]
+fn card_cardinality_network() {
+    card_test(card::Encoding::CardinalityNetwork)
+}
+
+#[test]
+fn card_commander() {
+    card_test(card::Encoding::Commander)
+}
+
+#[test]
+fn card_auto() {
+    card_test(card::Encoding::Auto);
+    assert_eq!(card::Encoding::Pairwise, card::choose_encoding(4, 1));
+    assert_ne!(card::Encoding::Pairwise, card::choose_encoding(100, 5));
+}
+
+
+// Checks that for every assignment to the constrained atoms, the encoding is
+// satisfiable exactly when the constraint holds.
+#[cfg(test)]
+fn pb_test(encoding: pb::Encoding) {
+    let atom = |i: u32| ast::Atom::new(i);
+    let terms_list: Vec<Vec<(i64, ast::Literal)>> = vec![
+        vec![(1, ast::Literal::new(true, atom(1))), (1, ast::Literal::new(false, atom(2))), (1, ast::Literal::new(true, atom(3)))],
+        vec![(3, ast::Literal::new(true, atom(1))), (2, ast::Literal::new(true, atom(2))), (5, ast::Literal::new(false, atom(3))), (1, ast::Literal::new(true, atom(4)))],
+        vec![(-2, ast::Literal::new(true, atom(1))), (3, ast::Literal::new(true, atom(2))), (4, ast::Literal::new(true, atom(3))), (-1, ast::Literal::new(false, atom(4)))],
+        vec![(7, ast::Literal::new(true, atom(1))), (-7, ast::Literal::new(false, atom(1))), (6, ast::Literal::new(true, atom(2))), (9, ast::Literal::new(true, atom(3)))],
+    ];
+    for terms in terms_list {
+        for relation in [pb::Relation::GreaterEq, pb::Relation::Equal, pb::Relation::LessEq] {
+            for bound in -2..=10 {
+                let constraint = pb::Constraint { terms: terms.clone(), relation, bound };
+                let mut encoded = ast::Cnf::new();
+                encoded.declare_atom(atom(4));
+                pb::encode(&mut encoded, &constraint, encoding).unwrap();
+                for bits in 0..(1 << 4) {
+                    let mut cnf = encoded.clone();
+                    let mut asgmt = ast::Asgmt::new();
+                    for i in 0..4 {
+                        let phase = bits & (1 << i) != 0;
+                        asgmt.insert(atom(i + 1), phase);
+                        cnf.add(ast::Clause::from(vec![ast::Literal::new(phase, atom(i + 1))]));
+                    }
+                    let holds = constraint.eval(&asgmt).unwrap();
+                    let result = dpll::sat(&mut cnf, 0);
+                    assert_eq!(holds, result.is_some(), "constraint: {:?}, asgmt: {}, encoding: {:?}", constraint, asgmt, encoding);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn pb_bdd() {
+    pb_test(pb::Encoding::Bdd)
+}
+
+#[test]
+fn pb_adder() {
+    pb_test(pb::Encoding::Adder)
+}
+
+#[test]
+fn pb_sorter() {
+    pb_test(pb::Encoding::Sorter)
+}
+
+#[test]
+fn pb_auto() {
+    pb_test(pb::Encoding::Auto)
+}
+
+#[test]
+fn pb_overflow() {
+    let literal = |phase: bool, i: u32| ast::Literal::new(phase, ast::Atom::new(i));
+    // Coefficients which overflow 64 bits when merged or negated
+    let constraints = [
+        pb::Constraint { terms: vec![(i64::MAX, literal(true, 1)), (1, literal(true, 1))], relation: pb::Relation::GreaterEq, bound: 1 },
+        pb::Constraint { terms: vec![(i64::MIN, literal(true, 1))], relation: pb::Relation::LessEq, bound: i64::MIN },
+        pb::Constraint { terms: vec![(i64::MIN, literal(false, 1))], relation: pb::Relation::Equal, bound: i64::MIN },
+    ];
+    for constraint in constraints {
+        for encoding in [pb::Encoding::Auto, pb::Encoding::Bdd, pb::Encoding::Adder, pb::Encoding::Sorter] {
+            let mut cnf = ast::Cnf::new();
+            pb::encode(&mut cnf, &constraint, encoding).unwrap();
+            let asgmt = dpll::sat(&mut cnf, 0).unwrap();
+            assert_eq!(Some(true), constraint.eval(&asgmt), "constraint: {:?}, encoding: {:?}", constraint, encoding);
+        }
+    }
+    // Whose bound no longer fits in 64 bits once normalized
+    let constraint = pb::Constraint {
+        terms: vec![(-i64::MAX, literal(true, 1)), (-i64::MAX, literal(true, 2)), (-i64::MAX, literal(true, 3)), (i64::MAX, literal(true, 4))],
+        relation: pb::Relation::GreaterEq,
+        bound: 0,
+    };
+    assert!(pb::encode(&mut ast::Cnf::new(), &constraint, pb::Encoding::Auto).is_err());
+}
+
+#[test]
+fn pb_bdd_limit() {
+    // Unrelated large coefficients, whose BDD grows exponentially
+    let terms: Vec<(i64, ast::Literal)> = (1..=64_u64)
+        .map(|i| ((i.wrapping_mul(0x9e3779b97f4a7c15) >> 24) as i64, ast::Literal::new(true, ast::Atom::new(i as u32))))
+        .collect();
+    let bound = terms.iter().map(|term| term.0 / 2).sum();
+    let constraint = pb::Constraint { terms, relation: pb::Relation::GreaterEq, bound };
+    assert!(pb::encode(&mut ast::Cnf::new(), &constraint, pb::Encoding::Bdd).is_err());
+    // Which the automatic encoding falls back from
+    assert!(pb::encode(&mut ast::Cnf::new(), &constraint, pb::Encoding::Auto).is_ok());
+}
+
+#[cfg(test)]
+fn opb_test<P>(path: P, cost: Option<Option<i64>>, encoding: pb::Encoding) where P: AsRef<std::path::Path> {
+    let result = opb::read_opb_minimize_and_print(path, encoding, 1);
+    if let Err(err) = &result {
+        eprintln!("Error: {}", err);
+    }
+    assert!(result.is_ok());
+    assert_eq!(cost, result.unwrap().map(|(_, cost)| cost))
+}
+
+#[test]
+fn opb_mini() {
+    for encoding in [pb::Encoding::Auto, pb::Encoding::Bdd, pb::Encoding::Adder, pb::Encoding::Sorter] {
+        opb_test("tests/opb/mini.opb", Some(Some(4)), encoding)
+    }
+}
+
+#[test]
+fn opb_mini_unsat() {
+    opb_test("tests/opb/mini-unsat.opb", None, pb::Encoding::Auto)
+}
+
+#[test]
+fn opb_mini_no_objective() {
+    opb_test("tests/opb/mini-no-objective.opb", Some(None), pb::Encoding::Auto)
+}
+
+#[test]
+fn dimacs_xor_sat() {
+    dimacs_test("tests/xor/parity-sat.cnf", true, 2)
+}
+
+#[test]
+fn dimacs_xor_unsat() {
+    dimacs_test("tests/xor/parity-unsat.cnf", false, 2)
+}
+
+#[test]
+fn dimacs_xor_unsat_clausal() {
+    dimacs_test("tests/xor/parity-unsat-clausal.cnf", false, 2)
+}
+
+#[test]
+fn dimacs_xor_tseitin() {
+    dimacs_test("tests/xor/tseitin-unsat.cnf", false, 1)
+}
+
+#[test]
+fn xor_recover() {
+    let mut cnf = dimacs::read_dimacs("tests/xor/parity-unsat-clausal.cnf", 0).unwrap();
+    let xors = xor::recover(&mut cnf, 2);
+    println!("{}", cnf);
+    assert_eq!(14, xors);
+    assert_eq!(14, cnf.xors().len());
+    assert_eq!(20, cnf.clauses().count());
+    assert!(dpll::sat(&mut cnf, 1).is_none());
+}
+
+// Checks that enumeration produces each projected model of the cnf once, by
+// testing every assignment to the projected atoms for satisfiability
+#[cfg(test)]
+fn enumerate_test(cnf: &ast::Cnf, projection: Vec<ast::Atom>) {
+    let models: Vec<ast::Asgmt> = enumerate::models(cnf, Some(projection.clone()), 0).collect();
+    let mut expected = 0;
+    for bits in 0..(1_u32 << projection.len()) {
+        let mut asgmt = ast::Asgmt::new();
+        let mut constrained = cnf.clone();
+        for (i, &atom) in projection.iter().enumerate() {
+            let phase = bits & (1 << i) != 0;
+            asgmt.insert(atom, phase);
+            constrained.add(ast::Clause::from(vec![ast::Literal::new(phase, atom)]));
+        }
+        let sat = dpll::sat(&mut constrained, 0).is_some();
+        let found = models.iter().filter(|&model| *model == asgmt).count();
+        assert_eq!(sat as usize, found, "projected model: {}", asgmt);
+        expected += found;
+    }
+    assert_eq!(expected, models.len());
+}
+
+#[test]
+fn enumerate_mini() {
+    for path in ["tests/mini.cnf", "tests/mini2.cnf", "tests/mini3.cnf"] {
+        let cnf = dimacs::read_dimacs(path, 0).unwrap();
+        enumerate_test(&cnf, cnf.atoms().collect())
+    }
+}
+
+#[test]
+fn enumerate_projected() {
+    let cnf = dimacs::read_dimacs("tests/uf20-01000.cnf", 0).unwrap();
+    enumerate_test(&cnf, (1..=8).map(ast::Atom::new).collect());
+    let cnf = dimacs::read_dimacs("tests/xor/parity-sat.cnf", 0).unwrap();
+    enumerate_test(&cnf, (1..=10).map(ast::Atom::new).collect())
+}
+
+#[test]
+fn enumerate_exactly_k() {
+    let literals: Vec<ast::Literal> = (1..=6).map(|i| ast::Literal::new(true, ast::Atom::new(i))).collect();
+    let mut cnf = ast::Cnf::new();
+    card::exactly_k(&mut cnf, &literals, 2, card::Encoding::Totalizer);
+    let projection: Vec<ast::Atom> = literals.iter().map(|lit| lit.atom()).collect();
+    enumerate_test(&cnf, projection.clone());
+    assert_eq!(15, enumerate::models(&cnf, Some(projection), 0).count());
+    // Limits apply to the iterator
+    assert_eq!(4, enumerate::models(&cnf, None, 0).take(4).count());
+}
+
+#[test]
+fn enumerate_unsat() {
+    let cnf = dimacs::read_dimacs("tests/uuf100-430/uuf100-01.cnf", 0).unwrap();
+    assert_eq!(0, enumerate::models(&cnf, None, 0).count())
+}
+
+#[test]
+fn bignum_arithmetic() {
+    let a = bignum::BigUint::from(u64::MAX);
+    let b = &a * &a + bignum::BigUint::one();
+    assert_eq!("340282366920938463426481119284349108226", b.to_string());
+    assert_eq!("1267650600228229401496703205376", bignum::BigUint::pow2(100).to_string());
+    assert_eq!("0", bignum::BigUint::zero().to_string());
+    assert!(a < b);
+}
+
+#[cfg(test)]
+fn count_test<P>(path: P, count: &str) where P: AsRef<std::path::Path> {
+    let result = dimacs::read_dimacs_count_and_print(path, 1);
+    if let Err(err) = &result {
+        eprintln!("Error: {}", err);
+    }
+    assert_eq!(count, result.unwrap().to_string())
+}
+
+#[test]
+fn count_mini() {
+    count_test("tests/mini.cnf", "6")
+}
+
+#[test]
+fn count_uf100_01() {
+    count_test("tests/uf100-430/uf100-01.cnf", "314");
+    let cnf = dimacs::read_dimacs("tests/uf100-430/uf100-01.cnf", 0).unwrap();
+    assert_eq!(314, enumerate::models(&cnf, None, 0).count())
+}
+
+#[test]
+fn count_uuf100_01() {
+    count_test("tests/uuf100-430/uuf100-01.cnf", "0")
+}
+
+#[test]
+fn count_xor() {
+    count_test("tests/xor/parity-sat.cnf", "5")
+}
+
+#[test]
+fn count_free_atoms() {
+    count_test("tests/count/free-atoms.cnf", "633825300114114700748351602688")
+}
+
+#[cfg(test)]
+fn approx_count_test<P>(path: P, projection: Option<Vec<ast::Atom>>, count: f64) where P: AsRef<std::path::Path> {
+    let cnf = dimacs::read_dimacs(path, 0).unwrap();
+    let epsilon = 0.8;
+    let estimate = approxmc::approx_count(&cnf, projection, epsilon, 0.5, 1, 1).unwrap().to_f64();
+    println!("count: {}, estimate: {}", count, estimate);
+    assert!(count / (1.0 + epsilon) <= estimate && estimate <= count * (1.0 + epsilon))
+}
+
+#[test]
+fn approx_count_exact() {
+    // Counts below the threshold are found exactly
+    let cnf = dimacs::read_dimacs("tests/mini.cnf", 0).unwrap();
+    assert_eq!(Ok(bignum::BigUint::from(6)), approxmc::approx_count(&cnf, None, 0.8, 0.2, 1, 1));
+    let cnf = dimacs::read_dimacs("tests/uuf100-430/uuf100-01.cnf", 0).unwrap();
+    assert!(approxmc::approx_count(&cnf, None, 0.8, 0.2, 1, 1).unwrap().is_zero());
+}
+
+#[test]
+fn approx_count_projected() {
+    // Three atoms with four models, and 17 free atoms
+    approx_count_test("tests/count/free-atoms.cnf", Some((1..=20).map(ast::Atom::new).collect()), 4.0 * 2_f64.powi(17))
+}
+
+#[test]
+fn approx_count_all_hashes() {
+    // More models than the threshold times 2^8, so that galloping up from one
+    // hash passes the 15 atoms before it finds a small cell
+    approx_count_test("tests/count/one-clause.cnf", None, 28672.0)
+}
+
+#[test]
+fn approx_count_uf100_03() {
+    approx_count_test("tests/uf100-430/uf100-03.cnf", None, 7064.0)
+}
+
+#[test]
+fn sample_uniform() {
+    // Few enough models to sample from directly
+    let literals: Vec<ast::Literal> = (1..=6).map(|i| ast::Literal::new(true, ast::Atom::new(i))).collect();
+    let mut cnf = ast::Cnf::new();
+    card::exactly_k(&mut cnf, &literals, 2, card::Encoding::SequentialCounter);
+    let projection: Vec<ast::Atom> = literals.iter().map(|lit| lit.atom()).collect();
+    let mut frequencies: std::collections::HashMap<Vec<bool>, usize> = std::collections::HashMap::new();
+    for sample in sample::samples(&cnf, Some(projection.clone()), 16.0, 1, 1).unwrap().take(1500) {
+        let phases: Vec<bool> = projection.iter().map(|atom| sample.get(atom).unwrap()).collect();
+        assert_eq!(2, phases.iter().filter(|&&phase| phase).count());
+        *frequencies.entry(phases).or_default() += 1;
+    }
+    println!("{:?}", frequencies);
+    assert_eq!(15, frequencies.len());
+    assert!(frequencies.values().all(|frequency| (50..=150).contains(frequency)));
+    assert!(sample::samples(&cnf, None, 1.5, 1, 1).is_err());
+}
+
+#[test]
+fn sample_hashed() {
+    let cnf = dimacs::read_dimacs("tests/count/free-atoms.cnf", 0).unwrap();
+    let projection: Vec<ast::Atom> = (1..=20).map(ast::Atom::new).collect();
+    let samples: Vec<ast::Asgmt> = sample::samples(&cnf, Some(projection.clone()), 16.0, 7, 1).unwrap().take(5).collect();
+    assert_eq!(5, samples.len());
+    for sample in samples.iter() {
+        assert_eq!(projection.len(), sample.atoms().len());
+        assert_eq!(Some(true), cnf.eval(sample));
+    }
+    // Samples are reproducible from the seed
+    assert_eq!(samples, sample::samples(&cnf, Some(projection), 16.0, 7, 0).unwrap().take(5).collect::<Vec<_>>());
+}
+
+#[cfg(test)]
+fn sls_test(algorithm: sls::Algorithm) {
+    let params = sls::Params { algorithm, ..sls::Params::default() };
+    for path in ["tests/uf250/uf250-01.cnf", "tests/uf100-430/uf100-01.cnf", "tests/xor/parity-sat.cnf"] {
+        let cnf = dimacs::read_dimacs(path, 0).unwrap();
+        let asgmt = sls::solve(&cnf, &params, 1);
+        assert!(asgmt.is_some());
+        assert_eq!(Some(true), cnf.eval(&asgmt.unwrap()));
+    }
+    // Local search cannot show unsatisfiability, so it gives up at the limits
+    let params = sls::Params { max_flips: 1000, max_tries: 2, ..params };
+    let cnf = dimacs::read_dimacs("tests/uuf100-430/uuf100-01.cnf", 0).unwrap();
+    assert!(sls::solve(&cnf, &params, 1).is_none());
+}
+
+#[test]
+fn sls_walksat() {
+    sls_test(sls::Algorithm::WalkSat)
+}
+
+#[test]
+fn sls_probsat() {
+    sls_test(sls::Algorithm::ProbSat)
+}
+
+#[test]
+fn sls_ccanr() {
+    sls_test(sls::Algorithm::CcAnr)
+}
+
+#[test]
+fn lookahead() {
+    for (path, sat) in [
+        ("tests/mini.cnf", true),
+        ("tests/uf100-430/uf100-01.cnf", true),
+        ("tests/uf250/uf250-01.cnf", true),
+        ("tests/uuf100-430/uuf100-01.cnf", false),
+        ("tests/xor/parity-sat.cnf", true),
+        ("tests/xor/parity-unsat.cnf", false),
+    ] {
+        let cnf = dimacs::read_dimacs(path, 0).unwrap();
+        let asgmt = lookahead::sat(&cnf, 1);
+        assert_eq!(sat, asgmt.is_some());
+        if let Some(asgmt) = asgmt {
+            assert_eq!(Some(true), cnf.eval(&asgmt));
+        }
+    }
+}
+
+#[cfg(test)]
+fn cube_and_conquer_test<P>(path: P, sat: bool) where P: AsRef<std::path::Path> {
+    let cnf = dimacs::read_dimacs(path, 0).unwrap();
+    let cubes = lookahead::cubes(&cnf, None, 16, 1);
+    assert!(cubes.len() <= 16);
+    let icnf = std::env::temp_dir().join(format!("satellite-cube-{}.icnf", std::process::id()));
+    dimacs::write_icnf(&mut std::fs::File::create(&icnf).unwrap(), &cnf, &cubes).unwrap();
+    let result = dimacs::read_icnf_conquer_and_print(&icnf, 1);
+    std::fs::remove_file(&icnf).unwrap();
+    let asgmt = result.unwrap();
+    assert_eq!(sat, asgmt.is_some());
+    if let Some(asgmt) = asgmt {
+        assert_eq!(Some(true), cnf.eval(&asgmt));
+    }
+}
+
+#[test]
+fn cube_and_conquer() {
+    cube_and_conquer_test("tests/uf100-430/uf100-01.cnf", true);
+    cube_and_conquer_test("tests/uuf100-430/uuf100-01.cnf", false);
+    cube_and_conquer_test("tests/xor/parity-sat.cnf", true);
+    // Cubes stop at the depth
+    let cnf = dimacs::read_dimacs("tests/uf250/uf250-01.cnf", 0).unwrap();
+    let cubes = lookahead::cubes(&cnf, Some(3), 1000, 1);
+    assert!(!cubes.is_empty() && cubes.len() <= 8);
+    assert!(cubes.iter().all(|cube| cube.len() <= 3));
+}
+
+#[test]
+fn portfolio() {
+    for share in [false, true] {
+        for (path, sat) in [
+            ("tests/uf250/uf250-01.cnf", true),
+            ("tests/uuf100-430/uuf100-01.cnf", false),
+            ("tests/xor/parity-unsat.cnf", false),
+        ] {
+            let cnf = dimacs::read_dimacs(path, 0).unwrap();
+            let asgmt = portfolio::sat(&mut cnf.clone(), 4, share, 1);
+            assert_eq!(sat, asgmt.is_some());
+            if let Some(asgmt) = asgmt {
+                assert_eq!(Some(true), cnf.eval(&asgmt));
+            }
+        }
+    }
+}
+
+#[test]
+fn preprocess_models() {
+    for (path, sat) in [
+        ("tests/uf250/uf250-01.cnf", true),
+        ("tests/uf100-430/uf100-02.cnf", true),
+        ("tests/uf20-01000.cnf", true),
+        ("tests/xor/parity-sat.cnf", true),
+        ("tests/uuf100-430/uuf100-02.cnf", false),
+    ] {
+        let cnf = dimacs::read_dimacs(path, 0).unwrap();
+        let asgmt = dpll::sat(&mut cnf.clone(), 1);
+        assert_eq!(sat, asgmt.is_some());
+        // Eliminated atoms are reconstructed
+        if let Some(asgmt) = asgmt {
+            assert_eq!(Some(true), cnf.eval(&asgmt));
+        }
+    }
+}
+
+#[test]
+fn preprocess_dimacs() {
+    let mut cnf = dimacs::read_dimacs("tests/count/free-atoms.cnf", 0).unwrap();
+    let simplifier = dpll::preprocess(&mut cnf, &simplify::STEPS, 1).unwrap();
+    // Every atom is eliminated, though still declared
+    assert!(cnf.is_empty());
+    let path = std::env::temp_dir().join(format!("satellite-preprocess-{}.cnf", std::process::id()));
+    dimacs::write_dimacs(&mut std::fs::File::create(&path).unwrap(), &cnf).unwrap();
+    let read = dimacs::read_dimacs(&path, 0);
+    std::fs::remove_file(&path).unwrap();
+    let read = read.unwrap();
+    assert_eq!(100, read.declared_atoms().count());
+    let mut asgmt = dpll::Solver::new(&read, 0).solve().unwrap();
+    simplifier.extend(&mut asgmt);
+    assert_eq!(Some(true), dimacs::read_dimacs("tests/count/free-atoms.cnf", 0).unwrap().eval(&asgmt));
+    // Clausal xors stay clauses, as DIMACS has no xors
+    let mut cnf = cnf_of(&[&[1, 2, 3], &[1, -2, -3], &[-1, 2, -3], &[-1, -2, 3]]);
+    dpll::preprocess(&mut cnf, &[simplify::Step::Subsume], 0).unwrap();
+    assert_eq!((4, 0), (cnf.len(), cnf.xors().count()));
+}
+
+#[test]
+fn clause_normal_form() {
+    let mut clause = ast::Clause::from(vec![literal(3), literal(-1), literal(3), literal(2)]);
+    assert!(!clause.is_normal());
+    assert!(clause.normalize());
+    assert!(clause.is_normal());
+    assert_eq!(vec![literal(-1), literal(2), literal(3)], clause.literals().cloned().collect::<Vec<_>>());
+    // Tautologies are trivially satisfied, rather than unsatisfiable
+    let mut cnf = cnf_of(&[&[1, 2, -1], &[-2]]);
+    assert!(!ast::Clause::from(vec![literal(1), literal(2), literal(-1)]).normalize());
+    assert!(dpll::sat(&mut cnf.clone(), 0).is_some());
+    let asgmt = cnf.normalize().unwrap();
+    assert!(cnf.is_empty());
+    assert_eq!(Some(false), asgmt.get(&ast::Atom::new(2)));
+}
+
+#[test]
+fn subsumption() {
+    let cnf = cnf_of(&[&[1, 2, 3], &[1, 2], &[-1, 2], &[-2, 5], &[3, 4, -5]]);
+    let mut simplifier = simplify::Simplifier::new(&cnf, 0);
+    simplifier.run(&[simplify::Step::Subsume]);
+    // (1 2) subsumes (1 2 3) and strengthens (!1 2) to the unit 2, which then
+    // propagates
+    let simplified = simplifier.cnf();
+    let clauses: Vec<Vec<ast::Literal>> = simplified.clauses().map(|clause| clause.literals().cloned().collect()).collect();
+    assert_eq!(3, clauses.len());
+    assert!(clauses.contains(&vec![literal(2)]));
+    assert!(clauses.contains(&vec![literal(5)]));
+    assert!(clauses.contains(&vec![literal(3), literal(4)]));
+}
+
+#[test]
+fn probing() {
+    let cnf = cnf_of(&[&[1, 2], &[1, -2], &[-1, 3, 4]]);
+    let mut simplifier = simplify::Simplifier::new(&cnf, 0);
+    simplifier.run(&[simplify::Step::Probe]);
+    // Assigning !1 propagates both 2 and !2, so 1 is a failed literal's
+    // inversion
+    let simplified = simplifier.cnf();
+    let clauses: Vec<Vec<ast::Literal>> = simplified.clauses().map(|clause| clause.literals().cloned().collect()).collect();
+    assert_eq!(2, clauses.len());
+    assert!(clauses.contains(&vec![literal(1)]));
+    assert!(clauses.contains(&vec![literal(3), literal(4)]));
+}
+
+#[test]
+fn equivalent_literals() {
+    let cnf = cnf_of(&[&[-1, 2], &[1, -2], &[-2, -3], &[2, 3], &[1, 4, 5], &[3, -4, 6]]);
+    let mut simplifier = simplify::Simplifier::new(&cnf, 0);
+    simplifier.run(&[simplify::Step::Substitute]);
+    // 1, 2 and !3 are equivalent, so 1 replaces the others
+    let simplified = simplifier.cnf();
+    let atoms: std::collections::BTreeSet<usize> = simplified.clauses().flat_map(|clause| clause.literals()).map(|lit| lit.atom().index()).collect();
+    assert_eq!(std::collections::BTreeSet::from([1, 4, 5, 6]), atoms);
+    assert_eq!(2, simplified.clauses().count());
+    let mut asgmt = dpll::Solver::new(&simplified, 0).solve().unwrap();
+    simplifier.extend(&mut asgmt);
+    assert_eq!(Some(true), cnf.eval(&asgmt));
+}
+
+#[test]
+fn blocked_clauses() {
+    let steps = [simplify::Step::Block, simplify::Step::Cover, simplify::Step::Eliminate];
+    for entry in std::fs::read_dir("tests/uf100-430").unwrap() {
+        let path = entry.unwrap().path();
+        let original = dimacs::read_dimacs(&path, 0).unwrap();
+        let mut cnf = original.clone();
+        let simplifier = dpll::preprocess(&mut cnf, &steps, 0).unwrap();
+        let mut asgmt = dpll::Solver::new(&cnf, 0).solve().unwrap();
+        simplifier.extend(&mut asgmt);
+        assert_eq!(Some(true), original.eval(&asgmt), "{}", path.display());
+    }
+}
+
+#[test]
+fn vivification() {
+    for (path, sat) in [
+        ("tests/uf100-430/uf100-03.cnf", true),
+        ("tests/uuf100-430/uuf100-03.cnf", false),
+        ("tests/xor/parity-sat.cnf", true),
+    ] {
+        let cnf = dimacs::read_dimacs(path, 0).unwrap();
+        let mut solver = dpll::Solver::new(&cnf, 0);
+        solver.configure(dpll::Config { vivify_effort: 1.0, vivify_irredundant: true, ..dpll::Config::default() });
+        let asgmt = solver.solve();
+        assert_eq!(sat, asgmt.is_some());
+        if let Some(asgmt) = asgmt {
+            assert_eq!(Some(true), cnf.eval(&asgmt));
+        }
+    }
+}
+
+#[test]
+fn mus() {
+    let original = dimacs::read_dimacs("tests/uuf100-430/uuf100-01.cnf", 0).unwrap();
+    let clauses: Vec<ast::Clause> = original.clauses().cloned().collect();
+    for algorithm in [mus::Algorithm::Deletion, mus::Algorithm::QuickXplain] {
+        let mus = mus::mus(&original, algorithm, 1).unwrap();
+        let subset = |skipped: Option<usize>| {
+            ast::Cnf::from(mus.iter().filter(|&&index| Some(index) != skipped).map(|&index| clauses[index].clone()).collect::<Vec<_>>())
+        };
+        assert!(dpll::Solver::new(&subset(None), 0).solve().is_none());
+        // Every clause is necessary
+        for &index in mus.iter() {
+            assert!(dpll::Solver::new(&subset(Some(index)), 0).solve().is_some());
+        }
+    }
+    assert_eq!(None, mus::mus(&dimacs::read_dimacs("tests/uf20-01000.cnf", 0).unwrap(), mus::Algorithm::Deletion, 0));
+}
+
+#[test]
+fn group_mus() {
+    for path in ["tests/gcnf/small.gcnf", "tests/gcnf/uuf100-01.gcnf"] {
+        let (hard, groups) = dimacs::read_gcnf(path, 0).unwrap();
+        for algorithm in [mus::Algorithm::Deletion, mus::Algorithm::QuickXplain] {
+            let mus = mus::Extractor::new(&hard, groups.clone(), 1).mus(algorithm).unwrap();
+            let subset = |skipped: Option<usize>| {
+                let mut cnf = hard.clone();
+                for &group in mus.iter().filter(|&&group| Some(group) != skipped) {
+                    for clause in groups[group].iter() {
+                        cnf.add(clause.clone());
+                    }
+                }
+                cnf
+            };
+            assert!(dpll::Solver::new(&subset(None), 0).solve().is_none());
+            // Every group is necessary
+            for &group in mus.iter() {
+                assert!(dpll::Solver::new(&subset(Some(group)), 0).solve().is_some());
+            }
+        }
+    }
+}
+
+#[test]
+fn mcs() {
+    let (hard, groups) = dimacs::read_gcnf("tests/gcnf/small.gcnf", 0).unwrap();
+    for algorithm in [mcs::Algorithm::Cld, mcs::Algorithm::LinearSearch] {
+        let mut mcses: Vec<Vec<usize>> = mcs::Mcses::new(&hard, groups.clone(), algorithm, 0).collect();
+        mcses.sort();
+        assert_eq!(vec![vec![0], vec![1, 2]], mcses);
+    }
+    let original = dimacs::read_dimacs("tests/uuf100-430/uuf100-01.cnf", 0).unwrap();
+    let clauses: Vec<ast::Clause> = original.clauses().cloned().collect();
+    for algorithm in [mcs::Algorithm::Cld, mcs::Algorithm::LinearSearch] {
+        for mcs in mcs::mcses(&original, algorithm, 0).take(3) {
+            let without = |kept: Option<usize>| {
+                ast::Cnf::from((0..clauses.len())
+                    .filter(|&index| Some(index) == kept || !mcs.contains(&index))
+                    .map(|index| clauses[index].clone())
+                    .collect::<Vec<_>>())
+            };
+            assert!(dpll::Solver::new(&without(None), 0).solve().is_some());
+            // Keeping any clause of it leaves the rest unsatisfiable
+            for &index in mcs.iter() {
+                assert!(dpll::Solver::new(&without(Some(index)), 0).solve().is_none());
+            }
+        }
+    }
+}
+
+#[test]
+fn backbone() {
+    for path in ["tests/mini.cnf", "tests/xor/parity-sat.cnf", "tests/uf100-430/uf100-01.cnf"] {
+        let cnf = dimacs::read_dimacs(path, 0).unwrap();
+        let atoms: Vec<ast::Atom> = cnf.atoms().collect();
+        // The literals of the first model which every other model shares
+        let models: Vec<ast::Asgmt> = enumerate::models(&cnf, Some(atoms.clone()), 0).collect();
+        let mut expected = ast::Asgmt::new();
+        for &atom in atoms.iter() {
+            if models.iter().all(|model| model.get(&atom) == models[0].get(&atom)) {
+                expected.insert(atom, models[0].get(&atom).unwrap());
+            }
+        }
+        for rotate in [false, true] {
+            assert_eq!(Some(expected.clone()), backbone::backbone(&cnf, rotate, 1));
+        }
+    }
+    assert_eq!(None, backbone::backbone(&dimacs::read_dimacs("tests/uuf100-430/uuf100-01.cnf", 0).unwrap(), true, 0));
+}
+
+#[test]
+fn minimize() {
+    for path in ["tests/mini.cnf", "tests/xor/parity-sat.cnf", "tests/uf100-430/uf100-01.cnf", "tests/uf100-430/uf100-02.cnf"] {
+        let cnf = dimacs::read_dimacs(path, 0).unwrap();
+        let satisfied = |clause: &ast::Clause, asgmt: &ast::Asgmt| {
+            clause.literals().any(|literal| asgmt.get(&literal.atom()) == Some(literal.phase()))
+        };
+        // Every clause holds, but not once any literal of the implicant is dropped
+        let model = dpll::sat(&mut cnf.clone(), 0).unwrap();
+        let implicant = minimize::prime_implicant(&cnf, &model);
+        assert!(cnf.clauses().all(|clause| satisfied(clause, &implicant)));
+        assert!(cnf.xors().all(|xor| xor.eval(&implicant) == Some(true)));
+        for atom in implicant.atoms() {
+            let mut smaller = implicant.clone();
+            smaller.remove(&atom);
+            assert!(cnf.xors().any(|xor| xor.atoms().any(|&other| other == atom))
+                || cnf.clauses().any(|clause| !satisfied(clause, &smaller)));
+        }
+        // No model has only some of the true atoms of the minimal one
+        let minimal = minimize::minimal_model(&cnf, 0).unwrap();
+        assert_eq!(Some(true), cnf.eval(&minimal));
+        let mut smaller = cnf.clone();
+        let mut true_atoms = Vec::new();
+        for atom in cnf.atoms() {
+            if minimal.get(&atom) == Some(true) {
+                true_atoms.push(ast::Literal::new(false, atom));
+            } else {
+                smaller.add(ast::Clause::from(vec![ast::Literal::new(false, atom)]));
+            }
+        }
+        smaller.add(ast::Clause::from(true_atoms));
+        assert!(dpll::Solver::new(&smaller, 0).solve().is_none());
+    }
+}
+
+#[cfg(test)]
+fn qbf_value(prefix: &[(qbf::Quantifier, ast::Atom)], matrix: &ast::Cnf, asgmt: &mut ast::Asgmt) -> bool {
+    let Some(((quantifier, atom), rest)) = prefix.split_first() else {
+        return matrix.eval(asgmt) == Some(true)
+    };
+    let mut values = [false, true].into_iter().map(|phase| {
+        asgmt.insert(*atom, phase);
+        let value = qbf_value(rest, matrix, asgmt);
+        asgmt.remove(atom);
+        value
+    });
+    match quantifier {
+        qbf::Quantifier::Exists => values.any(|value| value),
+        qbf::Quantifier::Forall => values.all(|value| value),
+    }
+}
+
+#[test]
+fn qbf() {
+    for (path, value) in [("tests/qbf/true.qdimacs", true), ("tests/qbf/false.qdimacs", false), ("tests/qbf/certificate.qdimacs", false)] {
+        let qbf = dimacs::read_qdimacs(path, 0).unwrap();
+        assert_eq!(value, qbf::solve(&qbf, 0).0);
+    }
+    // Random qbfs against their value by expansion, with the certificate a
+    // winning move of the outermost block
+    let mut rng = util::Rng::new(1);
+    for _ in 0..200 {
+        let atoms: Vec<ast::Atom> = (1..=8).map(ast::Atom::new).collect();
+        let mut matrix = ast::Cnf::new();
+        for _ in 0..12 {
+            matrix.add(ast::Clause::from((0..3)
+                .map(|_| ast::Literal::new(rng.next_bool(), atoms[rng.below(atoms.len())]))
+                .collect::<Vec<_>>()));
+        }
+        let quantifiers: Vec<qbf::Quantifier> = atoms.iter()
+            .map(|_| if rng.next_bool() { qbf::Quantifier::Exists } else { qbf::Quantifier::Forall })
+            .collect();
+        let prefix: Vec<(qbf::Quantifier, ast::Atom)> = quantifiers.into_iter().zip(atoms.iter().copied()).collect();
+        let qbf = qbf::Qbf {
+            prefix: prefix.iter().map(|&(quantifier, atom)| (quantifier, vec![atom])).collect(),
+            matrix: matrix.clone(),
+        };
+        let (value, certificate) = qbf::solve(&qbf, 0);
+        assert_eq!(qbf_value(&prefix, &matrix, &mut ast::Asgmt::new()), value);
+        let outer = prefix[0].0;
+        assert_eq!(certificate.is_some(), value == (outer == qbf::Quantifier::Exists));
+        if let Some(mut certificate) = certificate {
+            let block = prefix.iter().take_while(|(quantifier, _)| *quantifier == outer).count();
+            assert_eq!(block, certificate.atoms().len());
+            assert_eq!(value, qbf_value(&prefix[block..], &matrix, &mut certificate));
+        }
+    }
+}
+
+#[test]
+fn difference_logic() {
+    for (path, sat) in [("tests/smt/jobshop.smt2", true), ("tests/smt/cycle.smt2", true), ("tests/smt/unsat.smt2", false)] {
+        let problem = smtlib::read_idl(path, 0).unwrap();
+        assert_eq!(sat, smtlib::solve_idl(&problem, 0).is_some());
+    }
+    // Random clauses over atoms of constraints between three variables and
+    // zero, whose solutions, if any, include one with values of at most nine
+    let mut rng = util::Rng::new(1);
+    for _ in 0..100 {
+        let constraints: Vec<idl::Constraint> = (0..8)
+            .map(|_| idl::Constraint { x: rng.below(4), y: rng.below(4), bound: rng.below(7) as i64 - 3 })
+            .collect();
+        let mut cnf = ast::Cnf::new();
+        for _ in 0..10 {
+            cnf.add(ast::Clause::from((0..2)
+                .map(|_| ast::Literal::new(rng.next_bool(), ast::Atom::new(rng.below(constraints.len()) as u32 + 1)))
+                .collect::<Vec<_>>()));
+        }
+        let mut theory = idl::DifferenceLogic::new(4, 0);
+        for (i, &constraint) in constraints.iter().enumerate() {
+            theory.define(ast::Atom::new(i as u32 + 1), constraint);
+        }
+        let consistent = |values: &[i64]| {
+            cnf.clauses().all(|clause| clause.literals().any(|literal| {
+                constraints[literal.atom().index() - 1].eval(values) == literal.phase()
+            }))
+        };
+        let mut solver = dpll::Solver::with_theory(&cnf, theory, 0);
+        match solver.solve() {
+            Some(asgmt) => {
+                let values = solver.theory().model();
+                assert_eq!(Some(true), cnf.eval(&asgmt));
+                for atom in asgmt.atoms() {
+                    assert_eq!(asgmt.get(&atom), Some(constraints[atom.index() - 1].eval(&values)));
+                }
+            },
+            None => {
+                let range = || -9..=9;
+                assert!(!itertools::iproduct!(range(), range(), range()).any(|(x, y, z)| consistent(&[0, x, y, z])));
+            },
+        }
+    }
+}
+
+#[test]
+fn bit_blasting() {
+    // Operations on inputs fixed by unit clauses, rather than on constants,
+    // which would be folded away, against wrapping arithmetic of six bits
+    let width = 6;
+    let mask = (1 << width) - 1;
+    let signed = |value: u64| ((value << (64 - width)) as i64) >> (64 - width);
+    let mut rng = util::Rng::new(1);
+    for _ in 0..40 {
+        let (x, y) = (rng.below(1 << width) as u64, rng.below(1 << width) as u64);
+        let mut blaster = bv::Blaster::new(ast::Cnf::new());
+        let (a, b) = (blaster.fresh_bits(width), blaster.fresh_bits(width));
+        for (bits, value) in [(&a, x), (&b, y)] {
+            for (i, &literal) in bits.iter().enumerate() {
+                blaster.cnf.add(ast::Clause::from(vec![if (value >> i) & 1 == 1 { literal } else { literal.inversion() }]));
+            }
+        }
+        let shift = |value: u64, f: &dyn Fn(u64) -> u64| if y < width as u64 { f(value) & mask } else { 0 };
+        let bits = [
+            (blaster.add(&a, &b), (x + y) & mask),
+            (blaster.sub(&a, &b), x.wrapping_sub(y) & mask),
+            (blaster.neg(&a), x.wrapping_neg() & mask),
+            (blaster.mul(&a, &b), (x * y) & mask),
+            (blaster.shl(&a, &b), shift(x, &|x| x << y)),
+            (blaster.lshr(&a, &b), shift(x, &|x| x >> y)),
+            (blaster.ashr(&a, &b), (signed(x) >> y.min(63)) as u64 & mask),
+            (bv::concat(&bv::extract(&a, 1, 0), &bv::extract(&b, 5, 2)), ((x & 3) << 4) | (y >> 2)),
+        ];
+        let literals = [
+            (blaster.eq(&a, &b), x == y),
+            (blaster.ult(&a, &b), x < y),
+            (blaster.ule(&a, &b), x <= y),
+            (blaster.slt(&a, &b), signed(x) < signed(y)),
+            (blaster.sle(&a, &b), signed(x) <= signed(y)),
+        ];
+        let asgmt = dpll::Solver::new(&blaster.cnf, 0).solve().unwrap();
+        for (bits, value) in bits {
+            assert_eq!(Some(value), bv::decode(&bits, &asgmt).to_u64());
+        }
+        for (literal, value) in literals {
+            assert_eq!(Some(value), asgmt.get(&literal.atom()).map(|phase| phase == literal.phase()));
+        }
+    }
+    // Factors of 143, found by multiplication in reverse
+    let problem = smtlib::read_bv("tests/smt/bv-factor.smt2", 0).unwrap();
+    let model = smtlib::solve_bv(&problem, 0).unwrap();
+    let factors: Vec<u64> = model.bvs.iter().map(|(_, value)| value.to_u64().unwrap()).collect();
+    assert_eq!(vec![11, 13], factors);
+    for (path, sat) in [("tests/smt/bv-overflow.smt2", true), ("tests/smt/bv-shift.smt2", false)] {
+        assert_eq!(sat, smtlib::solve_bv(&smtlib::read_bv(path, 0).unwrap(), 0).is_some());
+    }
+}
+
+#[test]
+fn bmc() {
+    // A counter of three bits, counting up when enabled, is first seven
+    // after seven steps
+    for path in ["tests/aiger/counter.aag", "tests/aiger/counter.aig"] {
+        let aig = aiger::read_aiger(path, 0).unwrap();
+        assert_eq!(Ok(None), bmc::check(&aig, 6, 0));
+        let trace = bmc::check(&aig, 20, 0).unwrap().unwrap();
+        assert_eq!(8, trace.inputs.len());
+        assert_eq!(Some(true), aig.simulate(&trace));
+        let mut witness = Vec::new();
+        aiger::write_witness(&mut witness, &trace).unwrap();
+        assert_eq!("1\nb0\n000\n1\n1\n1\n1\n1\n1\n1\n", &String::from_utf8(witness).unwrap()[..23]);
+    }
+    // Constrained to stay below four, it never gets there
+    let aig = aiger::read_aiger("tests/aiger/counter-constrained.aag", 0).unwrap();
+    assert_eq!(1, aig.properties().len());
+    assert_eq!(Ok(None), bmc::check(&aig, 20, 0));
+}
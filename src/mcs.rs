@@ -0,0 +1,149 @@
+use crate::ast;
+use crate::dpll;
+use crate::mus;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Algorithm {
+    // Adds the groups a model falsifies one at a time to those it satisfies,
+    // keeping each which the others allow
+    LinearSearch,
+    // Clause D: checks whether the satisfied groups allow any of the falsified
+    // ones at all, by a clause over their selectors, until they allow none
+    // (Marques-Silva et al.)
+    #[value(name = "cld")]
+    Cld,
+}
+
+// The minimal correction sets of groups of clauses, along with hard clauses
+// (and xors) which always hold: the minimal sets of groups whose removal
+// leaves the rest satisfiable. Each is the complement of a maximal set of
+// groups which some model satisfies, grown from that of a first model, and is
+// then blocked by a clause requiring one of its groups. The only one of
+// satisfiable groups is empty.
+pub struct Mcses {
+    solver: dpll::Solver,
+    groups: Vec<Vec<ast::Clause>>,
+    selectors: Vec<ast::Literal>,
+    // The atom of the next clause D to be enabled by assumption
+    next_atom: ast::Atom,
+    algorithm: Algorithm,
+    done: bool,
+    calls: usize,
+    verbosity: usize,
+}
+
+impl Mcses {
+    pub fn new(hard: &ast::Cnf, groups: Vec<Vec<ast::Clause>>, algorithm: Algorithm, verbosity: usize) -> Self {
+        let (mut cnf, selectors) = mus::selector_cnf(hard, &groups);
+        Self {
+            solver: dpll::Solver::new(&cnf, verbosity.saturating_sub(1)),
+            groups,
+            selectors,
+            next_atom: cnf.fresh_atom(),
+            algorithm,
+            done: false,
+            calls: 0,
+            verbosity,
+        }
+    }
+
+    // Checks the groups together with the hard clauses and any enabled clause D
+    fn check(&mut self, groups: &[usize], enabled: Option<ast::Literal>) -> Option<ast::Asgmt> {
+        self.calls += 1;
+        let assumptions: Vec<ast::Literal> = enabled.into_iter()
+            .chain(groups.iter().map(|&group| self.selectors[group]))
+            .collect();
+        self.solver.solve_with(&assumptions)
+    }
+
+    fn satisfied(&self, model: &ast::Asgmt) -> Vec<bool> {
+        self.groups.iter()
+            .map(|group| group.iter().all(|clause| clause.eval(model) == Some(true)))
+            .collect()
+    }
+
+    fn linear_search(&mut self, model: ast::Asgmt) -> Vec<usize> {
+        let mut satisfied = self.satisfied(&model);
+        for group in 0..self.groups.len() {
+            if satisfied[group] {
+                continue
+            }
+            let groups: Vec<usize> = (0..self.groups.len())
+                .filter(|&other| satisfied[other] || other == group)
+                .collect();
+            if let Some(model) = self.check(&groups, None) {
+                for (satisfied, now) in satisfied.iter_mut().zip(self.satisfied(&model)) {
+                    *satisfied |= now;
+                }
+            }
+        }
+        (0..self.groups.len()).filter(|&group| !satisfied[group]).collect()
+    }
+
+    fn cld(&mut self, model: ast::Asgmt) -> Vec<usize> {
+        let mut satisfied = self.satisfied(&model);
+        loop {
+            let falsified: Vec<usize> = (0..self.groups.len()).filter(|&group| !satisfied[group]).collect();
+            if falsified.is_empty() {
+                return falsified
+            }
+            // D requires one of the falsified groups while enabled, and is
+            // disabled for good afterwards
+            let enabled = ast::Literal::new(true, self.next_atom);
+            self.next_atom = ast::Atom::new(self.next_atom.index() as u32 + 1);
+            let literals: Vec<ast::Literal> = std::iter::once(enabled.inversion())
+                .chain(falsified.iter().map(|&group| self.selectors[group]))
+                .collect();
+            self.solver.add_clause(&ast::Clause::from(literals));
+            let groups: Vec<usize> = (0..self.groups.len()).filter(|&group| satisfied[group]).collect();
+            let result = self.check(&groups, Some(enabled));
+            self.solver.add_clause(&ast::Clause::from(vec![enabled.inversion()]));
+            match result {
+                None => return falsified,
+                Some(model) => {
+                    for (satisfied, now) in satisfied.iter_mut().zip(self.satisfied(&model)) {
+                        *satisfied |= now;
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl Iterator for Mcses {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+        // Any model of the hard and blocking clauses starts the search
+        let model = self.check(&[], None)?;
+        let mcs = match self.algorithm {
+            Algorithm::LinearSearch => self.linear_search(model),
+            Algorithm::Cld => self.cld(model),
+        };
+        if 1 < self.verbosity {
+            log::info!("Found an MCS of {} groups after {} checks", mcs.len(), self.calls);
+        }
+        if mcs.is_empty() {
+            self.done = true;
+        } else {
+            let blocking: Vec<ast::Literal> = mcs.iter().map(|&group| self.selectors[group]).collect();
+            self.solver.add_clause(&ast::Clause::from(blocking));
+        }
+        Some(mcs)
+    }
+}
+
+// The minimal correction sets of the clauses of the cnf, by index, with its
+// xors always holding
+pub fn mcses(cnf: &ast::Cnf, algorithm: Algorithm, verbosity: usize) -> Mcses {
+    let mut hard = cnf.clone();
+    hard.retain_clauses(|_| false);
+    let groups = cnf.clauses().map(|clause| vec![clause.clone()]).collect();
+    Mcses::new(&hard, groups, algorithm, verbosity)
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::ast;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Reduces the model of the cnf to a prime implicant: a partial assignment
+// satisfying every clause, none of whose literals can be dropped. Each literal
+// is dropped in turn if every clause it satisfies has another true literal
+// left. Atoms of xors keep their values, as xors depend on all of them.
+pub fn prime_implicant(cnf: &ast::Cnf, model: &ast::Asgmt) -> ast::Asgmt {
+    let is_true = |literal: &ast::Literal| model.get(&literal.atom()) == Some(literal.phase());
+    // The number of true literals left in each clause, and the clauses by
+    // their true literals
+    let mut counts = Vec::new();
+    let mut occurs: HashMap<ast::Literal, Vec<usize>> = HashMap::new();
+    for (index, clause) in cnf.clauses().enumerate() {
+        let true_literals: Vec<ast::Literal> = clause.literals().filter(|lit| is_true(lit)).unique().cloned().collect();
+        counts.push(true_literals.len());
+        for literal in true_literals {
+            occurs.entry(literal).or_default().push(index);
+        }
+    }
+    let frozen: Vec<ast::Atom> = cnf.xors().flat_map(|xor| xor.atoms()).copied().collect();
+    let mut implicant = ast::Asgmt::new();
+    for atom in model.atoms().into_iter().sorted() {
+        let literal = ast::Literal::new(model.get(&atom).unwrap(), atom);
+        let clauses = occurs.get(&literal).map_or(&[][..], |clauses| &clauses[..]);
+        if frozen.contains(&atom) || clauses.iter().any(|&index| counts[index] < 2) {
+            implicant.insert(atom, literal.phase());
+            continue
+        }
+        for &index in clauses {
+            counts[index] -= 1;
+        }
+    }
+    implicant
+}
+
+// A model of the cnf whose true atoms include those of no other model, or
+// None if it is UNSAT. Each model found is improved upon by fixing its false
+// atoms and requiring one of its true atoms to be false, by a clause enabled
+// by assumption, until that is UNSAT.
+pub fn minimal_model(cnf: &ast::Cnf, verbosity: usize) -> Option<ast::Asgmt> {
+    let atoms: Vec<ast::Atom> = cnf.atoms().sorted().collect();
+    let mut next_atom = cnf.next_atom();
+    let mut solver = dpll::Solver::new(cnf, verbosity.saturating_sub(1));
+    let mut model = solver.solve()?;
+    let mut improvements = 0;
+    loop {
+        let (true_atoms, false_atoms): (Vec<ast::Atom>, Vec<ast::Atom>) = atoms.iter()
+            .partition(|atom| model.get(atom) == Some(true));
+        if true_atoms.is_empty() {
+            break
+        }
+        for atom in false_atoms {
+            solver.add_clause(&ast::Clause::from(vec![ast::Literal::new(false, atom)]));
+        }
+        let enabled = ast::Literal::new(true, next_atom);
+        next_atom = ast::Atom::new(next_atom.index() as u32 + 1);
+        let literals: Vec<ast::Literal> = std::iter::once(enabled.inversion())
+            .chain(true_atoms.iter().map(|&atom| ast::Literal::new(false, atom)))
+            .collect();
+        solver.add_clause(&ast::Clause::from(literals));
+        let result = solver.solve_with(&[enabled]);
+        solver.add_clause(&ast::Clause::from(vec![enabled.inversion()]));
+        match result {
+            Some(smaller) => {
+                model = smaller;
+                improvements += 1;
+            },
+            None => break,
+        }
+    }
+    let mut minimal = ast::Asgmt::new();
+    for atom in atoms {
+        minimal.insert(atom, model.get(&atom).unwrap_or(false));
+    }
+    if 0 < verbosity {
+        log::info!("Minimal model of {} true atoms after {} improvements", minimal.atoms().iter().filter(|atom| minimal.get(atom) == Some(true)).count(), improvements);
+    }
+    Some(minimal)
+}
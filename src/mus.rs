@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use crate::ast;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Algorithm {
+    // Drops each group in turn, keeping it if the others are then satisfiable,
+    // and otherwise dropping as well every group outside the UNSAT core
+    Deletion,
+    // Divides the groups in halves, finding the groups needed from the second
+    // with the first, and then those needed from the first with these
+    // (Junker)
+    #[value(name = "quickxplain")]
+    QuickXplain,
+}
+
+// A cnf of the hard clauses and of the groups, with an atom for each group (its
+// selector) added negatively to each of its clauses, so that assuming the
+// selector enables the group. Returns it along with the selectors.
+pub fn selector_cnf(hard: &ast::Cnf, groups: &[Vec<ast::Clause>]) -> (ast::Cnf, Vec<ast::Literal>) {
+    let mut cnf = hard.clone();
+    for literal in groups.iter().flatten().flat_map(|clause| clause.literals()) {
+        cnf.declare_atom(literal.atom());
+    }
+    let mut selectors = Vec::new();
+    for group in groups {
+        let selector = ast::Literal::new(true, cnf.fresh_atom());
+        for clause in group {
+            let mut clause = clause.clone();
+            clause.add(selector.inversion());
+            cnf.add(clause);
+        }
+        selectors.push(selector);
+    }
+    (cnf, selectors)
+}
+
+// Extracts minimal unsatisfiable sets of groups of clauses, along with hard
+// clauses (and xors) which always hold. Each group is enabled by assuming its
+// selector, so that a single incremental solver checks every set of groups,
+// and its UNSAT cores say which of the groups were needed.
+pub struct Extractor {
+    solver: dpll::Solver,
+    groups: Vec<Vec<ast::Clause>>,
+    selectors: Vec<ast::Literal>,
+    // The hard clauses (None) and those of the groups, with the clauses by the
+    // literals they contain, for model rotation
+    clauses: Vec<(Option<usize>, ast::Clause)>,
+    occurs: HashMap<ast::Literal, Vec<usize>>,
+    // Atoms of the xors, which model rotation does not flip
+    frozen: Vec<ast::Atom>,
+    calls: usize,
+    verbosity: usize,
+}
+
+impl Extractor {
+    pub fn new(hard: &ast::Cnf, groups: Vec<Vec<ast::Clause>>, verbosity: usize) -> Self {
+        let (cnf, selectors) = selector_cnf(hard, &groups);
+        let clauses: Vec<(Option<usize>, ast::Clause)> = hard.clauses()
+            .map(|clause| (None, clause.clone()))
+            .chain(groups.iter().enumerate().flat_map(|(k, group)| group.iter().map(move |clause| (Some(k), clause.clone()))))
+            .collect();
+        let mut occurs: HashMap<ast::Literal, Vec<usize>> = HashMap::new();
+        for (index, (_, clause)) in clauses.iter().enumerate() {
+            for &literal in clause.literals() {
+                occurs.entry(literal).or_default().push(index);
+            }
+        }
+        Self {
+            solver: dpll::Solver::new(&cnf, verbosity.saturating_sub(1)),
+            groups,
+            selectors,
+            clauses,
+            occurs,
+            frozen: hard.xors().flat_map(|xor| xor.atoms()).copied().collect(),
+            calls: 0,
+            verbosity,
+        }
+    }
+
+    // Checks the groups together with the hard clauses, returning a model, or
+    // else the groups of an UNSAT core
+    fn check(&mut self, groups: &[usize]) -> Result<ast::Asgmt, Vec<usize>> {
+        self.calls += 1;
+        let assumptions: Vec<ast::Literal> = groups.iter().map(|&group| self.selectors[group]).collect();
+        match self.solver.solve_with(&assumptions) {
+            Some(model) => Ok(model),
+            None => {
+                let first = self.selectors.first().map_or(0, |selector| selector.atom().index());
+                Err(self.solver.core().iter().map(|selector| selector.atom().index() - first).collect())
+            },
+        }
+    }
+
+    // Makes the group hold in every later check, or in none
+    fn fix(&mut self, group: usize, enabled: bool) {
+        let selector = self.selectors[group];
+        let literal = if enabled { selector } else { selector.inversion() };
+        self.solver.add_clause(&ast::Clause::from(vec![literal]));
+    }
+
+    // Model rotation: given a model of the hard clauses and of the included
+    // groups except one, which it falsifies by a single clause, the flip of an
+    // atom of that clause which falsifies a single other included group (and no
+    // hard clause) shows that group to be necessary too, and the flipped model
+    // is rotated in turn. Returns the number of groups found necessary.
+    fn rotate(&mut self, group: usize, model: ast::Asgmt, included: &[bool], necessary: &mut [bool]) -> usize {
+        let mut found = 0;
+        let mut stack = vec![(group, model)];
+        while let Some((group, model)) = stack.pop() {
+            let falsified: Vec<&ast::Clause> = self.groups[group].iter()
+                .filter(|clause| clause.eval(&model) == Some(false))
+                .collect();
+            let [clause] = falsified[..] else {
+                continue
+            };
+            let mut rotations = Vec::new();
+            for &literal in clause.literals() {
+                if self.frozen.contains(&literal.atom()) {
+                    continue
+                }
+                let mut rotated = model.clone();
+                rotated.insert(literal.atom(), literal.phase());
+                let mut hard = false;
+                let mut groups = Vec::new();
+                for &index in self.occurs.get(&literal.inversion()).into_iter().flatten() {
+                    let (owner, clause) = &self.clauses[index];
+                    if clause.eval(&rotated) != Some(false) {
+                        continue
+                    }
+                    match *owner {
+                        None => hard = true,
+                        Some(other) if included[other] => groups.push(other),
+                        Some(_) => (),
+                    }
+                }
+                groups.sort();
+                groups.dedup();
+                if let (false, [other]) = (hard, &groups[..]) {
+                    if *other != group && !necessary[*other] {
+                        rotations.push((*other, rotated));
+                    }
+                }
+            }
+            for (other, rotated) in rotations {
+                if necessary[other] {
+                    continue
+                }
+                if 2 < self.verbosity {
+                    log::info!("Group {} is necessary by model rotation", other);
+                }
+                necessary[other] = true;
+                self.fix(other, true);
+                found += 1;
+                stack.push((other, rotated));
+            }
+        }
+        found
+    }
+
+    // Tries dropping each of the groups of the UNSAT core in turn
+    fn deletion(&mut self, core: Vec<usize>) -> Vec<usize> {
+        let mut included = vec![false; self.groups.len()];
+        for &group in core.iter() {
+            included[group] = true;
+        }
+        let mut necessary = vec![false; self.groups.len()];
+        let (mut refined, mut rotated) = (0, 0);
+        for group in core {
+            if !included[group] || necessary[group] {
+                continue
+            }
+            // The necessary groups are fixed, so need not be assumed
+            let others: Vec<usize> = (0..self.groups.len())
+                .filter(|&other| other != group && included[other] && !necessary[other])
+                .collect();
+            match self.check(&others) {
+                Ok(model) => {
+                    if 1 < self.verbosity {
+                        log::info!("Group {} is necessary", group);
+                    }
+                    necessary[group] = true;
+                    self.fix(group, true);
+                    rotated += self.rotate(group, model, &included, &mut necessary);
+                },
+                Err(core) => {
+                    // Clause-set refinement: only the groups of the core remain
+                    let mut kept = vec![false; self.groups.len()];
+                    for other in core {
+                        kept[other] = true;
+                    }
+                    for other in 0..self.groups.len() {
+                        if !included[other] || necessary[other] || kept[other] {
+                            continue
+                        }
+                        included[other] = false;
+                        self.fix(other, false);
+                        refined += 1;
+                    }
+                },
+            }
+        }
+        if 0 < self.verbosity {
+            log::info!("Dropped {} groups by refinement, and found {} necessary by rotation", refined, rotated);
+        }
+        (0..self.groups.len()).filter(|&group| included[group]).collect()
+    }
+
+    // The minimal set of the candidates which is UNSAT together with the
+    // background, given that the candidates are. The background is checked
+    // first only if it has just grown.
+    fn quickxplain(&mut self, background: &mut Vec<usize>, check: bool, candidates: &[usize]) -> Vec<usize> {
+        if check && self.check(background).is_err() {
+            return Vec::new()
+        }
+        if candidates.len() == 1 {
+            return candidates.to_vec()
+        }
+        let (first, second) = candidates.split_at(candidates.len() / 2);
+        let len = background.len();
+        background.extend(first);
+        let mut needed = self.quickxplain(background, true, second);
+        background.truncate(len);
+        background.extend(needed.iter());
+        needed.extend(self.quickxplain(background, !needed.is_empty(), first));
+        background.truncate(len);
+        needed
+    }
+
+    // A minimal unsatisfiable set of the groups, by index, or None if they are
+    // satisfiable together
+    pub fn mus(&mut self, algorithm: Algorithm) -> Option<Vec<usize>> {
+        let all: Vec<usize> = (0..self.groups.len()).collect();
+        let core = self.check(&all).err()?;
+        if 0 < self.verbosity {
+            log::info!("Initial UNSAT core of {} of {} groups", core.len(), self.groups.len());
+        }
+        let mut mus = match (algorithm, core.len()) {
+            (_, 0) => Vec::new(),
+            (Algorithm::Deletion, _) => self.deletion(core),
+            (Algorithm::QuickXplain, _) => self.quickxplain(&mut Vec::new(), false, &core),
+        };
+        mus.sort();
+        if 0 < self.verbosity {
+            log::info!("Found a MUS of {} groups after {} checks", mus.len(), self.calls);
+        }
+        Some(mus)
+    }
+}
+
+// A minimal unsatisfiable subset of the clauses of the cnf, by index, with its
+// xors always included, or None if it is satisfiable
+pub fn mus(cnf: &ast::Cnf, algorithm: Algorithm, verbosity: usize) -> Option<Vec<usize>> {
+    let mut hard = cnf.clone();
+    hard.retain_clauses(|_| false);
+    let groups = cnf.clauses().map(|clause| vec![clause.clone()]).collect();
+    Extractor::new(&hard, groups, verbosity).mus(algorithm)
+}
@@ -0,0 +1,104 @@
+use std::{fs, path};
+
+use crate::ast;
+use crate::pb;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Parses a linear sum of terms such as "+3 x1 -2 ~x4"
+fn parse_terms<'a>(tokens: impl Iterator<Item = &'a str>, verbosity: usize) -> Option<Vec<(i64, ast::Literal)>> {
+    let mut terms = Vec::new();
+    let mut tokens = tokens.peekable();
+    while let Some(coefficient) = tokens.next() {
+        let coefficient = coefficient.strip_prefix('+').unwrap_or(coefficient).parse::<i64>().ok()?;
+        let var = tokens.next()?;
+        // Non-linear terms (products of literals) are not supported
+        if tokens.peek().is_some_and(|token| token.starts_with(['x', '~'])) {
+            return None
+        }
+        let (phase, var) = match var.strip_prefix('~') {
+            Some(var) => (false, var),
+            None => (true, var),
+        };
+        let atom = ast::Atom::new(var.strip_prefix('x')?.parse::<u32>().ok()?);
+        let literal = ast::Literal::new(phase, atom);
+        if 3 < verbosity {
+            log::info!("Adding term {} {}", coefficient, literal);
+        };
+        terms.push((coefficient, literal));
+    }
+    Some(terms)
+}
+
+// Reads the linear subset of the OPB format of the pseudo-Boolean competitions
+pub fn read_opb<P>(path: P, verbosity: usize) -> Option<pb::Problem>
+where P: AsRef<path::Path> {
+    let contents = fs::read_to_string(path).ok()?;
+    let contents: String = contents.lines()
+        .filter(|line| !line.starts_with('*'))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut problem = pb::Problem {
+        constraints: Vec::new(),
+        objective: None,
+    };
+    for statement in contents.split(';') {
+        let tokens: Vec<&str> = statement.split_whitespace().collect();
+        match tokens.first() {
+            None => continue,
+            Some(&"min:") => {
+                if problem.objective.is_some() {
+                    return None
+                }
+                let objective = parse_terms(tokens[1..].iter().cloned(), verbosity)?;
+                if 3 < verbosity {
+                    log::info!("Read objective with {} terms", objective.len());
+                };
+                problem.objective = Some(objective);
+            },
+            Some(_) => {
+                let (position, relation) = tokens.iter().enumerate()
+                    .find_map(|(i, &token)| match token {
+                        ">=" => Some((i, pb::Relation::GreaterEq)),
+                        "=" => Some((i, pb::Relation::Equal)),
+                        "<=" => Some((i, pb::Relation::LessEq)),
+                        _ => None,
+                    })?;
+                let terms = parse_terms(tokens[..position].iter().cloned(), verbosity)?;
+                let bound = match tokens[position + 1..] {
+                    [bound] => bound.strip_prefix('+').unwrap_or(bound).parse::<i64>().ok()?,
+                    _ => return None,
+                };
+                if 3 < verbosity {
+                    log::info!("Read constraint with {} terms and bound {}", terms.len(), bound);
+                };
+                problem.constraints.push(pb::Constraint { terms, relation, bound });
+            },
+        }
+    }
+    Some(problem)
+}
+
+pub fn read_opb_and_minimize<P>(path: P, encoding: pb::Encoding, verbosity: usize) -> Result<Option<(ast::Asgmt, Option<i64>)>, String>
+where P: AsRef<path::Path> {
+    let problem = read_opb(path, verbosity).ok_or("Error parsing OPB file.")?;
+    if 1 < verbosity {
+        log::info!("Read {} constraints", problem.constraints.len());
+    };
+    pb::minimize(&problem, encoding, verbosity)
+}
+
+pub fn read_opb_minimize_and_print<P>(path: P, encoding: pb::Encoding, verbosity: usize) -> Result<Option<(ast::Asgmt, Option<i64>)>, String>
+where P: AsRef<path::Path> {
+    let result = read_opb_and_minimize(path, encoding, verbosity)?;
+    if let Some((asgmt, cost)) = &result {
+        if let Some(cost) = cost {
+            println!("OPTIMUM: {}", cost);
+        }
+        println!("SAT: {}", asgmt);
+    } else {
+        println!("UNSAT");
+    }
+    Ok(result)
+}
@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::ast;
+use crate::card;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Relation {
+    GreaterEq,
+    Equal,
+    LessEq,
+}
+
+// A linear constraint over literals, with arbitrary integer coefficients
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Constraint {
+    pub terms: Vec<(i64, ast::Literal)>,
+    pub relation: Relation,
+    pub bound: i64,
+}
+
+// A set of constraints with an optional objective to be minimized
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Problem {
+    pub constraints: Vec<Constraint>,
+    pub objective: Option<Vec<(i64, ast::Literal)>>,
+}
+
+// A constraint Σ a_i l_i >= k, where every a_i is positive and at most k, and
+// every atom occurs at most once.
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Normal {
+    terms: Vec<(u64, ast::Literal)>,
+    bound: u64,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Encoding {
+    // Use a cardinality encoding when all coefficients are one, a sorter when
+    // the unary expansion is small, and otherwise a BDD unless it grows too
+    // large, in which case an adder.
+    Auto,
+    Bdd,
+    Adder,
+    // Falls back to a BDD or an adder, as the automatic encoding does, when
+    // the unary expansion is too long
+    Sorter,
+}
+
+// Past this many nodes the automatic encoding gives up on BDDs
+const BDD_NODE_LIMIT: usize = 1 << 14;
+
+// Past this many nodes even the BDD encoding fails
+const BDD_MAX_NODES: usize = 1 << 20;
+
+// Past this many literals in the unary expansion sorters are not used
+const SORTER_UNARY_LIMIT: u64 = 1 << 16;
+
+fn add_clause(cnf: &mut ast::Cnf, literals: Vec<ast::Literal>) {
+    cnf.add(ast::Clause::from(literals))
+}
+
+fn fresh_literal(cnf: &mut ast::Cnf) -> ast::Literal {
+    ast::Literal::new(true, cnf.fresh_atom())
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Rewrites a >= constraint so that all coefficients are positive, using
+// a·l = a - a·!l for negative a. Sums of 64-bit coefficients cannot overflow
+// 128 bits, but the normal constraint must fit in 64 bits, or else this fails.
+// Returns None if the constraint is trivially false; an empty list of terms
+// with bound 0 if it is trivially true.
+fn normalize_geq(terms: &[(i128, ast::Literal)], bound: i128) -> Result<Option<Normal>, String> {
+    // Merge occurrences of the same atom, as a coefficient on the positive literal
+    let mut coefficients: HashMap<ast::Atom, i128> = HashMap::new();
+    let mut bound = bound;
+    for &(coefficient, literal) in terms {
+        if literal.phase() {
+            *coefficients.entry(literal.atom()).or_insert(0) += coefficient;
+        } else {
+            *coefficients.entry(literal.atom()).or_insert(0) -= coefficient;
+            bound -= coefficient;
+        }
+    }
+    let mut normal_terms = Vec::new();
+    for (atom, coefficient) in coefficients.into_iter().sorted() {
+        if 0 < coefficient {
+            normal_terms.push((coefficient, ast::Literal::new(true, atom)));
+        } else if coefficient < 0 {
+            bound -= coefficient;
+            normal_terms.push((-coefficient, ast::Literal::new(false, atom)));
+        }
+    }
+    if bound <= 0 {
+        return Ok(Some(Normal { terms: Vec::new(), bound: 0 }))
+    }
+    for term in normal_terms.iter_mut() {
+        term.0 = term.0.min(bound);
+    }
+    let total: i128 = normal_terms.iter().map(|term| term.0).sum();
+    if total < bound {
+        return Ok(None)
+    }
+    let overflow = || "A pseudo-Boolean constraint overflows 64 bits once normalized.".to_string();
+    Ok(Some(Normal {
+        terms: normal_terms.into_iter()
+            .map(|(coefficient, literal)| Ok((u64::try_from(coefficient).map_err(|_| overflow())?, literal)))
+            .collect::<Result<_, String>>()?,
+        bound: u64::try_from(bound).map_err(|_| overflow())?,
+    }))
+}
+
+fn negate_terms(terms: &[(i128, ast::Literal)]) -> Vec<(i128, ast::Literal)> {
+    terms.iter().map(|&(coefficient, literal)| (-coefficient, literal)).collect()
+}
+
+// Returns the >= constraints equivalent to the constraint, or None if trivially
+// false
+fn normalize(constraint: &Constraint) -> Result<Option<Vec<Normal>>, String> {
+    let terms: Vec<(i128, ast::Literal)> = constraint.terms.iter()
+        .map(|&(coefficient, literal)| (coefficient as i128, literal))
+        .collect();
+    let bound = constraint.bound as i128;
+    let geq = || normalize_geq(&terms, bound);
+    let leq = || normalize_geq(&negate_terms(&terms), -bound);
+    let normals = match constraint.relation {
+        Relation::GreaterEq => vec![geq()?],
+        Relation::LessEq => vec![leq()?],
+        Relation::Equal => vec![geq()?, leq()?],
+    };
+    Ok(normals.into_iter()
+        .collect::<Option<Vec<Normal>>>()
+        .map(|normals| normals.into_iter().filter(|normal| 0 < normal.bound).collect()))
+}
+
+// Evaluates a linear sum under a total assignment to its atoms, in 128 bits so
+// that it cannot overflow
+pub fn eval_terms(terms: &[(i64, ast::Literal)], asgmt: &ast::Asgmt) -> Option<i128> {
+    let mut sum = 0;
+    for &(coefficient, literal) in terms {
+        if asgmt.get(&literal.atom())? == literal.phase() {
+            sum += coefficient as i128;
+        }
+    }
+    Some(sum)
+}
+
+impl Constraint {
+    pub fn eval(&self, asgmt: &ast::Asgmt) -> Option<bool> {
+        let sum = eval_terms(&self.terms, asgmt)?;
+        let bound = self.bound as i128;
+        Some(match self.relation {
+            Relation::GreaterEq => bound <= sum,
+            Relation::Equal => bound == sum,
+            Relation::LessEq => sum <= bound,
+        })
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum Node {
+    True,
+    False,
+    Inner(usize),
+}
+
+// A reduced ordered BDD for a normal constraint, where the inner node at level i
+// branches on terms[i]. Inner nodes are (level, high, low).
+struct Bdd {
+    nodes: Vec<(usize, Node, Node)>,
+    memo: HashMap<(usize, u64), Node>,
+    // Sums of the coefficients of terms[i..]
+    suffix_sums: Vec<u64>,
+}
+
+impl Bdd {
+    // Returns None if more than limit nodes are needed
+    fn build(normal: &Normal, limit: usize) -> Option<(Self, Node)> {
+        let mut suffix_sums = vec![0; normal.terms.len() + 1];
+        for i in (0..normal.terms.len()).rev() {
+            suffix_sums[i] = suffix_sums[i + 1] + normal.terms[i].0;
+        }
+        let mut bdd = Self {
+            nodes: Vec::new(),
+            memo: HashMap::new(),
+            suffix_sums,
+        };
+        let root = bdd.node(normal, 0, normal.bound, limit)?;
+        Some((bdd, root))
+    }
+
+    // The node for Σ_{j >= i} a_j l_j >= bound
+    fn node(&mut self, normal: &Normal, i: usize, bound: u64, limit: usize) -> Option<Node> {
+        if bound == 0 {
+            return Some(Node::True)
+        }
+        if self.suffix_sums[i] < bound {
+            return Some(Node::False)
+        }
+        if let Some(&node) = self.memo.get(&(i, bound)) {
+            return Some(node)
+        }
+        let high = self.node(normal, i + 1, bound.saturating_sub(normal.terms[i].0), limit)?;
+        let low = self.node(normal, i + 1, bound, limit)?;
+        let node = if high == low {
+            high
+        } else {
+            if limit <= self.nodes.len() {
+                return None
+            }
+            self.nodes.push((i, high, low));
+            Node::Inner(self.nodes.len() - 1)
+        };
+        self.memo.insert((i, bound), node);
+        Some(node)
+    }
+
+    // Since the constraint is monotone, the high child is implied by the low
+    // child, so v -> ite(l, high, low) simplifies to (v -> high) and
+    // (v -> l | low). Only this direction is needed to assert the root.
+    fn encode(&self, cnf: &mut ast::Cnf, normal: &Normal, root: Node) {
+        let vars: Vec<ast::Literal> = self.nodes.iter().map(|_| fresh_literal(cnf)).collect();
+        let lit = |node: Node| match node {
+            Node::True => None,
+            Node::False => Some(None),
+            Node::Inner(id) => Some(Some(vars[id])),
+        };
+        for (id, &(level, high, low)) in self.nodes.iter().enumerate() {
+            let v = vars[id].inversion();
+            if let Some(high) = lit(high) {
+                add_clause(cnf, std::iter::once(v).chain(high).collect());
+            }
+            if let Some(low) = lit(low) {
+                add_clause(cnf, [v, normal.terms[level].1].into_iter().chain(low).collect());
+            }
+        }
+        match lit(root) {
+            None => (),
+            Some(root) => add_clause(cnf, root.into_iter().collect()),
+        }
+    }
+}
+
+fn encode_bdd(cnf: &mut ast::Cnf, normal: &Normal) -> Result<(), String> {
+    let (bdd, root) = Bdd::build(normal, BDD_MAX_NODES)
+        .ok_or_else(|| format!("The BDD of a pseudo-Boolean constraint exceeds {} nodes.", BDD_MAX_NODES))?;
+    bdd.encode(cnf, normal, root);
+    Ok(())
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Literals which may be constant false (None)
+type Bit = Option<ast::Literal>;
+
+// Defines out <-> xor(inputs)
+fn define_xor(cnf: &mut ast::Cnf, out: ast::Literal, inputs: &[ast::Literal]) {
+    for phases in (0..inputs.len()).map(|_| [true, false]).multi_cartesian_product() {
+        let odd = phases.iter().filter(|&&phase| !phase).count() % 2 == 1;
+        let mut clause: Vec<ast::Literal> = inputs.iter().zip(phases)
+            .map(|(lit, phase)| if phase { *lit } else { lit.inversion() })
+            .collect();
+        // The clause rules out the assignment where the inputs are the
+        // inversions of the clause literals, which has xor equal to odd
+        clause.push(if odd { out } else { out.inversion() });
+        add_clause(cnf, clause);
+    }
+}
+
+// Defines out <-> at least two of the three inputs
+fn define_majority(cnf: &mut ast::Cnf, out: ast::Literal, a: ast::Literal, b: ast::Literal, c: ast::Literal) {
+    for (x, y) in [(a, b), (a, c), (b, c)] {
+        add_clause(cnf, vec![x.inversion(), y.inversion(), out]);
+        add_clause(cnf, vec![x, y, out.inversion()]);
+    }
+}
+
+// Defines out <-> a & b
+fn define_and(cnf: &mut ast::Cnf, out: ast::Literal, a: ast::Literal, b: ast::Literal) {
+    add_clause(cnf, vec![a.inversion(), b.inversion(), out]);
+    add_clause(cnf, vec![a, out.inversion()]);
+    add_clause(cnf, vec![b, out.inversion()]);
+}
+
+// Returns the binary representation (least significant first) of the sum,
+// built from full and half adders over buckets of equal weight
+fn adder_sum(cnf: &mut ast::Cnf, normal: &Normal) -> Vec<Bit> {
+    let mut buckets: Vec<Vec<ast::Literal>> = Vec::new();
+    for &(coefficient, literal) in normal.terms.iter() {
+        for bit in 0..u64::BITS as usize {
+            if coefficient & (1 << bit) != 0 {
+                if buckets.len() <= bit {
+                    buckets.resize(bit + 1, Vec::new());
+                }
+                buckets[bit].push(literal);
+            }
+        }
+    }
+    let mut sum = Vec::new();
+    let mut bit = 0;
+    while bit < buckets.len() {
+        while 2 <= buckets[bit].len() {
+            if buckets.len() <= bit + 1 {
+                buckets.push(Vec::new());
+            }
+            let s = fresh_literal(cnf);
+            let carry = fresh_literal(cnf);
+            if 3 <= buckets[bit].len() {
+                let (a, b, c) = (buckets[bit].pop().unwrap(), buckets[bit].pop().unwrap(), buckets[bit].pop().unwrap());
+                define_xor(cnf, s, &[a, b, c]);
+                define_majority(cnf, carry, a, b, c);
+            } else {
+                let (a, b) = (buckets[bit].pop().unwrap(), buckets[bit].pop().unwrap());
+                define_xor(cnf, s, &[a, b]);
+                define_and(cnf, carry, a, b);
+            }
+            buckets[bit].insert(0, s);
+            buckets[bit + 1].push(carry);
+        }
+        sum.push(buckets[bit].first().cloned());
+        bit += 1;
+    }
+    sum
+}
+
+// Asserts sum >= bound: for each set bit i of the bound, either sum_i is true or
+// some higher bit where the bound is unset is true.
+fn encode_adder(cnf: &mut ast::Cnf, normal: &Normal) {
+    let sum = adder_sum(cnf, normal);
+    let width = sum.len().max(u64::BITS as usize);
+    let sum_bit = |i: usize| sum.get(i).cloned().flatten();
+    let bound_bit = |i: usize| i < u64::BITS as usize && normal.bound & (1 << i) != 0;
+    for i in (0..width).filter(|&i| bound_bit(i)) {
+        let clause = std::iter::once(sum_bit(i))
+            .chain((i + 1..width).filter(|&j| !bound_bit(j)).map(sum_bit))
+            .flatten()
+            .collect();
+        add_clause(cnf, clause);
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// The number of literals of the unary expansion, saturating
+fn unary_len(normal: &Normal) -> u64 {
+    normal.terms.iter().fold(0, |len: u64, term| len.saturating_add(term.0))
+}
+
+// Each literal is repeated according to its coefficient and fed to a
+// cardinality network.
+fn encode_sorter(cnf: &mut ast::Cnf, normal: &Normal) {
+    let unary: Vec<ast::Literal> = normal.terms.iter()
+        .flat_map(|&(coefficient, literal)| std::iter::repeat_n(literal, coefficient as usize))
+        .collect();
+    card::at_least_k(cnf, &unary, normal.bound as usize, card::Encoding::CardinalityNetwork)
+}
+
+// A BDD unless it grows too large, in which case an adder
+fn encode_bdd_or_adder(cnf: &mut ast::Cnf, normal: &Normal) {
+    match Bdd::build(normal, BDD_NODE_LIMIT) {
+        Some((bdd, root)) => bdd.encode(cnf, normal, root),
+        None => encode_adder(cnf, normal),
+    }
+}
+
+fn encode_normal(cnf: &mut ast::Cnf, normal: &Normal, encoding: Encoding) -> Result<(), String> {
+    match encoding {
+        Encoding::Bdd => return encode_bdd(cnf, normal),
+        Encoding::Adder => encode_adder(cnf, normal),
+        Encoding::Sorter if unary_len(normal) <= SORTER_UNARY_LIMIT => encode_sorter(cnf, normal),
+        Encoding::Sorter => encode_bdd_or_adder(cnf, normal),
+        Encoding::Auto => {
+            if normal.terms.iter().all(|term| term.0 == 1) {
+                let literals: Vec<ast::Literal> = normal.terms.iter().map(|term| term.1).collect();
+                card::at_least_k(cnf, &literals, normal.bound as usize, card::Encoding::Auto);
+            } else if unary_len(normal) <= 8 * normal.terms.len() as u64 {
+                encode_sorter(cnf, normal)
+            } else {
+                encode_bdd_or_adder(cnf, normal)
+            }
+        },
+    }
+    Ok(())
+}
+
+pub fn encode(cnf: &mut ast::Cnf, constraint: &Constraint, encoding: Encoding) -> Result<(), String> {
+    for (_, literal) in constraint.terms.iter() {
+        cnf.declare_atom(literal.atom());
+    }
+    match normalize(constraint)? {
+        None => add_clause(cnf, Vec::new()),
+        Some(normals) => {
+            for normal in normals {
+                encode_normal(cnf, &normal, encoding)?
+            }
+        },
+    }
+    Ok(())
+}
+
+pub fn encode_problem(problem: &Problem, encoding: Encoding) -> Result<ast::Cnf, String> {
+    let mut cnf = ast::Cnf::new();
+    for (_, literal) in problem.constraints.iter().flat_map(|c| c.terms.iter()).chain(problem.objective.iter().flatten()) {
+        cnf.declare_atom(literal.atom());
+    }
+    for constraint in problem.constraints.iter() {
+        encode(&mut cnf, constraint, encoding)?
+    }
+    Ok(cnf)
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl Problem {
+    pub fn atoms(&self) -> impl Iterator<Item = ast::Atom> + '_ {
+        self.constraints.iter()
+            .flat_map(|constraint| constraint.terms.iter())
+            .chain(self.objective.iter().flatten())
+            .map(|(_, literal)| literal.atom())
+            .unique()
+    }
+
+    // Restricts a model of the encoding to the atoms of the problem. Atoms left
+    // unassigned by the solver are assigned so as to minimize the objective.
+    fn project(&self, asgmt: &ast::Asgmt) -> ast::Asgmt {
+        let mut projected = ast::Asgmt::new();
+        for atom in self.atoms() {
+            let phase = asgmt.get(&atom).unwrap_or_else(|| {
+                let weight: i64 = self.objective.iter().flatten()
+                    .filter(|(_, literal)| literal.atom() == atom)
+                    .map(|&(coefficient, literal)| if literal.phase() { coefficient } else { -coefficient })
+                    .sum();
+                weight < 0
+            });
+            projected.insert(atom, phase);
+        }
+        projected
+    }
+}
+
+// Finds a model of the problem minimizing the objective (if any), by repeatedly
+// solving with the objective bounded strictly below the best cost found so far.
+// Returns the model and, if there is an objective, its cost.
+pub fn minimize(problem: &Problem, encoding: Encoding, verbosity: usize) -> Result<Option<(ast::Asgmt, Option<i64>)>, String> {
+    let mut cnf = encode_problem(problem, encoding)?;
+    let Some(objective) = &problem.objective else {
+        return Ok(dpll::sat(&mut cnf, verbosity).map(|asgmt| (problem.project(&asgmt), None)))
+    };
+    let base = cnf;
+    let mut best: Option<(ast::Asgmt, Option<i64>)> = None;
+    loop {
+        let mut cnf = base.clone();
+        if let Some((_, Some(cost))) = &best {
+            let bound = cost.checked_sub(1).ok_or("The cost of a model overflows 64 bits.")?;
+            let bound = Constraint {
+                terms: objective.clone(),
+                relation: Relation::LessEq,
+                bound,
+            };
+            encode(&mut cnf, &bound, encoding)?;
+        }
+        let Some(asgmt) = dpll::sat(&mut cnf, verbosity.saturating_sub(1)) else {
+            return Ok(best)
+        };
+        let asgmt = problem.project(&asgmt);
+        let cost = i64::try_from(eval_terms(objective, &asgmt).unwrap())
+            .map_err(|_| "The cost of a model overflows 64 bits.".to_string())?;
+        if 0 < verbosity {
+            log::info!("Found model with cost {}", cost);
+        }
+        best = Some((asgmt, Some(cost)));
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::ast;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Learned clauses of at most this many literals are shared between workers
+const SHARE_LEN: usize = 8;
+
+// The configuration of each worker: the first has the defaults, and the rest
+// vary the restarts, decay, random decisions, initial phases, seed and whether
+// to vivify the original clauses
+fn config(worker: usize) -> dpll::Config {
+    if worker == 0 {
+        return dpll::Config::default()
+    }
+    dpll::Config {
+        restarts: if worker % 2 == 1 { dpll::Restarts::Geometric } else { dpll::Restarts::Luby },
+        restart_unit: [100, 50, 200, 100][worker % 4],
+        activity_decay: [0.95, 0.85, 0.99, 0.9][worker % 4],
+        random_decisions: 0.01 * (worker % 3) as f64,
+        initial_phase: [None, Some(false), Some(true)][worker % 3],
+        seed: worker as u64 + 1,
+        vivify_effort: dpll::Config::default().vivify_effort,
+        vivify_irredundant: worker % 4 == 3,
+    }
+}
+
+// Solves the cnf by differently configured solvers in parallel, one per
+// thread, returning the result of the first to finish. The solvers optionally
+// share their short learned clauses.
+pub fn sat(cnf: &mut ast::Cnf, threads: usize, share: bool, verbosity: usize) -> Option<ast::Asgmt> {
+    let (solver, simplifier) = dpll::solver(cnf, verbosity)?;
+    let pool = dpll::SharedClauses::pool(threads.max(1));
+    let stop = Arc::new(AtomicBool::new(false));
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1)).map(|worker| {
+            let mut solver = solver.clone();
+            solver.configure(config(worker));
+            let max_len = if share { SHARE_LEN } else { 0 };
+            solver.share(dpll::Exchange::new(pool.clone(), stop.clone(), worker, max_len));
+            let stop = stop.clone();
+            scope.spawn(move || {
+                let result = solver.solve();
+                // Only the first to finish reports, as the rest may have been
+                // stopped
+                if stop.swap(true, Ordering::Relaxed) {
+                    return None
+                }
+                if 0 < verbosity {
+                    log::info!("Worker {} finished first", worker);
+                }
+                Some(result)
+            })
+        }).collect();
+        workers.into_iter()
+            .filter_map(|worker| worker.join().unwrap())
+            .next()
+            .unwrap()
+    }).map(|mut model| {
+        simplifier.extend(&mut model);
+        model
+    })
+}
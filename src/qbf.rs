@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use crate::ast;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Quantifier {
+    Exists,
+    Forall,
+}
+
+// A quantified boolean formula in prenex form, with a cnf matrix. Atoms of the
+// matrix outside of the prefix are existential, outermost.
+#[derive(Clone, Debug)]
+pub struct Qbf {
+    pub prefix: Vec<(Quantifier, Vec<ast::Atom>)>,
+    pub matrix: ast::Cnf,
+}
+
+// A negation normal form formula over the matrix atoms, closed under
+// substitution of a move and renaming of atoms
+#[derive(Clone, Debug)]
+enum Formula {
+    Const(bool),
+    Literal(ast::Literal),
+    And(Vec<Formula>),
+    Or(Vec<Formula>),
+}
+
+impl Formula {
+    fn from_cnf(cnf: &ast::Cnf) -> Self {
+        Formula::And(cnf.clauses()
+            .map(|clause| Formula::Or(clause.literals().map(|&literal| Formula::Literal(literal)).collect()))
+            .collect())
+    }
+
+    // The formula with the assigned literals replaced by their values, and
+    // the constants propagated
+    fn assign(&self, asgmt: &ast::Asgmt) -> Self {
+        match self {
+            Formula::Const(_) => self.clone(),
+            Formula::Literal(literal) => match asgmt.get(&literal.atom()) {
+                Some(phase) => Formula::Const(phase == literal.phase()),
+                None => self.clone(),
+            },
+            Formula::And(children) => Formula::junction(true, children.iter().map(|child| child.assign(asgmt))),
+            Formula::Or(children) => Formula::junction(false, children.iter().map(|child| child.assign(asgmt))),
+        }
+    }
+
+    // A conjunction (or disjunction) of the given formulas, without constants
+    // unless it is one
+    fn junction(conjunction: bool, children: impl Iterator<Item = Formula>) -> Self {
+        let mut kept = Vec::new();
+        for child in children {
+            match child {
+                Formula::Const(value) if value == conjunction => (),
+                Formula::Const(_) => return Formula::Const(!conjunction),
+                _ => kept.push(child),
+            }
+        }
+        match kept.len() {
+            0 => Formula::Const(conjunction),
+            1 => kept.pop().unwrap(),
+            _ if conjunction => Formula::And(kept),
+            _ => Formula::Or(kept),
+        }
+    }
+
+    fn rename(&self, renaming: &HashMap<ast::Atom, ast::Atom>) -> Self {
+        match self {
+            Formula::Const(_) => self.clone(),
+            Formula::Literal(literal) => match renaming.get(&literal.atom()) {
+                Some(&atom) => Formula::Literal(ast::Literal::new(literal.phase(), atom)),
+                None => self.clone(),
+            },
+            Formula::And(children) => Formula::And(children.iter().map(|child| child.rename(renaming)).collect()),
+            Formula::Or(children) => Formula::Or(children.iter().map(|child| child.rename(renaming)).collect()),
+        }
+    }
+
+    fn negation(&self) -> Self {
+        match self {
+            Formula::Const(value) => Formula::Const(!value),
+            Formula::Literal(literal) => Formula::Literal(literal.inversion()),
+            Formula::And(children) => Formula::Or(children.iter().map(Formula::negation).collect()),
+            Formula::Or(children) => Formula::And(children.iter().map(Formula::negation).collect()),
+        }
+    }
+}
+
+// A game between two players, by turns assigning the blocks of atoms. The
+// player of the first block wins if the formula holds when it is existential,
+// and if it does not when it is universal.
+struct Game {
+    quantifier: Quantifier,
+    blocks: Vec<Vec<ast::Atom>>,
+    formula: Formula,
+}
+
+// Recursive counterexample-guided abstraction refinement (RAReQS, Janota et
+// al.): the move of the first player comes from an abstraction of the game,
+// which is expanded by the opponent's counter-moves to the moves found so far,
+// until it has none. The counter-moves are winning moves of the rest of the
+// game, solved the same way, so each recursive call has a block fewer.
+struct Cegar {
+    next_atom: ast::Atom,
+    calls: usize,
+    verbosity: usize,
+}
+
+impl Cegar {
+    fn fresh_atom(&mut self) -> ast::Atom {
+        let atom = self.next_atom;
+        self.next_atom = ast::Atom::new(atom.index() as u32 + 1);
+        atom
+    }
+
+    // Adds the clauses of a Plaisted-Greenbaum encoding of the formula,
+    // returning a literal implying it
+    fn encode(&mut self, formula: &Formula, cnf: &mut ast::Cnf) -> ast::Literal {
+        let (conjunction, children) = match formula {
+            Formula::Literal(literal) => return *literal,
+            Formula::And(children) => (true, children),
+            Formula::Or(children) => (false, children),
+            Formula::Const(_) => unreachable!("constants are propagated"),
+        };
+        let literal = ast::Literal::new(true, self.fresh_atom());
+        let children: Vec<ast::Literal> = children.iter().map(|child| self.encode(child, cnf)).collect();
+        if conjunction {
+            for child in children {
+                cnf.add(ast::Clause::from(vec![literal.inversion(), child]));
+            }
+        } else {
+            cnf.add(ast::Clause::from([literal.inversion()].into_iter().chain(children).collect::<Vec<_>>()));
+        }
+        literal
+    }
+
+    // A satisfying assignment of the formula
+    fn sat(&mut self, formula: &Formula) -> Option<ast::Asgmt> {
+        match formula {
+            Formula::Const(value) => return value.then(ast::Asgmt::new),
+            Formula::Literal(literal) => {
+                let mut asgmt = ast::Asgmt::new();
+                asgmt.insert(literal.atom(), literal.phase());
+                return Some(asgmt)
+            },
+            _ => (),
+        }
+        let mut cnf = ast::Cnf::new();
+        let root = self.encode(formula, &mut cnf);
+        cnf.add(ast::Clause::from(vec![root]));
+        self.calls += 1;
+        dpll::Solver::new(&cnf, self.verbosity.saturating_sub(2)).solve()
+    }
+
+    // A winning move of the player of the first block, or None if the other
+    // player wins
+    fn solve(&mut self, game: &Game) -> Option<ast::Asgmt> {
+        let first = game.blocks.first().map_or(&[][..], |block| &block[..]);
+        let restrict = |model: &ast::Asgmt| {
+            let mut asgmt = ast::Asgmt::new();
+            for &atom in first {
+                asgmt.insert(atom, model.get(&atom).unwrap_or(false));
+            }
+            asgmt
+        };
+        if game.blocks.len() <= 1 {
+            return match game.quantifier {
+                Quantifier::Exists => self.sat(&game.formula),
+                Quantifier::Forall => self.sat(&game.formula.negation()),
+            }.map(|model| restrict(&model))
+        }
+        // The formula under each counter-move, with the atoms of the later
+        // blocks renamed apart. The first player must win all of them.
+        let mut abstraction = Game {
+            quantifier: game.quantifier,
+            blocks: game.blocks.iter().skip(2).cloned().collect(),
+            formula: Formula::Const(game.quantifier == Quantifier::Exists),
+        };
+        if abstraction.blocks.is_empty() {
+            abstraction.blocks.push(Vec::new());
+        }
+        abstraction.blocks[0].extend(first);
+        let mut copies = Vec::new();
+        loop {
+            let mv = restrict(&self.solve(&abstraction)?);
+            let rest = Game {
+                quantifier: match game.quantifier {
+                    Quantifier::Exists => Quantifier::Forall,
+                    Quantifier::Forall => Quantifier::Exists,
+                },
+                blocks: game.blocks[1..].to_vec(),
+                formula: game.formula.assign(&mv),
+            };
+            let Some(counter_mv) = self.solve(&rest) else {
+                if 2 < self.verbosity {
+                    log::info!("Found a winning move of {} atoms after {} rounds", first.len(), copies.len());
+                }
+                return Some(mv)
+            };
+            let mut renaming = HashMap::new();
+            for (level, block) in game.blocks.iter().enumerate().skip(2) {
+                for &atom in block {
+                    let renamed = self.fresh_atom();
+                    renaming.insert(atom, renamed);
+                    abstraction.blocks[level - 2].push(renamed);
+                }
+            }
+            copies.push(game.formula.assign(&counter_mv).rename(&renaming));
+            abstraction.formula = Formula::junction(game.quantifier == Quantifier::Exists, copies.iter().cloned());
+        }
+    }
+}
+
+// Solves the qbf, returning its value along with a winning move for the
+// outermost block (its certificate) if the player of that block wins
+pub fn solve(qbf: &Qbf, verbosity: usize) -> (bool, Option<ast::Asgmt>) {
+    // Consecutive blocks of a quantifier are merged, and empty ones dropped
+    let mut free: Vec<ast::Atom> = qbf.matrix.atoms().collect();
+    let mut prefix: Vec<(Quantifier, Vec<ast::Atom>)> = Vec::new();
+    for (quantifier, atoms) in qbf.prefix.iter() {
+        free.retain(|atom| !atoms.contains(atom));
+        match prefix.last_mut() {
+            _ if atoms.is_empty() => (),
+            Some((last, block)) if last == quantifier => block.extend(atoms),
+            _ => prefix.push((*quantifier, atoms.clone())),
+        }
+    }
+    match prefix.first_mut() {
+        _ if free.is_empty() => (),
+        Some((Quantifier::Exists, block)) => block.extend(free),
+        _ => prefix.insert(0, (Quantifier::Exists, free)),
+    }
+    let mut cegar = Cegar {
+        next_atom: qbf.matrix.next_atom(),
+        calls: 0,
+        verbosity,
+    };
+    let game = Game {
+        quantifier: prefix.first().map_or(Quantifier::Exists, |(quantifier, _)| *quantifier),
+        blocks: prefix.iter().map(|(_, block)| block.clone()).collect(),
+        formula: Formula::from_cnf(&qbf.matrix),
+    };
+    let certificate = cegar.solve(&game);
+    if 0 < verbosity {
+        log::info!("Solved a QBF of {} quantifier blocks after {} SAT calls", prefix.len(), cegar.calls);
+    }
+    ((game.quantifier == Quantifier::Exists) == certificate.is_some(), certificate)
+}
@@ -0,0 +1,106 @@
+use crate::approxmc;
+use crate::ast;
+use crate::util;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Consecutive failures to find a cell of the right size after which sampling
+// gives up
+const MAX_FAILURES: usize = 100;
+
+// Near-uniform samples of the models of a cnf projected onto a sampling set, as
+// in UniGen2: each sample is drawn uniformly from a random cell of a number of
+// hashes chosen from an approximate count, provided the cell is neither too
+// small nor too large. Each projected model is sampled with probability within
+// a factor of 1 + epsilon of uniform.
+pub struct Samples {
+    cnf: ast::Cnf,
+    projection: Vec<ast::Atom>,
+    // Every projected model, when there are few enough to sample directly
+    models: Option<Vec<ast::Asgmt>>,
+    // The largest number of hashes to try
+    hashes: usize,
+    lo_threshold: usize,
+    hi_threshold: usize,
+    rng: util::Rng,
+    verbosity: usize,
+}
+
+impl Iterator for Samples {
+    type Item = ast::Asgmt;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(models) = &self.models {
+            if models.is_empty() {
+                return None
+            }
+            return Some(models[self.rng.below(models.len())].clone())
+        }
+        for _ in 0..MAX_FAILURES {
+            let mut hashed = approxmc::Hashed::new(&self.cnf, &self.projection, self.rng.next_u64(), self.verbosity);
+            // Cells only shrink as hashes are added
+            for m in self.hashes.saturating_sub(3)..=self.hashes {
+                let cell = hashed.cell(m, self.hi_threshold + 1);
+                if cell.len() <= self.hi_threshold {
+                    if self.lo_threshold <= cell.len() {
+                        return Some(cell[self.rng.below(cell.len())].clone())
+                    }
+                    break
+                }
+            }
+            if 0 < self.verbosity {
+                log::info!("Failed to find a cell of between {} and {} models", self.lo_threshold, self.hi_threshold);
+            }
+        }
+        None
+    }
+}
+
+// Returns the samples of the models of the cnf, projected onto the given atoms
+// or else onto its declared atoms. The tolerance must exceed 1.71.
+pub fn samples(cnf: &ast::Cnf, projection: Option<Vec<ast::Atom>>, epsilon: f64, seed: u64, verbosity: usize) -> Result<Samples, String> {
+    if epsilon <= 1.71 {
+        return Err("The tolerance of sampling must exceed 1.71.".to_string())
+    }
+    // Solve epsilon = (1 + kappa) (2.23 + 0.48 / (1 - kappa)^2) - 1 for kappa
+    // in (0, 1), where the right-hand side is increasing
+    let tolerance = |kappa: f64| (1.0 + kappa) * (2.23 + 0.48 / (1.0 - kappa).powi(2)) - 1.0;
+    let (mut lo, mut hi) = (0.0, 1.0);
+    for _ in 0..64 {
+        let mid = (lo + hi) / 2.0;
+        if tolerance(mid) < epsilon {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    let kappa: f64 = lo;
+    let pivot = (4.03 * (1.0 + 1.0 / kappa).powi(2)).ceil();
+    let hi_threshold = (1.0 + (1.0 + kappa) * pivot) as usize;
+    let lo_threshold = (pivot / (1.0 + kappa)) as usize;
+
+    let projection = approxmc::sampling_set(cnf, projection);
+    let mut rng = util::Rng::new(seed);
+    let mut samples = Samples {
+        cnf: cnf.clone(),
+        projection: projection.clone(),
+        models: None,
+        hashes: 0,
+        lo_threshold,
+        hi_threshold,
+        rng: util::Rng::new(rng.next_u64()),
+        verbosity,
+    };
+    let models = approxmc::Hashed::new(cnf, &projection, rng.next_u64(), verbosity).cell(0, hi_threshold + 1);
+    if models.len() <= hi_threshold {
+        samples.models = Some(models);
+        return Ok(samples)
+    }
+    let count = approxmc::approx_count(cnf, Some(projection), 0.8, 0.8, rng.next_u64(), verbosity)?;
+    samples.hashes = (count.to_f64().log2() + 1.8_f64.log2() - pivot.log2()).ceil().max(0.0) as usize;
+    if 0 < verbosity {
+        log::info!("Sampling from cells of {} to {} models with up to {} hashes", lo_threshold, hi_threshold, samples.hashes);
+    }
+    Ok(samples)
+}
@@ -0,0 +1,657 @@
+use itertools::Itertools;
+
+use crate::ast;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Steps of preprocessing, in the order of STEPS by default
+#[derive(PartialEq, Eq, Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Step {
+    // Bounded variable elimination
+    Eliminate,
+    // Removal of clauses containing others, and strengthening by
+    // self-subsuming resolution
+    Subsume,
+    // Failed literal probing, adding hyper-binary resolvents
+    Probe,
+    // Substitution of equivalent literals, found as strongly connected
+    // components of the binary implication graph
+    Substitute,
+    // Blocked clause elimination
+    Block,
+    // Covered clause elimination, which extends clauses by covered literal
+    // addition until they are blocked
+    Cover,
+}
+
+pub const STEPS: [Step; 5] = [Step::Probe, Step::Substitute, Step::Eliminate, Step::Subsume, Step::Block];
+
+// Atoms occurring more often than this in both phases are not eliminated
+const MAX_OCCURRENCES: usize = 10;
+// Atoms are not eliminated if a resolvent would be longer than this
+const MAX_RESOLVENT_LEN: usize = 20;
+// Clauses are not extended by covered literals beyond this length
+const MAX_COVERED_LEN: usize = 40;
+
+// The resolvent of two clauses sorted by atom on the given atom, or None if it
+// is a tautology
+fn resolve(positive: &[ast::Literal], negative: &[ast::Literal], atom: ast::Atom) -> Option<Vec<ast::Literal>> {
+    let mut resolvent = Vec::with_capacity(positive.len() + negative.len());
+    for literal in positive.iter().merge_by(negative.iter(), |a, b| (a.atom(), a.phase()) <= (b.atom(), b.phase())) {
+        if literal.atom() == atom {
+            continue
+        }
+        match resolvent.last() {
+            Some(&last) if last == *literal => continue,
+            Some(&last) if last == literal.inversion() => return None,
+            _ => resolvent.push(*literal),
+        }
+    }
+    Some(resolvent)
+}
+
+// An overapproximation of the atoms of a clause, as a bit for each atom modulo
+// 64, so that a clause can only subsume another if its signature is a subset
+pub fn signature(literals: &[ast::Literal]) -> u64 {
+    literals.iter().fold(0, |signature, literal| signature | 1 << (literal.atom().index() % 64))
+}
+
+// Whether the first clause subsumes the second, both normal: Some(None) if it
+// is a subset, and Some(Some(literal)) if it is once the inversion of one of
+// its literals is flipped, in which case the literal can be removed from the
+// second (self-subsuming resolution)
+pub fn subsumes(small: &[ast::Literal], large: &[ast::Literal]) -> Option<Option<ast::Literal>> {
+    if large.len() < small.len() {
+        return None
+    }
+    let mut flipped = None;
+    let mut large = large.iter();
+    for literal in small {
+        let lit = large.find(|lit| literal.atom() <= lit.atom())?;
+        if lit.atom() != literal.atom() {
+            return None
+        }
+        if lit != literal {
+            if flipped.is_some() {
+                return None
+            }
+            flipped = Some(*lit);
+        }
+    }
+    Some(flipped)
+}
+
+// A SatELite-style preprocessor, which keeps occurrence lists of the clauses
+// and propagates units as it goes. Atoms of xors are never eliminated. Models
+// of the simplified cnf are extended to models of the original by replaying
+// the clauses removed by elimination in reverse.
+pub struct Simplifier {
+    // The cnf without its clauses, to which the simplified clauses are added
+    template: ast::Cnf,
+    // Normal clauses, with their signatures
+    clauses: Vec<Vec<ast::Literal>>,
+    signatures: Vec<u64>,
+    removed: Vec<bool>,
+    // Clauses by the index of a literal they contain, lazily: entries may be
+    // removed clauses, or clauses no longer containing the literal
+    occurs: Vec<Vec<usize>>,
+
+    // Per-atom state, by index
+    values: Vec<Option<bool>>,
+    frozen: Vec<bool>,
+    eliminated: Vec<bool>,
+    // Assigned literals not yet propagated
+    units: Vec<ast::Literal>,
+
+    // Clauses removed by elimination, with the literal of the eliminated atom
+    // each contained. Substituted atoms are eliminated too, by the two clauses
+    // of their equivalence, and blocked clauses are removed with their
+    // blocking literal.
+    stack: Vec<(ast::Literal, Vec<ast::Literal>)>,
+
+    unsat: bool,
+    verbosity: usize,
+}
+
+impl Simplifier {
+    pub fn new(cnf: &ast::Cnf, verbosity: usize) -> Self {
+        let size = cnf.declared_atoms().chain(cnf.atoms()).map(|atom| atom.index() + 1).max().unwrap_or(0);
+        let mut template = cnf.clone();
+        template.retain_clauses(|_| false);
+        let mut simplifier = Self {
+            template,
+            clauses: Vec::new(),
+            signatures: Vec::new(),
+            removed: Vec::new(),
+            occurs: vec![Vec::new(); 2 * size],
+            values: vec![None; size],
+            frozen: vec![false; size],
+            eliminated: vec![false; size],
+            units: Vec::new(),
+            stack: Vec::new(),
+            unsat: false,
+            verbosity,
+        };
+        for &atom in cnf.xors().flat_map(|xor| xor.atoms()) {
+            simplifier.frozen[atom.index()] = true;
+        }
+        for clause in cnf.clauses() {
+            simplifier.add_clause(clause.clone());
+        }
+        simplifier.propagate();
+        simplifier
+    }
+
+    pub fn is_unsat(&self) -> bool {
+        self.unsat
+    }
+
+    fn value(&self, literal: ast::Literal) -> Option<bool> {
+        self.values[literal.atom().index()].map(|phase| phase == literal.phase())
+    }
+
+    fn assign(&mut self, literal: ast::Literal) {
+        match self.value(literal) {
+            Some(true) => (),
+            Some(false) => self.unsat = true,
+            None => {
+                self.values[literal.atom().index()] = Some(literal.phase());
+                self.units.push(literal);
+            },
+        }
+    }
+
+    fn add_clause(&mut self, mut clause: ast::Clause) {
+        if !clause.normalize() {
+            return
+        }
+        let mut literals: Vec<ast::Literal> = clause.into_iter().collect();
+        if literals.iter().any(|&lit| self.value(lit) == Some(true)) {
+            return
+        }
+        literals.retain(|&lit| self.value(lit).is_none());
+        match literals[..] {
+            [] => self.unsat = true,
+            [literal] => self.assign(literal),
+            _ => {
+                let index = self.clauses.len();
+                for literal in literals.iter() {
+                    self.occurs[literal.index()].push(index);
+                }
+                self.signatures.push(signature(&literals));
+                self.clauses.push(literals);
+                self.removed.push(false);
+            },
+        }
+    }
+
+    // The clauses containing the literal, dropping stale entries
+    fn occurrences(&mut self, literal: ast::Literal) -> Vec<usize> {
+        let (clauses, removed) = (&self.clauses, &self.removed);
+        self.occurs[literal.index()].retain(|&index| !removed[index] && clauses[index].contains(&literal));
+        self.occurs[literal.index()].clone()
+    }
+
+    fn occurrence_count(&self, literal: ast::Literal) -> usize {
+        self.occurs[literal.index()].len()
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.removed[index] = true;
+    }
+
+    // Removes the clauses satisfied by the units, and their false literals
+    // from the rest
+    fn propagate(&mut self) {
+        while let Some(literal) = self.units.pop() {
+            if self.unsat {
+                return
+            }
+            for index in self.occurrences(literal) {
+                self.remove(index);
+            }
+            for index in self.occurrences(literal.inversion()) {
+                self.strengthen(index, literal.inversion());
+            }
+        }
+    }
+
+    // Removes the literal from the clause, which may leave a unit
+    fn strengthen(&mut self, index: usize, literal: ast::Literal) {
+        self.clauses[index].retain(|&lit| lit != literal);
+        self.signatures[index] = signature(&self.clauses[index]);
+        match self.clauses[index][..] {
+            [] => self.unsat = true,
+            [unit] => {
+                self.remove(index);
+                self.assign(unit);
+            },
+            _ => (),
+        }
+    }
+
+    // Whether some clause subsumes the given normal literals
+    fn subsumed(&mut self, literals: &[ast::Literal]) -> bool {
+        let signature = signature(literals);
+        literals.iter().any(|&literal| {
+            self.occurrences(literal).into_iter().any(|index| {
+                self.signatures[index] & !signature == 0
+                    && subsumes(&self.clauses[index], literals) == Some(None)
+            })
+        })
+    }
+
+    pub fn run(&mut self, steps: &[Step]) {
+        for step in steps {
+            if self.unsat {
+                return
+            }
+            match step {
+                Step::Eliminate => self.eliminate(),
+                Step::Subsume => self.subsume(),
+                Step::Probe => self.probe(),
+                Step::Substitute => self.substitute(),
+                Step::Block => self.block(false),
+                Step::Cover => self.block(true),
+            }
+        }
+    }
+
+    // Eliminates the atom by replacing the clauses containing it with their
+    // resolvents, provided there are no more of these. Returns whether it did.
+    fn eliminate_atom(&mut self, atom: ast::Atom) -> bool {
+        let positive = ast::Literal::new(true, atom);
+        let pos = self.occurrences(positive);
+        let neg = self.occurrences(positive.inversion());
+        if MAX_OCCURRENCES < pos.len() && MAX_OCCURRENCES < neg.len() {
+            return false
+        }
+        let mut resolvents = Vec::new();
+        for &p in pos.iter() {
+            for &n in neg.iter() {
+                if let Some(resolvent) = resolve(&self.clauses[p], &self.clauses[n], atom) {
+                    // Resolvents subsumed by other clauses are redundant
+                    if self.subsumed(&resolvent) {
+                        continue
+                    }
+                    if MAX_RESOLVENT_LEN < resolvent.len() || pos.len() + neg.len() <= resolvents.len() {
+                        return false
+                    }
+                    resolvents.push(resolvent);
+                }
+            }
+        }
+        if 2 < self.verbosity {
+            log::info!("Eliminating {}, replacing {} clauses with {}", atom, pos.len() + neg.len(), resolvents.len());
+        }
+        for (literal, clauses) in [(positive, pos), (positive.inversion(), neg)] {
+            for index in clauses {
+                self.remove(index);
+                self.stack.push((literal, self.clauses[index].clone()));
+            }
+        }
+        self.eliminated[atom.index()] = true;
+        for resolvent in resolvents {
+            self.add_clause(ast::Clause::from(resolvent));
+        }
+        self.propagate();
+        true
+    }
+
+    // Eliminates atoms in passes until none can be, trying those with the
+    // fewest resolution pairs first
+    fn eliminate(&mut self) {
+        let mut eliminated = 0;
+        loop {
+            let candidates: Vec<ast::Atom> = (0..self.values.len())
+                .filter(|&index| !self.frozen[index] && !self.eliminated[index] && self.values[index].is_none())
+                .map(|index| ast::Atom::new(index as u32))
+                .filter(|&atom| {
+                    let positive = ast::Literal::new(true, atom);
+                    0 < self.occurrence_count(positive) + self.occurrence_count(positive.inversion())
+                })
+                .sorted_by_key(|&atom| {
+                    let positive = ast::Literal::new(true, atom);
+                    self.occurrence_count(positive) * self.occurrence_count(positive.inversion())
+                })
+                .collect();
+            let before = eliminated;
+            for atom in candidates {
+                if self.unsat {
+                    return
+                }
+                if self.values[atom.index()].is_none() && self.eliminate_atom(atom) {
+                    eliminated += 1;
+                }
+            }
+            if eliminated == before {
+                break
+            }
+        }
+        if 0 < self.verbosity {
+            log::info!("Eliminated {} atoms", eliminated);
+        }
+    }
+
+    // Removes every clause subsumed by another, and strengthens clauses by
+    // self-subsuming resolution, until neither applies. Each clause is checked
+    // against the clauses containing the least frequent atom of it, whose
+    // signatures allow it.
+    fn subsume(&mut self) {
+        let (mut subsumed, mut strengthened) = (0, 0);
+        let mut queue: Vec<usize> = (0..self.clauses.len())
+            .filter(|&index| !self.removed[index])
+            .sorted_by_key(|&index| std::cmp::Reverse(self.clauses[index].len()))
+            .collect();
+        let mut queued = vec![false; self.clauses.len()];
+        for &index in queue.iter() {
+            queued[index] = true;
+        }
+        while let Some(index) = queue.pop() {
+            queued[index] = false;
+            if self.unsat {
+                return
+            }
+            if self.removed[index] {
+                continue
+            }
+            let atom = self.clauses[index].iter()
+                .map(|lit| lit.atom())
+                .min_by_key(|&atom| {
+                    let positive = ast::Literal::new(true, atom);
+                    self.occurrence_count(positive) + self.occurrence_count(positive.inversion())
+                })
+                .unwrap();
+            let positive = ast::Literal::new(true, atom);
+            for literal in [positive, positive.inversion()] {
+                for other in self.occurrences(literal) {
+                    if other == index || self.removed[index] || self.removed[other]
+                        || self.signatures[index] & !self.signatures[other] != 0 {
+                        continue
+                    }
+                    match subsumes(&self.clauses[index], &self.clauses[other]) {
+                        None => (),
+                        Some(None) => {
+                            self.remove(other);
+                            subsumed += 1;
+                        },
+                        Some(Some(flipped)) => {
+                            self.strengthen(other, flipped);
+                            self.propagate();
+                            strengthened += 1;
+                            // The stronger clause may now subsume others
+                            if !self.removed[other] && !queued[other] {
+                                queued[other] = true;
+                                queue.push(other);
+                            }
+                        },
+                    }
+                }
+            }
+        }
+        if 0 < self.verbosity {
+            log::info!("Removed {} subsumed clauses and strengthened {}", subsumed, strengthened);
+        }
+    }
+
+    // Probes by a solver for the simplified cnf, which propagates by watched
+    // literals, and keeps its failed literals and hyper-binary resolvents
+    fn probe(&mut self) {
+        let mut solver = dpll::Solver::new(&self.cnf(), self.verbosity.saturating_sub(1));
+        let Some((units, resolvents)) = solver.probe() else {
+            self.unsat = true;
+            return
+        };
+        let fixed = units.iter().filter(|&&lit| self.value(lit).is_none()).count();
+        for literal in units {
+            self.assign(literal);
+        }
+        for clause in resolvents.iter() {
+            self.add_clause(clause.clone());
+        }
+        self.propagate();
+        if 0 < self.verbosity {
+            log::info!("Probing fixed {} atoms and added {} hyper-binary resolvents", fixed, resolvents.len());
+        }
+    }
+
+    // The strongly connected components of the binary implication graph, in
+    // which each binary clause (a b) has edges from !a to b and from !b to a,
+    // by Tarjan's algorithm
+    fn implication_components(&mut self) -> Vec<Vec<ast::Literal>> {
+        let literal = |index: usize| ast::Literal::new(index.is_multiple_of(2), ast::Atom::new((index / 2) as u32));
+        let mut edges: Vec<Vec<usize>> = vec![Vec::new(); self.occurs.len()];
+        for (index, clause) in self.clauses.iter().enumerate() {
+            if let (false, [a, b]) = (self.removed[index], &clause[..]) {
+                edges[a.inversion().index()].push(b.index());
+                edges[b.inversion().index()].push(a.index());
+            }
+        }
+        let mut order = vec![usize::MAX; edges.len()];
+        let mut lowlink = vec![0; edges.len()];
+        let mut on_stack = vec![false; edges.len()];
+        let mut stack = Vec::new();
+        let mut components = Vec::new();
+        let mut counter = 0;
+        for root in 0..edges.len() {
+            if order[root] != usize::MAX || edges[root].is_empty() {
+                continue
+            }
+            // Nodes being visited, with the next edge to follow
+            let mut path = vec![(root, 0)];
+            order[root] = counter;
+            lowlink[root] = counter;
+            counter += 1;
+            stack.push(root);
+            on_stack[root] = true;
+            while let Some(&mut (node, ref mut next)) = path.last_mut() {
+                if let Some(&child) = edges[node].get(*next) {
+                    *next += 1;
+                    if order[child] == usize::MAX {
+                        order[child] = counter;
+                        lowlink[child] = counter;
+                        counter += 1;
+                        stack.push(child);
+                        on_stack[child] = true;
+                        path.push((child, 0));
+                    } else if on_stack[child] {
+                        lowlink[node] = lowlink[node].min(order[child]);
+                    }
+                    continue
+                }
+                path.pop();
+                if let Some(&(parent, _)) = path.last() {
+                    lowlink[parent] = lowlink[parent].min(lowlink[node]);
+                }
+                if lowlink[node] == order[node] {
+                    let mut component = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        component.push(literal(member));
+                        if member == node {
+                            break
+                        }
+                    }
+                    if 1 < component.len() {
+                        components.push(component);
+                    }
+                }
+            }
+        }
+        components
+    }
+
+    // Replaces the literals of each strongly connected component of the binary
+    // implication graph, which are equivalent, by a representative
+    fn substitute(&mut self) {
+        let mut representatives: Vec<Option<ast::Literal>> = vec![None; self.occurs.len()];
+        let mut substituted = 0;
+        for component in self.implication_components() {
+            if component.iter().any(|&lit| component.contains(&lit.inversion())) {
+                self.unsat = true;
+                return
+            }
+            // The dual component of the inversions gets the same treatment
+            if representatives[component[0].index()].is_some() {
+                continue
+            }
+            // Atoms of xors cannot be substituted, so represent the others
+            let representative = *component.iter()
+                .min_by_key(|lit| (!self.frozen[lit.atom().index()], lit.atom()))
+                .unwrap();
+            for &literal in component.iter() {
+                representatives[literal.index()] = Some(representative);
+                representatives[literal.inversion().index()] = Some(representative.inversion());
+                if literal == representative || self.frozen[literal.atom().index()] {
+                    continue
+                }
+                if 2 < self.verbosity {
+                    log::info!("Substituting {} for {}", representative, literal);
+                }
+                self.stack.push((literal, vec![literal, representative.inversion()]));
+                self.stack.push((literal.inversion(), vec![literal.inversion(), representative]));
+                self.eliminated[literal.atom().index()] = true;
+                substituted += 1;
+                for from in [literal, literal.inversion()] {
+                    let to = if from == literal { representative } else { representative.inversion() };
+                    for index in self.occurrences(from) {
+                        self.remove(index);
+                        let clause: Vec<ast::Literal> = self.clauses[index].iter()
+                            .map(|&lit| if lit == from { to } else { lit })
+                            .collect();
+                        self.add_clause(ast::Clause::from(clause));
+                    }
+                }
+            }
+        }
+        self.propagate();
+        if 0 < self.verbosity {
+            log::info!("Substituted {} equivalent atoms", substituted);
+        }
+    }
+
+    // The literals which every clause resolving non-tautologically with the
+    // given normal clause on the literal contains apart from its inversion, or
+    // None if there are no such clauses, so that it is blocked on the literal
+    fn covered(&mut self, literals: &[ast::Literal], literal: ast::Literal) -> Option<Vec<ast::Literal>> {
+        let mut covered: Option<Vec<ast::Literal>> = None;
+        for index in self.occurrences(literal.inversion()) {
+            let other = &self.clauses[index];
+            if other.iter().any(|&lit| lit != literal.inversion() && literals.contains(&lit.inversion())) {
+                continue
+            }
+            let rest = other.iter().filter(|&&lit| lit != literal.inversion());
+            covered = Some(match covered {
+                None => rest.cloned().collect(),
+                Some(mut covered) => {
+                    covered.retain(|lit| other.contains(lit));
+                    covered
+                },
+            });
+        }
+        covered
+    }
+
+    // Removes the clause if it is blocked, or with covering if it becomes
+    // blocked by adding covered literals. Each addition is pushed on the stack
+    // with the clause before it, and the blocked clause last, so that the
+    // reconstruction satisfies the extended clauses in reverse. Returns
+    // whether it did.
+    fn block_clause(&mut self, index: usize, cover: bool) -> bool {
+        let mut literals = self.clauses[index].clone();
+        let mut additions = Vec::new();
+        let mut position = 0;
+        while position < literals.len() {
+            let literal = literals[position];
+            position += 1;
+            if self.frozen[literal.atom().index()] {
+                continue
+            }
+            match self.covered(&literals, literal) {
+                None => {
+                    if 2 < self.verbosity {
+                        log::info!("Removing a clause of {} literals blocked on {}", self.clauses[index].len(), literal);
+                    }
+                    self.remove(index);
+                    self.stack.extend(additions);
+                    self.stack.push((literal, literals));
+                    return true
+                },
+                Some(covered) if cover => {
+                    let added: Vec<ast::Literal> = covered.into_iter().filter(|lit| !literals.contains(lit)).collect();
+                    if added.is_empty() || MAX_COVERED_LEN < literals.len() + added.len() {
+                        continue
+                    }
+                    additions.push((literal, literals.clone()));
+                    literals.extend(added);
+                    literals.sort_by_key(|lit| (lit.atom(), lit.phase()));
+                    // The added literals may block or cover in turn
+                    position = 0;
+                },
+                Some(_) => (),
+            }
+        }
+        false
+    }
+
+    // Removes blocked clauses, or covered ones, in passes until none are, as
+    // removing a clause may block others
+    fn block(&mut self, cover: bool) {
+        let mut blocked = 0;
+        loop {
+            let before = blocked;
+            for index in 0..self.clauses.len() {
+                if !self.removed[index] && self.block_clause(index, cover) {
+                    blocked += 1;
+                }
+            }
+            if blocked == before {
+                break
+            }
+        }
+        if 0 < self.verbosity {
+            log::info!("Removed {} {} clauses", blocked, if cover { "covered" } else { "blocked" });
+        }
+    }
+
+    // The simplified cnf, in which units are kept as unit clauses
+    pub fn cnf(&self) -> ast::Cnf {
+        let mut cnf = self.template.clone();
+        if self.unsat {
+            cnf.add(ast::Clause::new());
+            return cnf
+        }
+        for (index, value) in self.values.iter().enumerate() {
+            if let Some(phase) = *value {
+                cnf.add(ast::Clause::from(vec![ast::Literal::new(phase, ast::Atom::new(index as u32))]));
+            }
+        }
+        for (index, literals) in self.clauses.iter().enumerate() {
+            if !self.removed[index] {
+                cnf.add(ast::Clause::from(literals.clone()));
+            }
+        }
+        cnf
+    }
+
+    // Extends a model of the simplified cnf to a model of the original: atoms
+    // it leaves unassigned are taken to be false, and then each clause removed
+    // by elimination or blocking, from the last, is satisfied by its literal if
+    // it is false
+    pub fn extend(&self, asgmt: &mut ast::Asgmt) {
+        for (_, clause) in self.stack.iter() {
+            for literal in clause {
+                if asgmt.get(&literal.atom()).is_none() {
+                    asgmt.insert(literal.atom(), false);
+                }
+            }
+        }
+        for (literal, clause) in self.stack.iter().rev() {
+            if clause.iter().all(|lit| asgmt.get(&lit.atom()) != Some(lit.phase())) {
+                asgmt.insert(literal.atom(), literal.phase());
+            }
+        }
+    }
+}
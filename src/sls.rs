@@ -0,0 +1,389 @@
+use itertools::Itertools;
+
+use crate::ast;
+use crate::util;
+use crate::xor;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Algorithm {
+    // Flips a variable of a random unsatisfied clause which breaks no clause if
+    // possible, and otherwise with some probability (the noise) a random one,
+    // or else one breaking the fewest clauses
+    #[value(name = "walksat")]
+    WalkSat,
+    // Flips a variable of a random unsatisfied clause, with a probability
+    // decreasing polynomially in the number of clauses it breaks
+    #[value(name = "probsat")]
+    ProbSat,
+    // Configuration checking with neighboring variables and aspiration, with
+    // clause weighting (Cai and Su)
+    #[value(name = "ccanr")]
+    CcAnr,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Params {
+    pub algorithm: Algorithm,
+    // The probability of a random walk step in WalkSAT
+    pub noise: f64,
+    pub max_flips: usize,
+    pub max_tries: usize,
+    pub seed: u64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::ProbSat,
+            noise: 0.567,
+            max_flips: 1_000_000,
+            max_tries: 10,
+            seed: 1,
+        }
+    }
+}
+
+// The break-only polynomial probSAT distribution for 3-SAT
+const PROBSAT_CB: f64 = 2.38;
+const PROBSAT_EPS: f64 = 1.0;
+
+// Clause weights are smoothed when their average exceeds this threshold,
+// scaling them by the factor and adding a share of the average
+const SWT_THRESHOLD: u64 = 50;
+const SWT_P: f64 = 0.3;
+const SWT_Q: f64 = 0.7;
+
+// The state of a local search over a complete assignment. Each clause counts
+// its true literals and, through the xor of their atoms, knows its one true
+// atom when there is just one. Scores are weighted by the clause weights, which
+// are all one except in CCAnr.
+struct Search {
+    clauses: Vec<Vec<ast::Literal>>,
+    // Clauses by the index of a literal they contain
+    occurs: Vec<Vec<usize>>,
+    // Atoms sharing a clause, by atom index
+    neighbors: Vec<Vec<usize>>,
+    atoms: Vec<usize>,
+
+    values: Vec<bool>,
+    true_count: Vec<usize>,
+    true_xor: Vec<usize>,
+    weights: Vec<u64>,
+    total_weight: u64,
+    // The weight of unsatisfied clauses which flipping the atom would satisfy,
+    // and of satisfied clauses which it would unsatisfy
+    make: Vec<u64>,
+    breaks: Vec<u64>,
+    // Unsatisfied clauses, with the position of each clause in the list
+    unsat: Vec<usize>,
+    unsat_position: Vec<usize>,
+    // CCAnr's configuration of each atom: whether a neighbor has flipped since
+    // it last flipped, and the step of its last flip
+    changed: Vec<bool>,
+    flipped_at: Vec<usize>,
+    // The atoms whose configuration changed and whose flip would improve the
+    // score, with the position of each in the list
+    candidates: Vec<usize>,
+    candidate_position: Vec<Option<usize>>,
+
+    rng: util::Rng,
+}
+
+impl Search {
+    fn new(cnf: &ast::Cnf, seed: u64) -> Self {
+        // Without duplicate literals or tautologies, which would confuse the
+        // counts of true literals
+        let clauses: Vec<Vec<ast::Literal>> = cnf.clauses()
+            .map(|clause| clause.literals().cloned().sorted().dedup().collect::<Vec<_>>())
+            .filter(|literals| literals.iter().tuple_windows().all(|(a, b)| a.atom() != b.atom()))
+            .collect();
+        let size = cnf.declared_atoms().chain(cnf.atoms()).map(|atom| atom.index() + 1).max().unwrap_or(0);
+        let mut occurs = vec![Vec::new(); 2 * size];
+        let mut neighbors = vec![Vec::new(); size];
+        for (i, clause) in clauses.iter().enumerate() {
+            for literal in clause {
+                occurs[literal.index()].push(i);
+                neighbors[literal.atom().index()].extend(clause.iter().map(|lit| lit.atom().index()));
+            }
+        }
+        for (atom, atom_neighbors) in neighbors.iter_mut().enumerate() {
+            atom_neighbors.sort();
+            atom_neighbors.dedup();
+            atom_neighbors.retain(|&neighbor| neighbor != atom);
+        }
+        let mut atoms: Vec<usize> = cnf.declared_atoms().chain(cnf.atoms()).map(|atom| atom.index()).collect();
+        atoms.sort();
+        atoms.dedup();
+        let num_clauses = clauses.len();
+        Self {
+            clauses,
+            occurs,
+            neighbors,
+            atoms,
+            values: vec![false; size],
+            true_count: vec![0; num_clauses],
+            true_xor: vec![0; num_clauses],
+            weights: vec![1; num_clauses],
+            total_weight: num_clauses as u64,
+            make: vec![0; size],
+            breaks: vec![0; size],
+            unsat: Vec::new(),
+            unsat_position: vec![0; num_clauses],
+            changed: vec![true; size],
+            flipped_at: vec![0; size],
+            candidates: Vec::new(),
+            candidate_position: vec![None; size],
+            rng: util::Rng::new(seed),
+        }
+    }
+
+    fn is_true(&self, literal: ast::Literal) -> bool {
+        self.values[literal.atom().index()] == literal.phase()
+    }
+
+    fn score(&self, atom: usize) -> i64 {
+        self.make[atom] as i64 - self.breaks[atom] as i64
+    }
+
+    fn add_unsat(&mut self, clause: usize) {
+        self.unsat_position[clause] = self.unsat.len();
+        self.unsat.push(clause);
+    }
+
+    fn remove_unsat(&mut self, clause: usize) {
+        let position = self.unsat_position[clause];
+        let last = self.unsat.pop().unwrap();
+        if last != clause {
+            self.unsat[position] = last;
+            self.unsat_position[last] = position;
+        }
+    }
+
+    // Adds the atom to the candidates or removes it, as it now qualifies
+    fn update_candidate(&mut self, atom: usize) {
+        let qualifies = self.changed[atom] && 0 < self.score(atom);
+        match (qualifies, self.candidate_position[atom]) {
+            (true, None) => {
+                self.candidate_position[atom] = Some(self.candidates.len());
+                self.candidates.push(atom);
+            },
+            (false, Some(position)) => {
+                self.candidate_position[atom] = None;
+                let last = self.candidates.pop().unwrap();
+                if last != atom {
+                    self.candidates[position] = last;
+                    self.candidate_position[last] = Some(position);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    // Computes the clause states, scores and candidates from the values and
+    // weights
+    fn recompute(&mut self) {
+        self.unsat.clear();
+        self.make.iter_mut().for_each(|make| *make = 0);
+        self.breaks.iter_mut().for_each(|breaks| *breaks = 0);
+        for i in 0..self.clauses.len() {
+            let true_atoms: Vec<usize> = self.clauses[i].iter()
+                .filter(|&&lit| self.is_true(lit))
+                .map(|lit| lit.atom().index())
+                .collect();
+            self.true_count[i] = true_atoms.len();
+            self.true_xor[i] = true_atoms.iter().fold(0, |acc, &atom| acc ^ atom);
+            match true_atoms[..] {
+                [] => {
+                    self.add_unsat(i);
+                    for k in 0..self.clauses[i].len() {
+                        self.make[self.clauses[i][k].atom().index()] += self.weights[i];
+                    }
+                },
+                [atom] => self.breaks[atom] += self.weights[i],
+                _ => (),
+            }
+        }
+        self.total_weight = self.weights.iter().sum();
+        self.candidates.clear();
+        self.candidate_position.iter_mut().for_each(|position| *position = None);
+        for k in 0..self.atoms.len() {
+            self.update_candidate(self.atoms[k]);
+        }
+    }
+
+    fn randomize(&mut self) {
+        for &atom in self.atoms.iter() {
+            self.values[atom] = self.rng.next_bool();
+        }
+        self.changed.iter_mut().for_each(|changed| *changed = true);
+        self.flipped_at.iter_mut().for_each(|step| *step = 0);
+        self.recompute();
+    }
+
+    fn flip(&mut self, atom: usize, step: usize) {
+        let phase = !self.values[atom];
+        self.values[atom] = phase;
+        let now_true = ast::Literal::new(phase, ast::Atom::new(atom as u32));
+        for k in 0..self.occurs[now_true.index()].len() {
+            let i = self.occurs[now_true.index()][k];
+            self.true_count[i] += 1;
+            self.true_xor[i] ^= atom;
+            match self.true_count[i] {
+                1 => {
+                    self.remove_unsat(i);
+                    for lit in self.clauses[i].iter() {
+                        self.make[lit.atom().index()] -= self.weights[i];
+                    }
+                    self.breaks[atom] += self.weights[i];
+                },
+                2 => self.breaks[self.true_xor[i] ^ atom] -= self.weights[i],
+                _ => (),
+            }
+        }
+        let now_false = now_true.inversion();
+        for k in 0..self.occurs[now_false.index()].len() {
+            let i = self.occurs[now_false.index()][k];
+            self.true_count[i] -= 1;
+            self.true_xor[i] ^= atom;
+            match self.true_count[i] {
+                0 => {
+                    self.add_unsat(i);
+                    for lit in self.clauses[i].iter() {
+                        self.make[lit.atom().index()] += self.weights[i];
+                    }
+                    self.breaks[atom] -= self.weights[i];
+                },
+                1 => self.breaks[self.true_xor[i]] += self.weights[i],
+                _ => (),
+            }
+        }
+        self.changed[atom] = false;
+        self.flipped_at[atom] = step;
+        self.update_candidate(atom);
+        // The scores of no other atoms than the neighbors have changed
+        for k in 0..self.neighbors[atom].len() {
+            let neighbor = self.neighbors[atom][k];
+            self.changed[neighbor] = true;
+            self.update_candidate(neighbor);
+        }
+    }
+
+    fn random_unsat_clause(&mut self) -> usize {
+        self.unsat[self.rng.below(self.unsat.len())]
+    }
+
+    fn pick_walksat(&mut self, noise: f64) -> usize {
+        let clause = self.random_unsat_clause();
+        let atoms: Vec<usize> = self.clauses[clause].iter().map(|lit| lit.atom().index()).collect();
+        let least = atoms.iter().map(|&atom| self.breaks[atom]).min().unwrap();
+        if least != 0 && self.rng.next_f64() < noise {
+            return atoms[self.rng.below(atoms.len())]
+        }
+        let best: Vec<usize> = atoms.into_iter().filter(|&atom| self.breaks[atom] == least).collect();
+        best[self.rng.below(best.len())]
+    }
+
+    fn pick_probsat(&mut self) -> usize {
+        let clause = self.random_unsat_clause();
+        let probabilities: Vec<f64> = self.clauses[clause].iter()
+            .map(|lit| (PROBSAT_EPS + self.breaks[lit.atom().index()] as f64).powf(-PROBSAT_CB))
+            .collect();
+        let total: f64 = probabilities.iter().sum();
+        let mut threshold = total * self.rng.next_f64();
+        for (k, probability) in probabilities.iter().enumerate() {
+            if threshold < *probability {
+                return self.clauses[clause][k].atom().index()
+            }
+            threshold -= probability;
+        }
+        self.clauses[clause].last().unwrap().atom().index()
+    }
+
+    fn pick_ccanr(&mut self) -> usize {
+        // The best of the candidates, or else of the atoms whose flip would
+        // improve the score by more than the average weight, preferring older
+        // flips on ties. Only atoms of unsatisfied clauses improve it at all.
+        let average = self.total_weight as i64 / self.weights.len().max(1) as i64;
+        let key = |search: &Self, atom: usize| (search.score(atom), std::cmp::Reverse(search.flipped_at[atom]));
+        let decreasing = self.candidates.iter()
+            .max_by_key(|&&atom| key(self, atom));
+        let aspiring = || self.unsat.iter()
+            .flat_map(|&i| self.clauses[i].iter().map(|lit| lit.atom().index()))
+            .filter(|&atom| average < self.score(atom))
+            .max_by_key(|&atom| key(self, atom));
+        if let Some(atom) = decreasing.copied().or_else(aspiring) {
+            return atom
+        }
+
+        // Otherwise weight the unsatisfied clauses, and flip the atom of a
+        // random one which has the best score
+        self.total_weight += self.unsat.len() as u64;
+        for k in 0..self.unsat.len() {
+            let i = self.unsat[k];
+            self.weights[i] += 1;
+            for j in 0..self.clauses[i].len() {
+                let atom = self.clauses[i][j].atom().index();
+                self.make[atom] += 1;
+                self.update_candidate(atom);
+            }
+        }
+        if SWT_THRESHOLD < average as u64 {
+            let average = average as f64;
+            for weight in self.weights.iter_mut() {
+                *weight = ((SWT_P * *weight as f64 + SWT_Q * average) as u64).max(1);
+            }
+            self.recompute();
+        }
+        let clause = self.random_unsat_clause();
+        self.clauses[clause].iter()
+            .map(|lit| lit.atom().index())
+            .max_by_key(|&atom| key(self, atom))
+            .unwrap()
+    }
+}
+
+// Searches for a model by local search, returning None when the limits are
+// reached without finding one. The search is incomplete, so this does not mean
+// the cnf is unsatisfiable.
+pub fn solve(cnf: &ast::Cnf, params: &Params, verbosity: usize) -> Option<ast::Asgmt> {
+    let mut expanded = cnf.clone();
+    xor::expand(&mut expanded);
+    if expanded.clauses().any(|clause| clause.is_empty()) {
+        return None
+    }
+    let mut search = Search::new(&expanded, params.seed);
+    for attempt in 1..=params.max_tries {
+        search.randomize();
+        for step in 1..=params.max_flips + 1 {
+            if search.unsat.is_empty() {
+                if 0 < verbosity {
+                    log::info!("SAT after {} flips on try {}", step - 1, attempt);
+                }
+                let mut asgmt = ast::Asgmt::new();
+                for atom in cnf.declared_atoms().chain(cnf.atoms()) {
+                    asgmt.insert(atom, search.values[atom.index()]);
+                }
+                return Some(asgmt)
+            }
+            if step == params.max_flips + 1 {
+                break
+            }
+            let atom = match params.algorithm {
+                Algorithm::WalkSat => search.pick_walksat(params.noise),
+                Algorithm::ProbSat => search.pick_probsat(),
+                Algorithm::CcAnr => search.pick_ccanr(),
+            };
+            if 3 < verbosity {
+                log::info!("Flipping {} with {} unsatisfied clauses", atom, search.unsat.len());
+            }
+            search.flip(atom, step);
+        }
+        if 0 < verbosity {
+            log::info!("Try {} failed with {} unsatisfied clauses", attempt, search.unsat.len());
+        }
+    }
+    None
+}
@@ -0,0 +1,739 @@
+use std::collections::HashMap;
+use std::{fs, path};
+
+use itertools::Itertools;
+
+use crate::ast;
+use crate::bv;
+use crate::dpll;
+use crate::idl;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Sexp {
+    Symbol(String),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    fn symbol(&self) -> Option<&str> {
+        match self {
+            Sexp::Symbol(symbol) => Some(symbol),
+            Sexp::List(_) => None,
+        }
+    }
+
+    // The head symbol and the arguments of an application
+    fn application(&self) -> Option<(&str, &[Sexp])> {
+        match self {
+            Sexp::List(list) => Some((list.first()?.symbol()?, &list[1..])),
+            Sexp::Symbol(_) => None,
+        }
+    }
+}
+
+// Parses the s-expressions of a script, skipping comments. Quoted symbols
+// such as |x y| keep their bars, and strings their quotes.
+pub fn parse(text: &str) -> Option<Vec<Sexp>> {
+    let mut stack: Vec<Vec<Sexp>> = vec![Vec::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            },
+            '(' => stack.push(Vec::new()),
+            ')' => {
+                let list = stack.pop()?;
+                stack.last_mut()?.push(Sexp::List(list));
+            },
+            '|' | '"' => {
+                let mut symbol = String::from(c);
+                loop {
+                    let next = chars.next()?;
+                    symbol.push(next);
+                    if next == c {
+                        break
+                    }
+                }
+                stack.last_mut()?.push(Sexp::Symbol(symbol));
+            },
+            _ if c.is_whitespace() => (),
+            _ => {
+                let mut symbol = String::from(c);
+                while let Some(next) = chars.next_if(|&c| !c.is_whitespace() && !"();|\"".contains(c)) {
+                    symbol.push(next);
+                }
+                stack.last_mut()?.push(Sexp::Symbol(symbol));
+            },
+        }
+    }
+    match stack.len() {
+        1 => stack.pop(),
+        _ => None,
+    }
+}
+
+// The commands of a script which state the problem: the names and sorts of
+// the constants declared, and the terms asserted
+struct Statement<'a> {
+    logic: Option<&'a str>,
+    declarations: Vec<(&'a str, &'a Sexp)>,
+    assertions: Vec<&'a Sexp>,
+}
+
+// The statement of the script, whose other commands are ignored
+fn statement(script: &[Sexp]) -> Option<Statement<'_>> {
+    let mut logic = None;
+    let mut declarations = Vec::new();
+    let mut assertions = Vec::new();
+    for command in script {
+        match command.application()? {
+            ("set-logic", [name]) => logic = Some(name.symbol()?),
+            ("declare-const", [name, sort]) => declarations.push((name.symbol()?, sort)),
+            ("declare-fun", [name, Sexp::List(args), sort]) if args.is_empty() => {
+                declarations.push((name.symbol()?, sort))
+            },
+            ("assert", [term]) => assertions.push(term),
+            ("set-info" | "set-option" | "check-sat" | "get-model" | "exit", _) => (),
+            _ => return None,
+        }
+    }
+    Some(Statement { logic, declarations, assertions })
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// A problem of quantifier-free integer difference logic (QF_IDL): a cnf whose
+// atoms include those the theory defines as difference constraints. Integer
+// variable 0 stands for zero.
+#[derive(Clone, Debug)]
+pub struct IdlProblem {
+    pub cnf: ast::Cnf,
+    pub theory: idl::DifferenceLogic,
+    pub bools: Vec<(String, ast::Atom)>,
+    pub ints: Vec<(String, idl::Var)>,
+}
+
+// A model of an IDL problem, by the names of its variables
+#[derive(Clone, Debug)]
+pub struct IdlModel {
+    pub bools: Vec<(String, bool)>,
+    pub ints: Vec<(String, i64)>,
+}
+
+// Encodes the Boolean structure of the terms by Tseitin's encoding, and their
+// comparisons by atoms of difference constraints
+struct IdlEncoder {
+    cnf: ast::Cnf,
+    bools: HashMap<String, ast::Atom>,
+    ints: HashMap<String, idl::Var>,
+    constraints: HashMap<idl::Constraint, ast::Atom>,
+    truth: Option<ast::Literal>,
+}
+
+impl IdlEncoder {
+    fn constant(&mut self, value: bool) -> ast::Literal {
+        let truth = *self.truth.get_or_insert_with(|| {
+            let literal = ast::Literal::new(true, self.cnf.fresh_atom());
+            self.cnf.add(ast::Clause::from(vec![literal]));
+            literal
+        });
+        if value { truth } else { truth.inversion() }
+    }
+
+    // The literal standing for the constraint, or for the negation of one
+    // equivalent to its negation
+    fn constraint(&mut self, constraint: idl::Constraint) -> ast::Literal {
+        if let Some(&atom) = self.constraints.get(&constraint) {
+            return ast::Literal::new(true, atom)
+        }
+        if let Some(&atom) = self.constraints.get(&constraint.negation()) {
+            return ast::Literal::new(false, atom)
+        }
+        let atom = self.cnf.fresh_atom();
+        self.constraints.insert(constraint, atom);
+        ast::Literal::new(true, atom)
+    }
+
+    // A fresh literal equivalent to the conjunction (or disjunction) of the
+    // literals
+    fn junction(&mut self, conjunction: bool, literals: Vec<ast::Literal>) -> ast::Literal {
+        let literal = ast::Literal::new(conjunction, self.cnf.fresh_atom());
+        // As a disjunction is the negation of the conjunction of the inversions
+        let literals: Vec<ast::Literal> = literals.into_iter()
+            .map(|lit| if conjunction { lit } else { lit.inversion() })
+            .collect();
+        let positive = if conjunction { literal } else { literal.inversion() };
+        for &lit in literals.iter() {
+            self.cnf.add(ast::Clause::from(vec![positive.inversion(), lit]));
+        }
+        self.cnf.add(ast::Clause::from([positive].into_iter()
+            .chain(literals.iter().map(|lit| lit.inversion()))
+            .collect::<Vec<_>>()));
+        literal
+    }
+
+    // A fresh literal equivalent to the condition choosing between the two
+    fn ite(&mut self, condition: ast::Literal, then: ast::Literal, otherwise: ast::Literal) -> ast::Literal {
+        let literal = ast::Literal::new(true, self.cnf.fresh_atom());
+        for (guard, value) in [(condition.inversion(), then), (condition, otherwise)] {
+            self.cnf.add(ast::Clause::from(vec![guard, literal.inversion(), value]));
+            self.cnf.add(ast::Clause::from(vec![guard, literal, value.inversion()]));
+        }
+        literal
+    }
+
+    fn iff(&mut self, a: ast::Literal, b: ast::Literal) -> ast::Literal {
+        self.ite(a, b, b.inversion())
+    }
+
+    // The linear integer term as coefficients of its variables and a constant
+    fn linear(&self, term: &Sexp) -> Option<(HashMap<idl::Var, i64>, i64)> {
+        if let Some(symbol) = term.symbol() {
+            if let Ok(value) = symbol.parse::<i64>() {
+                return Some((HashMap::new(), value))
+            }
+            return Some((HashMap::from([(*self.ints.get(symbol)?, 1)]), 0))
+        }
+        let (head, args) = term.application()?;
+        let mut terms = args.iter().map(|arg| self.linear(arg)).collect::<Option<Vec<_>>>()?;
+        let scale = |(mut coefficients, constant): (HashMap<idl::Var, i64>, i64), factor: i64| {
+            coefficients.values_mut().for_each(|coefficient| *coefficient *= factor);
+            (coefficients, constant * factor)
+        };
+        let sum = |terms: Vec<(HashMap<idl::Var, i64>, i64)>| {
+            terms.into_iter().fold((HashMap::new(), 0), |(mut coefficients, constant), (other, other_constant)| {
+                for (var, coefficient) in other {
+                    *coefficients.entry(var).or_insert(0) += coefficient;
+                }
+                (coefficients, constant + other_constant)
+            })
+        };
+        match head {
+            "+" => Some(sum(terms)),
+            "-" if terms.len() == 1 => Some(scale(terms.pop()?, -1)),
+            "-" => {
+                let first = terms.remove(0);
+                Some(sum([first].into_iter().chain(terms.into_iter().map(|term| scale(term, -1))).collect()))
+            },
+            // A product is linear only if all but one of its factors are
+            // constants
+            "*" => {
+                let (constants, vars): (Vec<_>, Vec<_>) = terms.into_iter().partition(|(coefficients, _)| coefficients.is_empty());
+                let factor = constants.iter().map(|(_, constant)| constant).product();
+                match &vars[..] {
+                    [] => Some((HashMap::new(), factor)),
+                    [term] => Some(scale(term.clone(), factor)),
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
+    }
+
+    // The literal of the comparison of two integer terms, whose difference
+    // must be a difference of variables (or one variable) and a constant
+    fn comparison(&mut self, relation: &str, lhs: &Sexp, rhs: &Sexp) -> Option<ast::Literal> {
+        let (mut coefficients, lhs_constant) = self.linear(lhs)?;
+        let (rhs_coefficients, rhs_constant) = self.linear(rhs)?;
+        for (var, coefficient) in rhs_coefficients {
+            *coefficients.entry(var).or_insert(0) -= coefficient;
+        }
+        coefficients.retain(|_, coefficient| *coefficient != 0);
+        // The comparison is of x - y + constant with zero
+        let constant = lhs_constant - rhs_constant;
+        let (x, y) = match coefficients.into_iter().sorted_by_key(|&(_, coefficient)| -coefficient).collect::<Vec<_>>()[..] {
+            [] => (0, 0),
+            [(x, 1)] => (x, 0),
+            [(y, -1)] => (0, y),
+            [(x, 1), (y, -1)] => (x, y),
+            _ => return None,
+        };
+        let at_most = idl::Constraint { x, y, bound: -constant };
+        let at_least = idl::Constraint { x: y, y: x, bound: constant };
+        let literal = |encoder: &mut Self, constraint: idl::Constraint| {
+            if x == y {
+                encoder.constant(0 <= constraint.bound)
+            } else {
+                encoder.constraint(constraint)
+            }
+        };
+        match relation {
+            "<=" => Some(literal(self, at_most)),
+            "<" => Some(literal(self, idl::Constraint { bound: at_most.bound - 1, ..at_most })),
+            ">=" => Some(literal(self, at_least)),
+            ">" => Some(literal(self, idl::Constraint { bound: at_least.bound - 1, ..at_least })),
+            "=" | "distinct" => {
+                let equal = vec![literal(self, at_most), literal(self, at_least)];
+                let literal = self.junction(true, equal);
+                Some(if relation == "=" { literal } else { literal.inversion() })
+            },
+            _ => None,
+        }
+    }
+
+    fn is_int(&self, term: &Sexp) -> bool {
+        match term {
+            Sexp::Symbol(symbol) => symbol.parse::<i64>().is_ok() || self.ints.contains_key(symbol),
+            Sexp::List(_) => matches!(term.application(), Some(("+" | "-" | "*", _))),
+        }
+    }
+
+    // The literal of the Boolean term
+    fn encode(&mut self, term: &Sexp) -> Option<ast::Literal> {
+        if let Some(symbol) = term.symbol() {
+            return match symbol {
+                "true" => Some(self.constant(true)),
+                "false" => Some(self.constant(false)),
+                _ => Some(ast::Literal::new(true, *self.bools.get(symbol)?)),
+            }
+        }
+        let (head, args) = term.application()?;
+        match (head, args) {
+            ("!", [term, ..]) => self.encode(term),
+            ("<=" | "<" | ">=" | ">" | "=" | "distinct", [lhs, rhs]) if self.is_int(lhs) || self.is_int(rhs) => {
+                self.comparison(head, lhs, rhs)
+            },
+            ("not", [arg]) => Some(self.encode(arg)?.inversion()),
+            ("and" | "or", _) => {
+                let literals = args.iter().map(|arg| self.encode(arg)).collect::<Option<Vec<_>>>()?;
+                Some(self.junction(head == "and", literals))
+            },
+            // Implication associates to the right
+            ("=>", [.., _]) => {
+                let mut literals = args.iter().map(|arg| self.encode(arg)).collect::<Option<Vec<_>>>()?;
+                let last = literals.pop()?;
+                let literals = literals.into_iter().map(|literal| literal.inversion()).chain([last]).collect();
+                Some(self.junction(false, literals))
+            },
+            ("xor", [a, b]) | ("distinct", [a, b]) => {
+                let (a, b) = (self.encode(a)?, self.encode(b)?);
+                Some(self.iff(a, b).inversion())
+            },
+            ("=", [a, b]) => {
+                let (a, b) = (self.encode(a)?, self.encode(b)?);
+                Some(self.iff(a, b))
+            },
+            ("ite", [condition, then, otherwise]) => {
+                let (condition, then, otherwise) = (self.encode(condition)?, self.encode(then)?, self.encode(otherwise)?);
+                Some(self.ite(condition, then, otherwise))
+            },
+            _ => None,
+        }
+    }
+}
+
+// Reads a script of the QF_IDL logic of SMT-LIB 2, without let, defined
+// functions, or chained comparisons
+pub fn read_idl<P>(path: P, verbosity: usize) -> Option<IdlProblem>
+where P: AsRef<path::Path> {
+    let script = parse(&fs::read_to_string(path).ok()?)?;
+    idl_problem(&statement(&script)?, verbosity)
+}
+
+fn idl_problem(statement: &Statement, verbosity: usize) -> Option<IdlProblem> {
+    let mut encoder = IdlEncoder {
+        cnf: ast::Cnf::new(),
+        bools: HashMap::new(),
+        ints: HashMap::new(),
+        constraints: HashMap::new(),
+        truth: None,
+    };
+    let mut bools = Vec::new();
+    let mut ints = Vec::new();
+    for &(name, sort) in statement.declarations.iter() {
+        match sort.symbol()? {
+            "Bool" => {
+                let atom = encoder.cnf.fresh_atom();
+                encoder.bools.insert(name.to_string(), atom);
+                bools.push((name.to_string(), atom));
+            },
+            "Int" => {
+                // Variable 0 is zero
+                let var = ints.len() + 1;
+                encoder.ints.insert(name.to_string(), var);
+                ints.push((name.to_string(), var));
+            },
+            _ => return None,
+        }
+    }
+    for &assertion in statement.assertions.iter() {
+        let literal = encoder.encode(assertion)?;
+        encoder.cnf.add(ast::Clause::from(vec![literal]));
+    }
+    let mut theory = idl::DifferenceLogic::new(ints.len() + 1, verbosity);
+    for (constraint, atom) in encoder.constraints {
+        theory.define(atom, constraint);
+    }
+    if 3 < verbosity {
+        log::info!("Read IDL problem with cnf: {}", encoder.cnf);
+    }
+    Some(IdlProblem { cnf: encoder.cnf, theory, bools, ints })
+}
+
+pub fn solve_idl(problem: &IdlProblem, verbosity: usize) -> Option<IdlModel> {
+    let mut solver = dpll::Solver::with_theory(&problem.cnf, problem.theory.clone(), verbosity);
+    let asgmt = solver.solve()?;
+    let values = solver.theory().model();
+    Some(IdlModel {
+        bools: problem.bools.iter()
+            .map(|(name, atom)| (name.clone(), asgmt.get(atom).unwrap_or(false)))
+            .collect(),
+        ints: problem.ints.iter()
+            .map(|(name, var)| (name.clone(), values[*var]))
+            .collect(),
+    })
+}
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// A problem of quantifier-free bit-vectors (QF_BV), bit-blasted: a cnf along
+// with the literals of the declared constants
+#[derive(Clone, Debug)]
+pub struct BvProblem {
+    pub cnf: ast::Cnf,
+    pub bools: Vec<(String, ast::Literal)>,
+    pub bvs: Vec<(String, bv::BitVec)>,
+}
+
+// A model of a BV problem, by the names of its constants
+#[derive(Clone, Debug)]
+pub struct BvModel {
+    pub bools: Vec<(String, bool)>,
+    pub bvs: Vec<(String, bv::Value)>,
+}
+
+#[derive(Clone, Debug)]
+enum BvTerm {
+    Bool(ast::Literal),
+    Bv(bv::BitVec),
+}
+
+// Bit-blasts terms, whose names are bound by the declarations and then by
+// the enclosing lets, innermost last
+struct BvEncoder {
+    blaster: bv::Blaster,
+    scopes: Vec<HashMap<String, BvTerm>>,
+}
+
+fn bv_width(sort: &Sexp) -> Option<usize> {
+    match sort.application()? {
+        ("_", [name, width]) if name.symbol()? == "BitVec" => width.symbol()?.parse().ok(),
+        _ => None,
+    }
+}
+
+impl BvEncoder {
+    fn literal(&mut self, term: &Sexp) -> Option<ast::Literal> {
+        match self.encode(term)? {
+            BvTerm::Bool(literal) => Some(literal),
+            BvTerm::Bv(_) => None,
+        }
+    }
+
+    fn bits(&mut self, term: &Sexp) -> Option<bv::BitVec> {
+        match self.encode(term)? {
+            BvTerm::Bv(bits) => Some(bits),
+            BvTerm::Bool(_) => None,
+        }
+    }
+
+    // The bits of arguments of equal width
+    fn operands(&mut self, args: &[Sexp]) -> Option<Vec<bv::BitVec>> {
+        let operands = args.iter().map(|arg| self.bits(arg)).collect::<Option<Vec<_>>>()?;
+        operands.iter().map(|bits| bits.len()).all_equal().then_some(operands)
+    }
+
+    // The constant of the given bits, most significant first
+    fn constant(&self, bits: impl DoubleEndedIterator<Item = bool>) -> bv::BitVec {
+        bits.rev().map(|bit| self.blaster.constant(bit)).collect()
+    }
+
+    fn symbol(&mut self, symbol: &str) -> Option<BvTerm> {
+        if let Some(binary) = symbol.strip_prefix("#b") {
+            return Some(BvTerm::Bv(self.constant(binary.chars().map(|c| c == '1'))))
+        }
+        if let Some(hex) = symbol.strip_prefix("#x") {
+            let digits = hex.chars().map(|c| c.to_digit(16)).collect::<Option<Vec<_>>>()?;
+            let bits = digits.into_iter().flat_map(|digit| (0..4).rev().map(move |i| (digit >> i) & 1 == 1));
+            return Some(BvTerm::Bv(self.constant(bits.collect::<Vec<_>>().into_iter())))
+        }
+        match symbol {
+            "true" => Some(BvTerm::Bool(self.blaster.constant(true))),
+            "false" => Some(BvTerm::Bool(self.blaster.constant(false))),
+            _ => self.scopes.iter().rev().find_map(|scope| scope.get(symbol)).cloned(),
+        }
+    }
+
+    // Applications of indexed operators such as ((_ extract 7 4) x)
+    fn indexed(&mut self, head: &Sexp, args: &[Sexp]) -> Option<BvTerm> {
+        let (name, indices) = match head.application()? {
+            ("_", [name, indices @ ..]) => (name.symbol()?, indices),
+            _ => return None,
+        };
+        let indices = indices.iter().map(|index| index.symbol()?.parse::<usize>().ok()).collect::<Option<Vec<_>>>()?;
+        let bits = match args {
+            [arg] => self.bits(arg)?,
+            _ => return None,
+        };
+        let width = bits.len();
+        let bits = match (name, &indices[..]) {
+            ("extract", &[high, low]) if low <= high && high < width => bv::extract(&bits, high, low),
+            ("zero_extend", &[extra]) => bv::concat(&vec![self.blaster.constant(false); extra], &bits),
+            ("sign_extend", &[extra]) => bv::concat(&vec![*bits.last()?; extra], &bits),
+            ("repeat", &[times]) if 0 < times => bits.repeat(times),
+            ("rotate_left", &[distance]) => {
+                let mut bits = bits;
+                bits.rotate_right(distance % width.max(1));
+                bits
+            },
+            ("rotate_right", &[distance]) => {
+                let mut bits = bits;
+                bits.rotate_left(distance % width.max(1));
+                bits
+            },
+            _ => return None,
+        };
+        Some(BvTerm::Bv(bits))
+    }
+
+    // Binds the names of the let in a new scope, each to a term of the
+    // enclosing ones, and encodes the body there
+    fn bind(&mut self, bindings: &Sexp, body: &Sexp) -> Option<BvTerm> {
+        let Sexp::List(bindings) = bindings else {
+            return None
+        };
+        let mut scope = HashMap::new();
+        for binding in bindings {
+            match binding {
+                Sexp::List(pair) if pair.len() == 2 => {
+                    let term = self.encode(&pair[1])?;
+                    scope.insert(pair[0].symbol()?.to_string(), term);
+                },
+                _ => return None,
+            }
+        }
+        self.scopes.push(scope);
+        let term = self.encode(body);
+        self.scopes.pop();
+        term
+    }
+
+    fn encode(&mut self, term: &Sexp) -> Option<BvTerm> {
+        let list = match term {
+            Sexp::Symbol(symbol) => return self.symbol(symbol),
+            Sexp::List(list) => list,
+        };
+        let (head, args) = list.split_first()?;
+        if let Sexp::List(_) = head {
+            return self.indexed(head, args)
+        }
+        let blaster = &mut self.blaster;
+        match (head.symbol()?, args) {
+            ("_", [value, width]) => {
+                let value = value.symbol()?.strip_prefix("bv")?.parse::<u128>().ok()?;
+                let width = width.symbol()?.parse::<usize>().ok()?;
+                Some(BvTerm::Bv((0..width).map(|i| blaster.constant(i < 128 && (value >> i) & 1 == 1)).collect()))
+            },
+            ("let", [bindings, body]) => self.bind(bindings, body),
+            ("!", [term, ..]) => self.encode(term),
+            ("not", [arg]) => Some(BvTerm::Bool(self.literal(arg)?.inversion())),
+            ("and" | "or" | "xor" | "=>", [_, ..]) => {
+                let head = head.symbol()?;
+                let literals = args.iter().map(|arg| self.literal(arg)).collect::<Option<Vec<_>>>()?;
+                let blaster = &mut self.blaster;
+                Some(BvTerm::Bool(match head {
+                    "and" => blaster.all(&literals),
+                    "or" => blaster.any(&literals),
+                    "xor" => literals.into_iter().reduce(|a, b| blaster.xor(a, b))?,
+                    // Implication associates to the right
+                    _ => literals.into_iter().rev().reduce(|b, a| blaster.or(a.inversion(), b))?,
+                }))
+            },
+            ("=" | "distinct", [a, b]) => {
+                let equal = match (self.encode(a)?, self.encode(b)?) {
+                    (BvTerm::Bool(a), BvTerm::Bool(b)) => self.blaster.iff(a, b),
+                    (BvTerm::Bv(a), BvTerm::Bv(b)) if a.len() == b.len() => self.blaster.eq(&a, &b),
+                    _ => return None,
+                };
+                Some(BvTerm::Bool(if head.symbol()? == "=" { equal } else { equal.inversion() }))
+            },
+            ("ite", [condition, then, otherwise]) => {
+                let condition = self.literal(condition)?;
+                match (self.encode(then)?, self.encode(otherwise)?) {
+                    (BvTerm::Bool(a), BvTerm::Bool(b)) => Some(BvTerm::Bool(self.blaster.mux(condition, a, b))),
+                    (BvTerm::Bv(a), BvTerm::Bv(b)) if a.len() == b.len() => Some(BvTerm::Bv(self.blaster.ite(condition, &a, &b))),
+                    _ => None,
+                }
+            },
+            ("bvnot", [arg]) => Some(BvTerm::Bv(bv::not(&self.bits(arg)?))),
+            ("bvneg", [arg]) => {
+                let bits = self.bits(arg)?;
+                Some(BvTerm::Bv(self.blaster.neg(&bits)))
+            },
+            ("concat", [_, ..]) => {
+                let operands = args.iter().map(|arg| self.bits(arg)).collect::<Option<Vec<_>>>()?;
+                Some(BvTerm::Bv(operands.into_iter().reduce(|high, low| bv::concat(&high, &low))?))
+            },
+            // Left-associative operators
+            ("bvadd" | "bvmul" | "bvand" | "bvor" | "bvxor", [_, ..]) => {
+                let head = head.symbol()?;
+                let operands = self.operands(args)?;
+                let blaster = &mut self.blaster;
+                Some(BvTerm::Bv(operands.into_iter().reduce(|a, b| match head {
+                    "bvadd" => blaster.add(&a, &b),
+                    "bvmul" => blaster.mul(&a, &b),
+                    "bvand" => blaster.bitwise(&a, &b, bv::Blaster::and),
+                    "bvor" => blaster.bitwise(&a, &b, bv::Blaster::or),
+                    _ => blaster.bitwise(&a, &b, bv::Blaster::xor),
+                })?))
+            },
+            (op, [_, _]) => {
+                let operands = self.operands(args)?;
+                let (a, b) = (&operands[0], &operands[1]);
+                let blaster = &mut self.blaster;
+                let bits = match op {
+                    "bvsub" => blaster.sub(a, b),
+                    "bvshl" => blaster.shl(a, b),
+                    "bvlshr" => blaster.lshr(a, b),
+                    "bvashr" => blaster.ashr(a, b),
+                    "bvnand" => bv::not(&blaster.bitwise(a, b, bv::Blaster::and)),
+                    "bvnor" => bv::not(&blaster.bitwise(a, b, bv::Blaster::or)),
+                    "bvxnor" => blaster.bitwise(a, b, bv::Blaster::iff),
+                    "bvcomp" => vec![blaster.eq(a, b)],
+                    _ => return Some(BvTerm::Bool(match op {
+                        "bvult" => blaster.ult(a, b),
+                        "bvule" => blaster.ule(a, b),
+                        "bvugt" => blaster.ult(b, a),
+                        "bvuge" => blaster.ule(b, a),
+                        "bvslt" => blaster.slt(a, b),
+                        "bvsle" => blaster.sle(a, b),
+                        "bvsgt" => blaster.slt(b, a),
+                        "bvsge" => blaster.sle(b, a),
+                        _ => return None,
+                    })),
+                };
+                Some(BvTerm::Bv(bits))
+            },
+            _ => None,
+        }
+    }
+}
+
+// Reads a script of the QF_BV logic of SMT-LIB 2, without division or
+// defined functions
+pub fn read_bv<P>(path: P, verbosity: usize) -> Option<BvProblem>
+where P: AsRef<path::Path> {
+    let script = parse(&fs::read_to_string(path).ok()?)?;
+    bv_problem(&statement(&script)?, verbosity)
+}
+
+fn bv_problem(statement: &Statement, verbosity: usize) -> Option<BvProblem> {
+    let mut encoder = BvEncoder {
+        blaster: bv::Blaster::new(ast::Cnf::new()),
+        scopes: vec![HashMap::new()],
+    };
+    let mut bools = Vec::new();
+    let mut bvs = Vec::new();
+    for &(name, sort) in statement.declarations.iter() {
+        let term = if sort.symbol() == Some("Bool") {
+            let literal = encoder.blaster.fresh();
+            bools.push((name.to_string(), literal));
+            BvTerm::Bool(literal)
+        } else {
+            let bits = encoder.blaster.fresh_bits(bv_width(sort)?);
+            bvs.push((name.to_string(), bits.clone()));
+            BvTerm::Bv(bits)
+        };
+        encoder.scopes[0].insert(name.to_string(), term);
+    }
+    for &assertion in statement.assertions.iter() {
+        let literal = encoder.literal(assertion)?;
+        encoder.blaster.cnf.add(ast::Clause::from(vec![literal]));
+    }
+    if 3 < verbosity {
+        log::info!("Bit-blasted BV problem to cnf: {}", encoder.blaster.cnf);
+    }
+    Some(BvProblem { cnf: encoder.blaster.cnf, bools, bvs })
+}
+
+pub fn solve_bv(problem: &BvProblem, verbosity: usize) -> Option<BvModel> {
+    let asgmt = dpll::sat(&mut problem.cnf.clone(), verbosity)?;
+    Some(BvModel {
+        bools: problem.bools.iter()
+            .map(|(name, literal)| (name.clone(), asgmt.get(&literal.atom()) == Some(literal.phase())))
+            .collect(),
+        bvs: problem.bvs.iter()
+            .map(|(name, bits)| (name.clone(), bv::decode(bits, &asgmt)))
+            .collect(),
+    })
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Prints the result as an SMT-LIB solver would, followed by the model as
+// definitions of the declared constants, by their names, sorts and values
+fn print_result(model: Option<Vec<(String, String, String)>>) {
+    let Some(definitions) = model else {
+        println!("unsat");
+        return
+    };
+    println!("sat");
+    println!("(");
+    for (name, sort, value) in definitions {
+        println!("  (define-fun {} () {} {})", name, sort, value);
+    }
+    println!(")");
+}
+
+// Solves a script of the QF_IDL or QF_BV logic, as set by the script (by
+// default, QF_IDL)
+pub fn read_smtlib_solve_and_print<P>(path: P, verbosity: usize) -> Result<bool, String>
+where P: AsRef<path::Path> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let script = parse(&contents).ok_or("Error parsing SMT-LIB file.")?;
+    let statement = statement(&script).ok_or("Unsupported SMT-LIB command.")?;
+    let bools = |bools: Vec<(String, bool)>| {
+        bools.into_iter().map(|(name, value)| (name, "Bool".to_string(), value.to_string()))
+    };
+    let model = match statement.logic {
+        Some("QF_BV") => {
+            let problem = bv_problem(&statement, verbosity).ok_or("Unsupported QF_BV term.")?;
+            if 1 < verbosity {
+                log::info!("Bit-blasted to {} clauses over {} atoms", problem.cnf.len(), problem.cnf.atoms().count());
+            }
+            solve_bv(&problem, verbosity).map(|model| {
+                bools(model.bools)
+                    .chain(model.bvs.into_iter().map(|(name, value)| {
+                        (name, format!("(_ BitVec {})", value.0.len()), value.to_string())
+                    }))
+                    .collect()
+            })
+        },
+        None | Some("QF_IDL") => {
+            let problem = idl_problem(&statement, verbosity).ok_or("Unsupported QF_IDL term.")?;
+            if 1 < verbosity {
+                log::info!("Read {} clauses over {} atoms", problem.cnf.len(), problem.cnf.atoms().count());
+            }
+            solve_idl(&problem, verbosity).map(|model| {
+                bools(model.bools)
+                    .chain(model.ints.into_iter().map(|(name, value)| {
+                        let value = if value < 0 { format!("(- {})", -value) } else { value.to_string() };
+                        (name, "Int".to_string(), value)
+                    }))
+                    .collect()
+            })
+        },
+        Some(logic) => return Err(format!("Unsupported logic {}.", logic)),
+    };
+    let sat = model.is_some();
+    print_result(model);
+    Ok(sat)
+}
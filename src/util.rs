@@ -1,7 +1,33 @@
-// TODO: implement monadic crate?
-pub fn fold_option<A, B, F>(iter: impl Iterator<Item = A>, init: B, mut f: F) -> Option<B>
-where
-    F: FnMut(B, A) -> Option<B>
-{
-    iter.fold(Some(init), |o, a| o.map(|x| f(x, a)).flatten())
-}
\ No newline at end of file
+// A small seedable random number generator (SplitMix64), so that randomized
+// algorithms can be reproduced from their seed
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_bool(&mut self) -> bool {
+        self.next_u64() >> 63 == 1
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    // Uniform in [0, n)
+    // Assumption: 0 < n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
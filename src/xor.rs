@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::ast;
+use crate::dpll;
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// A row of a GF(2) matrix: a bitset over the columns, and the right-hand side
+#[derive(Clone, Debug)]
+struct Row {
+    bits: Vec<u64>,
+    parity: bool,
+}
+
+impl Row {
+    fn get(&self, column: usize) -> bool {
+        self.bits[column / 64] & (1 << (column % 64)) != 0
+    }
+
+    fn set(&mut self, column: usize) {
+        self.bits[column / 64] ^= 1 << (column % 64)
+    }
+
+    fn add(&mut self, other: &Row) {
+        for (word, other_word) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *word ^= other_word
+        }
+        self.parity ^= other.parity
+    }
+
+    fn columns(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bits.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64).filter(move |bit| word & (1 << bit) != 0).map(move |bit| 64 * i + bit)
+        })
+    }
+}
+
+// The xors sharing atoms (transitively), as a matrix over their atoms, kept in
+// reduced row echelon form: each row has a basic column which occurs in no
+// other row. Row operations preserve the solutions, so the matrix needs no
+// undoing on backtracking.
+#[derive(Clone, Debug)]
+struct Matrix {
+    columns: Vec<ast::Atom>,
+    rows: Vec<Row>,
+    // The basic column of each row, or None for a row without columns (which is
+    // a contradiction, as zero rows with even parity are dropped)
+    basic: Vec<Option<usize>>,
+    // Set when an atom of the matrix is assigned or unassigned, so that rows
+    // are only examined when they might imply something new
+    dirty: bool,
+}
+
+impl Matrix {
+    fn new(columns: Vec<ast::Atom>, mut rows: Vec<Row>) -> Self {
+        let mut basic = Vec::new();
+        let mut pivot = 0;
+        for column in 0..columns.len() {
+            let Some(found) = (pivot..rows.len()).find(|&r| rows[r].get(column)) else {
+                continue
+            };
+            rows.swap(pivot, found);
+            basic.push(Some(column));
+            Self::eliminate(&mut rows, pivot, column);
+            pivot += 1;
+        }
+        // The remaining rows are zero
+        for row in rows.split_off(pivot) {
+            if row.parity {
+                rows.push(row);
+                basic.push(None);
+            }
+        }
+        Self { columns, rows, basic, dirty: true }
+    }
+
+    // Removes the column from every row but the pivot row, which contains it
+    fn eliminate(rows: &mut [Row], pivot: usize, column: usize) {
+        let pivot_row = rows[pivot].clone();
+        for (r, row) in rows.iter_mut().enumerate() {
+            if r != pivot && row.get(column) {
+                row.add(&pivot_row);
+            }
+        }
+    }
+}
+
+// Propagates xor constraints by Gauss-Jordan elimination over GF(2), keeping
+// one matrix per connected component of the xors
+#[derive(Clone, Debug, Default)]
+pub struct Gauss {
+    matrices: Vec<Matrix>,
+    // The matrix of each atom, indexed by atom
+    matrix_of: Vec<Option<usize>>,
+}
+
+impl Gauss {
+    pub fn new(xors: &[ast::Xor]) -> Self {
+        let mut gauss = Self::default();
+        for xor in xors {
+            gauss.add(xor);
+        }
+        gauss
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matrices.is_empty()
+    }
+
+    fn matrix_of(&self, atom: ast::Atom) -> Option<usize> {
+        self.matrix_of.get(atom.index()).cloned().flatten()
+    }
+
+    // Adds an xor, merging the matrices of its atoms
+    pub fn add(&mut self, xor: &ast::Xor) {
+        let merged: Vec<usize> = xor.atoms()
+            .filter_map(|&atom| self.matrix_of(atom))
+            .sorted()
+            .dedup()
+            .collect();
+        let mut xors: Vec<ast::Xor> = Vec::new();
+        // Remove from the highest index so that lower indices stay valid
+        for &i in merged.iter().rev() {
+            let matrix = self.matrices.swap_remove(i);
+            xors.extend(matrix.rows.iter().map(|row| {
+                ast::Xor::new(row.columns().map(|column| matrix.columns[column]), row.parity)
+            }));
+        }
+        xors.push(xor.clone());
+        // Indices of moved matrices change, so recompute the atom map
+        let columns: Vec<ast::Atom> = xors.iter().flat_map(|xor| xor.atoms().cloned()).sorted().dedup().collect();
+        let column_of: HashMap<ast::Atom, usize> = columns.iter().enumerate().map(|(i, &atom)| (atom, i)).collect();
+        let words = columns.len().div_ceil(64);
+        let rows = xors.iter()
+            .map(|xor| {
+                let mut row = Row { bits: vec![0; words], parity: xor.parity() };
+                for atom in xor.atoms() {
+                    row.set(column_of[atom]);
+                }
+                row
+            })
+            .collect();
+        self.matrices.push(Matrix::new(columns, rows));
+        self.matrix_of.clear();
+        for (i, matrix) in self.matrices.iter().enumerate() {
+            for atom in matrix.columns.iter() {
+                if self.matrix_of.len() <= atom.index() {
+                    self.matrix_of.resize(atom.index() + 1, None);
+                }
+                self.matrix_of[atom.index()] = Some(i);
+            }
+        }
+    }
+
+    // Marks the matrix of the atom for elimination
+    pub fn touch(&mut self, atom: ast::Atom) {
+        if let Some(i) = self.matrix_of(atom) {
+            self.matrices[i].dirty = true;
+        }
+    }
+
+    pub fn touch_all(&mut self) {
+        for matrix in self.matrices.iter_mut() {
+            matrix.dirty = true;
+        }
+    }
+
+    // Makes the basic column of each row of each dirty matrix unassigned where
+    // possible, given the values of atoms by index. Then no combination of rows
+    // has fewer unassigned atoms than its rows, so rows left with no unassigned
+    // atoms are conflicts if their parity is wrong, and rows left with one
+    // unassigned atom imply it.
+    pub fn propagate(&mut self, values: &[Option<bool>]) -> dpll::Propagation {
+        let value = |atom: ast::Atom| values.get(atom.index()).cloned().flatten();
+        let mut implied = Vec::new();
+        for i in 0..self.matrices.len() {
+            let matrix = &mut self.matrices[i];
+            if !matrix.dirty {
+                continue
+            }
+            matrix.dirty = false;
+            for r in 0..matrix.rows.len() {
+                let Some(column) = matrix.basic[r] else {
+                    continue
+                };
+                if value(matrix.columns[column]).is_none() {
+                    continue
+                }
+                let replacement = matrix.rows[r].columns().find(|&c| value(matrix.columns[c]).is_none());
+                if let Some(replacement) = replacement {
+                    Matrix::eliminate(&mut matrix.rows, r, replacement);
+                    matrix.basic[r] = Some(replacement);
+                }
+            }
+            for row in matrix.rows.iter() {
+                let mut parity = row.parity;
+                let mut unassigned = None;
+                let mut count = 0;
+                let mut explanation = Vec::new();
+                for column in row.columns() {
+                    let atom = matrix.columns[column];
+                    match value(atom) {
+                        Some(phase) => {
+                            parity ^= phase;
+                            explanation.push(ast::Literal::new(!phase, atom));
+                        },
+                        None => {
+                            unassigned = Some(atom);
+                            count += 1;
+                        },
+                    }
+                }
+                match (count, unassigned) {
+                    (0, _) if parity => {
+                        // Implications found so far are dropped, so those
+                        // matrices must be examined again
+                        for matrix in self.matrices[..=i].iter_mut() {
+                            matrix.dirty = true;
+                        }
+                        return dpll::Propagation::Conflict(explanation)
+                    },
+                    (1, Some(atom)) => {
+                        let literal = ast::Literal::new(parity, atom);
+                        explanation.insert(0, literal);
+                        implied.push((literal, explanation));
+                    },
+                    _ => (),
+                }
+            }
+        }
+        dpll::Propagation::Implied(implied)
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// The largest xors recovered from their clausal encoding, which has 2^(n-1)
+// clauses for an xor of n atoms
+const MAX_RECOVERED_LEN: usize = 6;
+
+// Finds sets of clauses which together encode an xor, and replaces them by the
+// xor. A clause over the atoms rules out the one assignment falsifying all of
+// its literals, so the encoding of an xor with some parity consists of every
+// clause whose number of negative literals has the opposite parity. Returns the
+// number of xors recovered.
+pub fn recover(cnf: &mut ast::Cnf, verbosity: usize) -> usize {
+    // Clauses by their (sorted) atoms, as bitmasks of negative positions
+    let mut candidates: HashMap<Vec<ast::Atom>, Vec<u32>> = HashMap::new();
+    for clause in cnf.clauses() {
+        if clause.len() < 3 || MAX_RECOVERED_LEN < clause.len() {
+            continue
+        }
+        let literals: Vec<ast::Literal> = clause.literals().cloned().sorted_by_key(|lit| lit.atom()).collect();
+        let atoms: Vec<ast::Atom> = literals.iter().map(|lit| lit.atom()).collect();
+        if atoms.iter().tuple_windows().any(|(a, b)| a == b) {
+            continue
+        }
+        let negatives = literals.iter().enumerate()
+            .filter(|(_, lit)| !lit.phase())
+            .fold(0, |mask, (i, _)| mask | (1 << i));
+        candidates.entry(atoms).or_default().push(negatives);
+    }
+
+    let mut recovered: HashMap<Vec<ast::Atom>, bool> = HashMap::new();
+    for (atoms, mut patterns) in candidates {
+        patterns.sort();
+        patterns.dedup();
+        for odd_negatives in [false, true] {
+            let count = patterns.iter().filter(|mask| (mask.count_ones() % 2 == 1) == odd_negatives).count();
+            if count == 1 << (atoms.len() - 1) {
+                // Clauses with an even number of negatives rule out assignments
+                // with an even number of true atoms
+                recovered.insert(atoms.clone(), !odd_negatives);
+                break
+            }
+        }
+    }
+
+    cnf.retain_clauses(|clause| {
+        let literals: Vec<ast::Literal> = clause.literals().cloned().sorted_by_key(|lit| lit.atom()).collect();
+        let atoms: Vec<ast::Atom> = literals.iter().map(|lit| lit.atom()).collect();
+        match recovered.get(&atoms) {
+            Some(&parity) => {
+                let odd_negatives = literals.iter().filter(|lit| !lit.phase()).count() % 2 == 1;
+                odd_negatives == parity
+            },
+            None => true,
+        }
+    });
+    let count = recovered.len();
+    for (atoms, parity) in recovered {
+        let xor = ast::Xor::new(atoms, parity);
+        if 1 < verbosity {
+            log::info!("Recovered xor {}", xor);
+        }
+        cnf.add_xor(xor);
+    }
+    count
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+
+// Xors longer than this are split before being encoded into clauses
+const MAX_DIRECT_LEN: usize = 4;
+
+// Replaces the xors of the cnf by clauses, for algorithms which only handle
+// clauses. Long xors are split with fresh atoms standing for the parity of a
+// prefix, which are functionally determined and so preserve the models.
+pub fn expand(cnf: &mut ast::Cnf) {
+    for xor in cnf.take_xors() {
+        let mut atoms: Vec<ast::Atom> = xor.atoms().cloned().collect();
+        while MAX_DIRECT_LEN < atoms.len() {
+            let prefix = cnf.fresh_atom();
+            let rest = atoms.split_off(MAX_DIRECT_LEN - 1);
+            atoms.push(prefix);
+            encode(cnf, &atoms, false);
+            atoms = std::iter::once(prefix).chain(rest).collect();
+        }
+        encode(cnf, &atoms, xor.parity());
+    }
+}
+
+// Adds the clauses ruling out each assignment of the atoms with the wrong parity
+fn encode(cnf: &mut ast::Cnf, atoms: &[ast::Atom], parity: bool) {
+    for bits in 0..1_u32 << atoms.len() {
+        if (bits.count_ones() % 2 == 1) == parity {
+            continue
+        }
+        // The clause falsified exactly by the assignment given by the bits
+        let clause: Vec<ast::Literal> = atoms.iter().enumerate()
+            .map(|(i, &atom)| ast::Literal::new(bits & (1 << i) == 0, atom))
+            .collect();
+        cnf.add(ast::Clause::from(clause));
+    }
+}
@@ -0,0 +1,9 @@
+; Nontrivial factors of 143 of 8 bits, whose product does not overflow
+(set-logic QF_BV)
+(declare-fun a () (_ BitVec 8))
+(declare-fun b () (_ BitVec 8))
+(assert (= (bvmul ((_ zero_extend 8) a) ((_ zero_extend 8) b)) (_ bv143 16)))
+(assert (bvugt a #x01))
+(assert (bvult a b))
+(check-sat)
+(get-model)
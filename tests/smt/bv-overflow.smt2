@@ -0,0 +1,11 @@
+; A signed sum overflowing: both operands positive, yet the sum negative
+(set-logic QF_BV)
+(declare-fun a () (_ BitVec 8))
+(declare-fun b () (_ BitVec 8))
+(declare-fun c () (_ BitVec 16))
+(assert (bvsgt a #x00))
+(assert (bvsgt b #x00))
+(assert (bvslt (bvadd a b) #x00))
+(assert (= c (concat (bvsub a b) (bvashr (bvor a b) #x01))))
+(assert (= ((_ rotate_left 3) b) #b10100000))
+(check-sat)
@@ -0,0 +1,11 @@
+; Shifting left and back right only loses the high bits, so the low ones of
+; x cannot differ
+(set-logic QF_BV)
+(declare-const x (_ BitVec 12))
+(declare-const s (_ BitVec 12))
+(declare-const p Bool)
+(assert (bvult s #x004))
+(assert (let ((y (bvlshr (bvshl x s) s)))
+  (distinct ((_ extract 7 0) y) ((_ extract 7 0) x))))
+(assert (=> p (= ((_ sign_extend 4) ((_ extract 7 0) x)) (bvneg x))))
+(check-sat)
@@ -0,0 +1,13 @@
+; A chain of differences closing a negative cycle unless p is false
+(set-logic QF_IDL)
+(declare-fun x () Int)
+(declare-fun y () Int)
+(declare-fun z () Int)
+(declare-fun p () Bool)
+(assert (< x y))
+(assert (=> p (< y z)))
+(assert (or p (distinct y z)))
+(assert (=> (not p) (> (- y z) 0)))
+(assert (<= z x))
+(assert (or (= x (- 3)) (not p)))
+(check-sat)
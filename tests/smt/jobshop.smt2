@@ -0,0 +1,18 @@
+; Two jobs of two tasks on two machines, within a makespan of 8
+(set-logic QF_IDL)
+(declare-fun a1 () Int)
+(declare-fun a2 () Int)
+(declare-fun b1 () Int)
+(declare-fun b2 () Int)
+(declare-const end Int)
+; Tasks start at zero or later, and each follows the last of its job
+(assert (and (>= a1 0) (>= b1 0)))
+(assert (>= (- a2 a1) 3))
+(assert (>= (- b2 b1) 2))
+(assert (and (<= (+ a2 2) end) (<= (+ b2 4) end) (<= end 8)))
+; Tasks on the same machine do not overlap
+(assert (or (>= (- b2 a1) 3) (>= (- a1 b2) 4)))
+(assert (or (>= (- b1 a2) 2) (>= (- a2 b1) 2)))
+(check-sat)
+(get-model)
+(exit)
@@ -0,0 +1,8 @@
+(set-logic QF_IDL)
+(declare-fun x () Int)
+(declare-fun y () Int)
+(declare-fun z () Int)
+(assert (or (< x y) (< x z)))
+(assert (<= y (- x 1)))
+(assert (ite (> z x) (< z y) false))
+(check-sat)